@@ -3,6 +3,23 @@ pub use sea_orm_migration::prelude::*;
 mod m20220101_000001_create_user_table;
 mod m20240114_105650_create_login_history;
 mod m20240127_130539_create_user_info;
+mod m20240205_093012_add_session_refresh_token;
+mod m20240212_201544_add_multi_device_login_history;
+mod m20240220_143208_add_user_info_review;
+mod m20240226_110421_create_audit_log;
+mod m20240304_160233_create_image;
+mod m20240311_092750_add_user_info_identity;
+mod m20240318_101530_create_we_chat_access_token;
+mod m20240325_143012_add_phone_binding;
+mod m20240401_101245_add_unionid_identity;
+mod m20240408_094517_add_tenant;
+mod m20240415_153820_create_notification;
+mod m20240422_110904_create_media_check;
+mod m20240429_141126_create_travel_record;
+mod m20240506_093415_create_health_check_in;
+mod m20240513_102247_add_observation;
+mod m20240520_160508_add_inspector_role;
+mod m20240527_091932_create_subadmin_region;
 
 pub struct Migrator;
 
@@ -13,6 +30,23 @@ impl MigratorTrait for Migrator {
             Box::new(m20220101_000001_create_user_table::Migration),
             Box::new(m20240114_105650_create_login_history::Migration),
             Box::new(m20240127_130539_create_user_info::Migration),
+            Box::new(m20240205_093012_add_session_refresh_token::Migration),
+            Box::new(m20240212_201544_add_multi_device_login_history::Migration),
+            Box::new(m20240220_143208_add_user_info_review::Migration),
+            Box::new(m20240226_110421_create_audit_log::Migration),
+            Box::new(m20240304_160233_create_image::Migration),
+            Box::new(m20240311_092750_add_user_info_identity::Migration),
+            Box::new(m20240318_101530_create_we_chat_access_token::Migration),
+            Box::new(m20240325_143012_add_phone_binding::Migration),
+            Box::new(m20240401_101245_add_unionid_identity::Migration),
+            Box::new(m20240408_094517_add_tenant::Migration),
+            Box::new(m20240415_153820_create_notification::Migration),
+            Box::new(m20240422_110904_create_media_check::Migration),
+            Box::new(m20240429_141126_create_travel_record::Migration),
+            Box::new(m20240506_093415_create_health_check_in::Migration),
+            Box::new(m20240513_102247_add_observation::Migration),
+            Box::new(m20240520_160508_add_inspector_role::Migration),
+            Box::new(m20240527_091932_create_subadmin_region::Migration),
         ]
     }
 }
@@ -0,0 +1,122 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(WeChatSession::Table)
+                    .add_column(
+                        ColumnDef::new(WeChatSession::RefreshToken)
+                            .uuid()
+                            .unique_key()
+                            .not_null()
+                            .default(Expr::cust("uuid_generate_v4()")),
+                    )
+                    .add_column(
+                        ColumnDef::new(WeChatSession::RefreshFamily)
+                            .uuid()
+                            .not_null()
+                            .default(Expr::cust("uuid_generate_v4()")),
+                    )
+                    .add_column(
+                        ColumnDef::new(WeChatSession::RefreshExpires)
+                            .date_time()
+                            .not_null()
+                            .default(Expr::cust("now()")),
+                    )
+                    .add_column(
+                        ColumnDef::new(WeChatSession::Revoked)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_we_chat_session_refresh_family")
+                    .table(WeChatSession::Table)
+                    .col(WeChatSession::RefreshFamily)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(RetiredRefreshToken::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(RetiredRefreshToken::RefreshToken)
+                            .uuid()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(RetiredRefreshToken::RefreshFamily)
+                            .uuid()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(RetiredRefreshToken::RetiredAt)
+                            .date_time()
+                            .not_null()
+                            .default(Expr::cust("now()")),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(RetiredRefreshToken::Table).to_owned())
+            .await?;
+
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx_we_chat_session_refresh_family")
+                    .table(WeChatSession::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(WeChatSession::Table)
+                    .drop_column(WeChatSession::RefreshToken)
+                    .drop_column(WeChatSession::RefreshFamily)
+                    .drop_column(WeChatSession::RefreshExpires)
+                    .drop_column(WeChatSession::Revoked)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum WeChatSession {
+    Table,
+    RefreshToken,
+    RefreshFamily,
+    RefreshExpires,
+    Revoked,
+}
+
+#[derive(DeriveIden)]
+enum RetiredRefreshToken {
+    Table,
+    RefreshToken,
+    RefreshFamily,
+    RetiredAt,
+}
@@ -0,0 +1,176 @@
+use sea_orm_migration::{
+    prelude::*,
+    sea_orm::{EnumIter, Iterable},
+    sea_query::extension::postgres::Type,
+};
+
+use crate::m20220101_000001_create_user_table::AppUser;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+
+        db.execute_unprepared(
+            "ALTER TABLE we_chat_session DROP CONSTRAINT IF EXISTS we_chat_session_user_id_key",
+        )
+        .await?;
+        db.execute_unprepared(
+            "ALTER TABLE we_chat_session DROP CONSTRAINT IF EXISTS we_chat_session_last_session_key",
+        )
+        .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(WeChatSession::Table)
+                    .add_column(
+                        ColumnDef::new(WeChatSession::CreatedAt)
+                            .date_time()
+                            .not_null()
+                            .default(Expr::cust("now()")),
+                    )
+                    .add_column(ColumnDef::new(WeChatSession::ClientIp).string_len(45))
+                    .add_column(ColumnDef::new(WeChatSession::UserAgent).string_len(256))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_type(
+                Type::create()
+                    .as_enum(LoginOutcome::Table)
+                    .values(LoginOutcome::iter().skip(1))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(LoginHistory::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(LoginHistory::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(LoginHistory::UserId).uuid())
+                    .col(ColumnDef::new(LoginHistory::SessionId).integer())
+                    .col(
+                        ColumnDef::new(LoginHistory::LoginAt)
+                            .date_time()
+                            .not_null()
+                            .default(Expr::cust("now()")),
+                    )
+                    .col(ColumnDef::new(LoginHistory::ClientIp).string_len(45))
+                    .col(ColumnDef::new(LoginHistory::UserAgent).string_len(256))
+                    .col(
+                        ColumnDef::new(LoginHistory::Outcome)
+                            .enumeration(LoginOutcome::Table, LoginOutcome::iter().skip(1))
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(LoginHistory::Errcode).integer())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_foreign_key(
+                ForeignKey::create()
+                    .name("fk_login_record_user_id")
+                    .from(LoginHistory::Table, LoginHistory::UserId)
+                    .to(AppUser::Table, AppUser::Id)
+                    .to_owned(),
+            )
+            .await?;
+
+        // The history is an audit trail: refuse any attempt to rewrite or delete it.
+        db.execute_unprepared(
+            "CREATE OR REPLACE FUNCTION login_history_append_only() RETURNS trigger AS $$
+            BEGIN
+                RAISE EXCEPTION 'login_history is append-only';
+            END;
+            $$ LANGUAGE plpgsql",
+        )
+        .await?;
+        db.execute_unprepared(
+            "CREATE TRIGGER login_history_append_only
+            BEFORE UPDATE OR DELETE ON login_history
+            FOR EACH ROW EXECUTE FUNCTION login_history_append_only()",
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+
+        manager
+            .drop_table(Table::drop().table(LoginHistory::Table).to_owned())
+            .await?;
+
+        db.execute_unprepared("DROP FUNCTION IF EXISTS login_history_append_only()")
+            .await?;
+
+        manager
+            .drop_type(Type::drop().name(LoginOutcome::Table).to_owned())
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(WeChatSession::Table)
+                    .drop_column(WeChatSession::CreatedAt)
+                    .drop_column(WeChatSession::ClientIp)
+                    .drop_column(WeChatSession::UserAgent)
+                    .to_owned(),
+            )
+            .await?;
+
+        db.execute_unprepared(
+            "ALTER TABLE we_chat_session ADD CONSTRAINT we_chat_session_last_session_key UNIQUE (last_session)",
+        )
+        .await?;
+        db.execute_unprepared(
+            "ALTER TABLE we_chat_session ADD CONSTRAINT we_chat_session_user_id_key UNIQUE (user_id)",
+        )
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum WeChatSession {
+    Table,
+    CreatedAt,
+    ClientIp,
+    UserAgent,
+}
+
+#[derive(DeriveIden)]
+enum LoginHistory {
+    Table,
+    Id,
+    UserId,
+    SessionId,
+    LoginAt,
+    ClientIp,
+    UserAgent,
+    Outcome,
+    Errcode,
+}
+
+#[derive(DeriveIden, EnumIter)]
+pub enum LoginOutcome {
+    Table,
+    Success,
+    Failure,
+}
@@ -0,0 +1,78 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserInfo::Table)
+                    .add_column(
+                        ColumnDef::new(UserInfo::CreatedAt)
+                            .date_time()
+                            .not_null()
+                            .default(Expr::cust("now()")),
+                    )
+                    .add_column(ColumnDef::new(UserInfo::ReviewReason).string_len(256))
+                    .add_column(ColumnDef::new(UserInfo::Reviewer).uuid())
+                    .add_column(ColumnDef::new(UserInfo::ReviewedAt).date_time())
+                    .add_column(
+                        ColumnDef::new(UserInfo::Revision)
+                            .integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_user_info_validated")
+                    .table(UserInfo::Table)
+                    .col(UserInfo::Validated)
+                    .col(UserInfo::CreatedAt)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx_user_info_validated")
+                    .table(UserInfo::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserInfo::Table)
+                    .drop_column(UserInfo::CreatedAt)
+                    .drop_column(UserInfo::ReviewReason)
+                    .drop_column(UserInfo::Reviewer)
+                    .drop_column(UserInfo::ReviewedAt)
+                    .drop_column(UserInfo::Revision)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum UserInfo {
+    Table,
+    Validated,
+    CreatedAt,
+    ReviewReason,
+    Reviewer,
+    ReviewedAt,
+    Revision,
+}
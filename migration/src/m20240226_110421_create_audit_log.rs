@@ -0,0 +1,114 @@
+use sea_orm_migration::{
+    prelude::*,
+    sea_orm::{EnumIter, Iterable},
+    sea_query::extension::postgres::Type,
+};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_type(
+                Type::create()
+                    .as_enum(AuditAction::Table)
+                    .values(AuditAction::iter().skip(1))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(AuditLog::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(AuditLog::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(AuditLog::RecordId).uuid().not_null())
+                    .col(ColumnDef::new(AuditLog::Actor).uuid().not_null())
+                    .col(
+                        ColumnDef::new(AuditLog::Action)
+                            .enumeration(AuditAction::Table, AuditAction::iter().skip(1))
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(AuditLog::Diff).json_binary().not_null())
+                    .col(
+                        ColumnDef::new(AuditLog::CreatedAt)
+                            .date_time()
+                            .not_null()
+                            .default(Expr::cust("now()")),
+                    )
+                    .col(ColumnDef::new(AuditLog::RequestId).uuid().not_null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_audit_log_record_id")
+                    .table(AuditLog::Table)
+                    .col(AuditLog::RecordId)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_audit_log_actor")
+                    .table(AuditLog::Table)
+                    .col(AuditLog::Actor)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_audit_log_created_at")
+                    .table(AuditLog::Table)
+                    .col(AuditLog::CreatedAt)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(AuditLog::Table).to_owned())
+            .await?;
+
+        manager
+            .drop_type(Type::drop().name(AuditAction::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum AuditLog {
+    Table,
+    Id,
+    RecordId,
+    Actor,
+    Action,
+    Diff,
+    CreatedAt,
+    RequestId,
+}
+
+#[derive(DeriveIden, EnumIter)]
+pub enum AuditAction {
+    Table,
+    Insert,
+    Update,
+    Delete,
+    Review,
+}
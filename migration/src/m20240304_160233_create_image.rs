@@ -0,0 +1,71 @@
+use sea_orm_migration::prelude::*;
+
+use crate::m20220101_000001_create_user_table::AppUser;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Image::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Image::Id)
+                            .uuid()
+                            .primary_key()
+                            .unique_key()
+                            .default(Expr::cust("uuid_generate_v4()")),
+                    )
+                    .col(ColumnDef::new(Image::Owner).uuid().not_null())
+                    .col(ColumnDef::new(Image::ContentType).string_len(64).not_null())
+                    .col(ColumnDef::new(Image::Size).big_integer().not_null())
+                    .col(
+                        ColumnDef::new(Image::CreatedAt)
+                            .date_time()
+                            .not_null()
+                            .default(Expr::cust("now()")),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_foreign_key(
+                ForeignKey::create()
+                    .name("fk_image_owner")
+                    .from(Image::Table, Image::Owner)
+                    .to(AppUser::Table, AppUser::Id)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_foreign_key(
+                ForeignKey::drop()
+                    .table(Image::Table)
+                    .name("fk_image_owner")
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(Image::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Image {
+    Table,
+    Id,
+    Owner,
+    ContentType,
+    Size,
+    CreatedAt,
+}
@@ -0,0 +1,87 @@
+use sea_orm_migration::{
+    prelude::*,
+    sea_orm::{EnumIter, Iterable},
+    sea_query::extension::postgres::Type,
+};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_type(
+                Type::create()
+                    .as_enum(Gender::Table)
+                    .values(Gender::iter().skip(1))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserInfo::Table)
+                    .add_column(ColumnDef::new(UserInfo::BirthDate).date())
+                    .add_column(
+                        ColumnDef::new(UserInfo::Gender)
+                            .enumeration(Gender::Table, Gender::iter().skip(1)),
+                    )
+                    .add_column(ColumnDef::new(UserInfo::RegionCode).string_len(6))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_user_info_region_code")
+                    .table(UserInfo::Table)
+                    .col(UserInfo::RegionCode)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx_user_info_region_code")
+                    .table(UserInfo::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserInfo::Table)
+                    .drop_column(UserInfo::BirthDate)
+                    .drop_column(UserInfo::Gender)
+                    .drop_column(UserInfo::RegionCode)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_type(Type::drop().name(Gender::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum UserInfo {
+    Table,
+    BirthDate,
+    Gender,
+    RegionCode,
+}
+
+#[derive(DeriveIden, EnumIter)]
+pub enum Gender {
+    Table,
+    Male,
+    Female,
+}
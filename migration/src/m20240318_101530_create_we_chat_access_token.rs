@@ -0,0 +1,54 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(WeChatAccessToken::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(WeChatAccessToken::Appid)
+                            .string_len(64)
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(WeChatAccessToken::AccessToken)
+                            .string_len(512)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(WeChatAccessToken::ExpiresAt)
+                            .date_time()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(WeChatAccessToken::UpdatedAt)
+                            .date_time()
+                            .not_null()
+                            .default(Expr::cust("now()")),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(WeChatAccessToken::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum WeChatAccessToken {
+    Table,
+    Appid,
+    AccessToken,
+    ExpiresAt,
+    UpdatedAt,
+}
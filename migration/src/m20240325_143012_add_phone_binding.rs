@@ -0,0 +1,67 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(AppUser::Table)
+                    .add_column(ColumnDef::new(AppUser::Phone).string_len(32))
+                    .add_column(ColumnDef::new(AppUser::PhoneVerifiedAt).date_time())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserInfo::Table)
+                    .add_column(
+                        ColumnDef::new(UserInfo::PhoneVerified)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserInfo::Table)
+                    .drop_column(UserInfo::PhoneVerified)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(AppUser::Table)
+                    .drop_column(AppUser::Phone)
+                    .drop_column(AppUser::PhoneVerifiedAt)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum AppUser {
+    Table,
+    Phone,
+    PhoneVerifiedAt,
+}
+
+#[derive(DeriveIden)]
+enum UserInfo {
+    Table,
+    PhoneVerified,
+}
@@ -0,0 +1,245 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(AppUser::Table)
+                    .add_column(ColumnDef::new(AppUser::Unionid).string_len(64))
+                    .add_column(ColumnDef::new(AppUser::MergedInto).uuid())
+                    .to_owned(),
+            )
+            .await?;
+
+        // Sessions from before this have no appid and stay on the tenant's mini-program.
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(WeChatSession::Table)
+                    .add_column(ColumnDef::new(WeChatSession::Appid).string_len(64))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_app_user_unionid")
+                    .table(AppUser::Table)
+                    .col(AppUser::Unionid)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_foreign_key(
+                ForeignKey::create()
+                    .name("fk_app_user_merged_into")
+                    .from(AppUser::Table, AppUser::MergedInto)
+                    .to(AppUser::Table, AppUser::Id)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(WeChatIdentity::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(WeChatIdentity::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(WeChatIdentity::UserId).uuid().not_null())
+                    .col(
+                        ColumnDef::new(WeChatIdentity::Appid)
+                            .string_len(64)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(WeChatIdentity::Openid)
+                            .string_len(128)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(WeChatIdentity::CreatedAt)
+                            .date_time()
+                            .not_null()
+                            .default(Expr::cust("now()")),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_we_chat_identity_user_id")
+                            .from(WeChatIdentity::Table, WeChatIdentity::UserId)
+                            .to(AppUser::Table, AppUser::Id),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_we_chat_identity_appid_openid")
+                    .table(WeChatIdentity::Table)
+                    .col(WeChatIdentity::Appid)
+                    .col(WeChatIdentity::Openid)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(DuplicateAccount::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(DuplicateAccount::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(DuplicateAccount::Unionid)
+                            .string_len(64)
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(DuplicateAccount::UserId).uuid().not_null())
+                    .col(
+                        ColumnDef::new(DuplicateAccount::DuplicateId)
+                            .uuid()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(DuplicateAccount::CreatedAt)
+                            .date_time()
+                            .not_null()
+                            .default(Expr::cust("now()")),
+                    )
+                    .col(ColumnDef::new(DuplicateAccount::ResolvedAt).date_time())
+                    .col(ColumnDef::new(DuplicateAccount::ResolvedBy).uuid())
+                    .col(ColumnDef::new(DuplicateAccount::Kept).uuid())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_duplicate_account_user_id")
+                            .from(DuplicateAccount::Table, DuplicateAccount::UserId)
+                            .to(AppUser::Table, AppUser::Id),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_duplicate_account_duplicate_id")
+                            .from(DuplicateAccount::Table, DuplicateAccount::DuplicateId)
+                            .to(AppUser::Table, AppUser::Id),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_duplicate_account_pair")
+                    .table(DuplicateAccount::Table)
+                    .col(DuplicateAccount::UserId)
+                    .col(DuplicateAccount::DuplicateId)
+                    .unique()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(DuplicateAccount::Table).to_owned())
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(WeChatIdentity::Table).to_owned())
+            .await?;
+
+        manager
+            .drop_foreign_key(
+                ForeignKey::drop()
+                    .table(AppUser::Table)
+                    .name("fk_app_user_merged_into")
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx_app_user_unionid")
+                    .table(AppUser::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(AppUser::Table)
+                    .drop_column(AppUser::Unionid)
+                    .drop_column(AppUser::MergedInto)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(WeChatSession::Table)
+                    .drop_column(WeChatSession::Appid)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum AppUser {
+    Table,
+    Id,
+    Unionid,
+    MergedInto,
+}
+
+#[derive(DeriveIden)]
+enum WeChatSession {
+    Table,
+    Appid,
+}
+
+#[derive(DeriveIden)]
+enum WeChatIdentity {
+    Table,
+    Id,
+    UserId,
+    Appid,
+    Openid,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum DuplicateAccount {
+    Table,
+    Id,
+    Unionid,
+    UserId,
+    DuplicateId,
+    CreatedAt,
+    ResolvedAt,
+    ResolvedBy,
+    Kept,
+}
@@ -0,0 +1,163 @@
+use sea_orm_migration::prelude::*;
+
+/// Rows that predate tenants belong to the primary app's default tenant.
+const DEFAULT_TENANT: &str = "default";
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(AppUser::Table)
+                    .add_column(
+                        ColumnDef::new(AppUser::Tenant)
+                            .string_len(32)
+                            .not_null()
+                            .default(DEFAULT_TENANT),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserInfo::Table)
+                    .add_column(
+                        ColumnDef::new(UserInfo::Tenant)
+                            .string_len(32)
+                            .not_null()
+                            .default(DEFAULT_TENANT),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(WeChatSession::Table)
+                    .add_column(
+                        ColumnDef::new(WeChatSession::Tenant)
+                            .string_len(32)
+                            .not_null()
+                            .default(DEFAULT_TENANT),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // A unionid is shared across districts, but each district keeps its own account.
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx_app_user_unionid")
+                    .table(AppUser::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_app_user_tenant_unionid")
+                    .table(AppUser::Table)
+                    .col(AppUser::Tenant)
+                    .col(AppUser::Unionid)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_user_info_tenant")
+                    .table(UserInfo::Table)
+                    .col(UserInfo::Tenant)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx_user_info_tenant")
+                    .table(UserInfo::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx_app_user_tenant_unionid")
+                    .table(AppUser::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_app_user_unionid")
+                    .table(AppUser::Table)
+                    .col(AppUser::Unionid)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(WeChatSession::Table)
+                    .drop_column(WeChatSession::Tenant)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserInfo::Table)
+                    .drop_column(UserInfo::Tenant)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(AppUser::Table)
+                    .drop_column(AppUser::Tenant)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum AppUser {
+    Table,
+    Tenant,
+    Unionid,
+}
+
+#[derive(DeriveIden)]
+enum UserInfo {
+    Table,
+    Tenant,
+}
+
+#[derive(DeriveIden)]
+enum WeChatSession {
+    Table,
+    Tenant,
+}
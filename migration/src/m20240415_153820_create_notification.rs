@@ -0,0 +1,188 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(SubscribeAuthorization::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(SubscribeAuthorization::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(SubscribeAuthorization::UserId)
+                            .uuid()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(SubscribeAuthorization::TemplateId)
+                            .string_len(64)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(SubscribeAuthorization::Remaining)
+                            .integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .col(
+                        ColumnDef::new(SubscribeAuthorization::UpdatedAt)
+                            .date_time()
+                            .not_null()
+                            .default(Expr::cust("now()")),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_subscribe_authorization_user_id")
+                            .from(
+                                SubscribeAuthorization::Table,
+                                SubscribeAuthorization::UserId,
+                            )
+                            .to(AppUser::Table, AppUser::Id),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_subscribe_authorization_user_template")
+                    .table(SubscribeAuthorization::Table)
+                    .col(SubscribeAuthorization::UserId)
+                    .col(SubscribeAuthorization::TemplateId)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(NotificationOutbox::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(NotificationOutbox::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(NotificationOutbox::UserId).uuid().not_null())
+                    .col(
+                        ColumnDef::new(NotificationOutbox::Appid)
+                            .string_len(64)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(NotificationOutbox::Openid)
+                            .string_len(128)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(NotificationOutbox::TemplateId)
+                            .string_len(64)
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(NotificationOutbox::Page).string_len(256))
+                    .col(
+                        ColumnDef::new(NotificationOutbox::Data)
+                            .json_binary()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(NotificationOutbox::Attempts)
+                            .integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .col(
+                        ColumnDef::new(NotificationOutbox::NextAttemptAt)
+                            .date_time()
+                            .default(Expr::cust("now()")),
+                    )
+                    .col(ColumnDef::new(NotificationOutbox::SentAt).date_time())
+                    .col(ColumnDef::new(NotificationOutbox::LastError).string_len(256))
+                    .col(
+                        ColumnDef::new(NotificationOutbox::CreatedAt)
+                            .date_time()
+                            .not_null()
+                            .default(Expr::cust("now()")),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_notification_outbox_user_id")
+                            .from(NotificationOutbox::Table, NotificationOutbox::UserId)
+                            .to(AppUser::Table, AppUser::Id),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_notification_outbox_next_attempt_at")
+                    .table(NotificationOutbox::Table)
+                    .col(NotificationOutbox::NextAttemptAt)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(NotificationOutbox::Table).to_owned())
+            .await?;
+
+        manager
+            .drop_table(
+                Table::drop()
+                    .table(SubscribeAuthorization::Table)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum AppUser {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum SubscribeAuthorization {
+    Table,
+    Id,
+    UserId,
+    TemplateId,
+    Remaining,
+    UpdatedAt,
+}
+
+#[derive(DeriveIden)]
+enum NotificationOutbox {
+    Table,
+    Id,
+    UserId,
+    Appid,
+    Openid,
+    TemplateId,
+    Page,
+    Data,
+    Attempts,
+    NextAttemptAt,
+    SentAt,
+    LastError,
+    CreatedAt,
+}
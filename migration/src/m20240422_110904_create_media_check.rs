@@ -0,0 +1,82 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(MediaCheck::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(MediaCheck::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(MediaCheck::ImageId)
+                            .uuid()
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(
+                        ColumnDef::new(MediaCheck::Token)
+                            .uuid()
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(
+                        ColumnDef::new(MediaCheck::TraceId)
+                            .string_len(64)
+                            .unique_key(),
+                    )
+                    .col(ColumnDef::new(MediaCheck::Suggest).string_len(16))
+                    .col(ColumnDef::new(MediaCheck::Label).integer())
+                    .col(
+                        ColumnDef::new(MediaCheck::CreatedAt)
+                            .date_time()
+                            .not_null()
+                            .default(Expr::cust("now()")),
+                    )
+                    .col(ColumnDef::new(MediaCheck::CheckedAt).date_time())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_media_check_image_id")
+                            .from(MediaCheck::Table, MediaCheck::ImageId)
+                            .to(Image::Table, Image::Id),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(MediaCheck::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Image {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum MediaCheck {
+    Table,
+    Id,
+    ImageId,
+    Token,
+    TraceId,
+    Suggest,
+    Label,
+    CreatedAt,
+    CheckedAt,
+}
@@ -0,0 +1,140 @@
+use sea_orm_migration::{
+    prelude::*,
+    sea_orm::{EnumIter, Iterable},
+    sea_query::extension::postgres::Type,
+};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_type(
+                Type::create()
+                    .as_enum(TransportMode::Table)
+                    .values(TransportMode::iter().skip(1))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(TravelRecord::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(TravelRecord::Id)
+                            .uuid()
+                            .primary_key()
+                            .default(Expr::cust("uuid_generate_v4()")),
+                    )
+                    .col(ColumnDef::new(TravelRecord::UserInfoId).uuid().not_null())
+                    .col(ColumnDef::new(TravelRecord::Creator).uuid().not_null())
+                    .col(
+                        ColumnDef::new(TravelRecord::OriginRegion)
+                            .string_len(6)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(TravelRecord::DepartureDate)
+                            .date()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(TravelRecord::ArrivalDate).date().not_null())
+                    .col(
+                        ColumnDef::new(TravelRecord::Transport)
+                            .enumeration(TransportMode::Table, TransportMode::iter().skip(1))
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(TravelRecord::VehicleNo).string_len(32))
+                    .col(
+                        ColumnDef::new(TravelRecord::Tenant)
+                            .string_len(32)
+                            .not_null()
+                            .default("default"),
+                    )
+                    .col(
+                        ColumnDef::new(TravelRecord::CreatedAt)
+                            .date_time()
+                            .not_null()
+                            .default(Expr::cust("now()")),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_travel_record_user_info_id")
+                            .from(TravelRecord::Table, TravelRecord::UserInfoId)
+                            .to(UserInfo::Table, UserInfo::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_travel_record_creator")
+                            .from(TravelRecord::Table, TravelRecord::Creator)
+                            .to(AppUser::Table, AppUser::Id),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_travel_record_tenant_arrival_date")
+                    .table(TravelRecord::Table)
+                    .col(TravelRecord::Tenant)
+                    .col(TravelRecord::ArrivalDate)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(TravelRecord::Table).to_owned())
+            .await?;
+
+        manager
+            .drop_type(Type::drop().name(TransportMode::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum AppUser {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum UserInfo {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum TravelRecord {
+    Table,
+    Id,
+    UserInfoId,
+    Creator,
+    OriginRegion,
+    DepartureDate,
+    ArrivalDate,
+    Transport,
+    VehicleNo,
+    Tenant,
+    CreatedAt,
+}
+
+#[derive(DeriveIden, EnumIter)]
+pub enum TransportMode {
+    Table,
+    Bus,
+    Car,
+    Flight,
+    Ship,
+    Train,
+    Other,
+}
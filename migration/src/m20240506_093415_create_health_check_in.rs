@@ -0,0 +1,123 @@
+use sea_orm_migration::{
+    prelude::*,
+    sea_orm::{EnumIter, Iterable},
+    sea_query::extension::postgres::Type,
+};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_type(
+                Type::create()
+                    .as_enum(TestResult::Table)
+                    .values(TestResult::iter().skip(1))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(HealthCheckIn::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(HealthCheckIn::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(HealthCheckIn::UserInfoId).uuid().not_null())
+                    .col(ColumnDef::new(HealthCheckIn::CheckDate).date().not_null())
+                    .col(
+                        ColumnDef::new(HealthCheckIn::Temperature)
+                            .double()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(HealthCheckIn::Symptoms).string_len(256))
+                    .col(
+                        ColumnDef::new(HealthCheckIn::TestResult)
+                            .enumeration(TestResult::Table, TestResult::iter().skip(1))
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(HealthCheckIn::CreatedAt)
+                            .date_time()
+                            .not_null()
+                            .default(Expr::cust("now()")),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_health_check_in_user_info_id")
+                            .from(HealthCheckIn::Table, HealthCheckIn::UserInfoId)
+                            .to(UserInfo::Table, UserInfo::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // One check-in per person per day.
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_health_check_in_user_info_check_date")
+                    .table(HealthCheckIn::Table)
+                    .col(HealthCheckIn::UserInfoId)
+                    .col(HealthCheckIn::CheckDate)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_health_check_in_check_date")
+                    .table(HealthCheckIn::Table)
+                    .col(HealthCheckIn::CheckDate)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(HealthCheckIn::Table).to_owned())
+            .await?;
+
+        manager
+            .drop_type(Type::drop().name(TestResult::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum UserInfo {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum HealthCheckIn {
+    Table,
+    Id,
+    UserInfoId,
+    CheckDate,
+    Temperature,
+    Symptoms,
+    TestResult,
+    CreatedAt,
+}
+
+#[derive(DeriveIden, EnumIter)]
+pub enum TestResult {
+    Table,
+    NotTested,
+    Negative,
+    Positive,
+}
@@ -0,0 +1,225 @@
+use sea_orm_migration::{
+    prelude::*,
+    sea_orm::{EnumIter, Iterable},
+    sea_query::extension::postgres::Type,
+};
+
+/// The default `observation_days`; records approved before periods were stored get it.
+const BACKFILL_OBSERVATION_DAYS: i32 = 14;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_type(
+                Type::create()
+                    .as_enum(ObservationType::Table)
+                    .values(ObservationType::iter().skip(1))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_type(
+                Type::create()
+                    .as_enum(ObservationStatus::Table)
+                    .values(ObservationStatus::iter().skip(1))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserInfo::Table)
+                    .add_column(
+                        ColumnDef::new(UserInfo::ObservationType)
+                            .enumeration(ObservationType::Table, ObservationType::iter().skip(1)),
+                    )
+                    .add_column(ColumnDef::new(UserInfo::ObservationStart).date())
+                    .add_column(ColumnDef::new(UserInfo::ObservationEnd).date())
+                    .add_column(
+                        ColumnDef::new(UserInfo::ObservationStatus).enumeration(
+                            ObservationStatus::Table,
+                            ObservationStatus::iter().skip(1),
+                        ),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_user_info_observation_status_end")
+                    .table(UserInfo::Table)
+                    .col(UserInfo::ObservationStatus)
+                    .col(UserInfo::ObservationEnd)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(ObservationTransition::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ObservationTransition::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(ObservationTransition::UserInfoId)
+                            .uuid()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ObservationTransition::FromStatus).enumeration(
+                            ObservationStatus::Table,
+                            ObservationStatus::iter().skip(1),
+                        ),
+                    )
+                    .col(
+                        ColumnDef::new(ObservationTransition::ToStatus)
+                            .enumeration(
+                                ObservationStatus::Table,
+                                ObservationStatus::iter().skip(1),
+                            )
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ObservationTransition::Actor)
+                            .uuid()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ObservationTransition::CreatedAt)
+                            .date_time()
+                            .not_null()
+                            .default(Expr::cust("now()")),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_observation_transition_user_info_id")
+                            .from(
+                                ObservationTransition::Table,
+                                ObservationTransition::UserInfoId,
+                            )
+                            .to(UserInfo::Table, UserInfo::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_observation_transition_user_info_id")
+                    .table(ObservationTransition::Table)
+                    .col(ObservationTransition::UserInfoId)
+                    .to_owned(),
+            )
+            .await?;
+
+        // Approved records were observed at home from their review day on; keep them so,
+        // or mark them released when that period is already over.
+        let db = manager.get_connection();
+        db.execute_unprepared(&format!(
+            "UPDATE user_info SET \
+                observation_type = 'home', \
+                observation_start = reviewed_at::date, \
+                observation_end = reviewed_at::date + {days}, \
+                observation_status = (CASE WHEN reviewed_at::date + {days} >= CURRENT_DATE \
+                    THEN 'observing' ELSE 'released' END)::observation_status \
+            WHERE validated = 'pass' AND reviewed_at IS NOT NULL",
+            days = BACKFILL_OBSERVATION_DAYS - 1,
+        ))
+        .await?;
+        db.execute_unprepared(
+            "INSERT INTO observation_transition (user_info_id, from_status, to_status, actor) \
+            SELECT id, NULL, observation_status, '00000000-0000-0000-0000-000000000000' \
+            FROM user_info WHERE observation_status IS NOT NULL",
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ObservationTransition::Table).to_owned())
+            .await?;
+
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx_user_info_observation_status_end")
+                    .table(UserInfo::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserInfo::Table)
+                    .drop_column(UserInfo::ObservationType)
+                    .drop_column(UserInfo::ObservationStart)
+                    .drop_column(UserInfo::ObservationEnd)
+                    .drop_column(UserInfo::ObservationStatus)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_type(Type::drop().name(ObservationStatus::Table).to_owned())
+            .await?;
+
+        manager
+            .drop_type(Type::drop().name(ObservationType::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum UserInfo {
+    Table,
+    Id,
+    ObservationType,
+    ObservationStart,
+    ObservationEnd,
+    ObservationStatus,
+}
+
+#[derive(DeriveIden)]
+enum ObservationTransition {
+    Table,
+    Id,
+    UserInfoId,
+    FromStatus,
+    ToStatus,
+    Actor,
+    CreatedAt,
+}
+
+#[derive(DeriveIden, EnumIter)]
+pub enum ObservationType {
+    Table,
+    Home,
+    Centralized,
+}
+
+#[derive(DeriveIden, EnumIter)]
+pub enum ObservationStatus {
+    Table,
+    Observing,
+    Released,
+    Cancelled,
+}
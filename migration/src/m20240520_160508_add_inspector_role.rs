@@ -0,0 +1,37 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // The value survives a rollback, see below, so applying this again must not fail.
+        let db = manager.get_connection();
+        db.execute_unprepared("ALTER TYPE user_role ADD VALUE IF NOT EXISTS 'inspector'")
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Postgres cannot drop an enum value; demote inspectors so the value goes unused.
+        manager
+            .exec_stmt(
+                Query::update()
+                    .table(AppUser::Table)
+                    .value(AppUser::UserRole, Expr::cust("'normal'::user_role"))
+                    .and_where(
+                        Expr::col(AppUser::UserRole).eq(Expr::cust("'inspector'::user_role")),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum AppUser {
+    Table,
+    UserRole,
+}
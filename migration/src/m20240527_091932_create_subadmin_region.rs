@@ -0,0 +1,133 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserInfo::Table)
+                    .add_column(ColumnDef::new(UserInfo::AddressRegion).string_len(12))
+                    .to_owned(),
+            )
+            .await?;
+
+        // Existing records only know the region of the ID card; it stands in for the
+        // address until the reporter corrects it.
+        manager
+            .exec_stmt(
+                Query::update()
+                    .table(UserInfo::Table)
+                    .value(UserInfo::AddressRegion, Expr::col(UserInfo::RegionCode))
+                    .and_where(Expr::col(UserInfo::AddressRegion).is_null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_user_info_address_region")
+                    .table(UserInfo::Table)
+                    .col(UserInfo::AddressRegion)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(SubadminRegion::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(SubadminRegion::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(SubadminRegion::UserId).uuid().not_null())
+                    .col(
+                        ColumnDef::new(SubadminRegion::RegionCode)
+                            .string_len(12)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(SubadminRegion::CreatedAt)
+                            .date_time()
+                            .not_null()
+                            .default(Expr::cust("now()")),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_subadmin_region_user_id")
+                            .from(SubadminRegion::Table, SubadminRegion::UserId)
+                            .to(AppUser::Table, AppUser::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_subadmin_region_user_region")
+                    .table(SubadminRegion::Table)
+                    .col(SubadminRegion::UserId)
+                    .col(SubadminRegion::RegionCode)
+                    .unique()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(SubadminRegion::Table).to_owned())
+            .await?;
+
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx_user_info_address_region")
+                    .table(UserInfo::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserInfo::Table)
+                    .drop_column(UserInfo::AddressRegion)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum AppUser {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum UserInfo {
+    Table,
+    AddressRegion,
+    RegionCode,
+}
+
+#[derive(DeriveIden)]
+enum SubadminRegion {
+    Table,
+    Id,
+    UserId,
+    RegionCode,
+    CreatedAt,
+}
@@ -0,0 +1,596 @@
+use rocket::{get, http::Status, post, serde::json::Json, State};
+use sea_orm::{
+    sea_query::{Expr, OnConflict},
+    ActiveModelTrait, ColumnTrait, Condition, ConnectionTrait, DatabaseConnection, DbErr,
+    EntityTrait, IntoActiveModel, PaginatorTrait, QueryFilter, QueryOrder, QuerySelect, Set,
+    TransactionTrait,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::error::ApiError;
+use crate::orm::entities::{
+    app_user, duplicate_account, image, notification_outbox, prelude::*,
+    sea_orm_active_enums::AuditAction, sea_orm_active_enums::UserRole, subscribe_authorization,
+    travel_record, user_info, we_chat_identity, we_chat_session,
+};
+use crate::wechat::Code2Session;
+
+use super::{
+    audit::record_audit, phone::refresh_phone_verified, role_name, tenant_users, AdminUser,
+    RequestId,
+};
+
+/// Longest `merged_into` chain followed before giving up; merges only ever add one hop.
+const MAX_MERGE_DEPTH: usize = 8;
+
+/// Follows `merged_into` so logins to a merged account land on the surviving one.
+async fn surviving_user<C: ConnectionTrait>(
+    db: &C,
+    mut user: app_user::Model,
+) -> Result<app_user::Model, DbErr> {
+    for _ in 0..MAX_MERGE_DEPTH {
+        let Some(target) = user.merged_into else {
+            return Ok(user);
+        };
+        user = AppUser::find_by_id(target)
+            .one(db)
+            .await?
+            .ok_or_else(|| DbErr::RecordNotFound("app user".to_owned()))?;
+    }
+
+    Err(DbErr::Custom(format!(
+        "merged_into chain of user {} is cyclic or longer than {}",
+        user.id, MAX_MERGE_DEPTH
+    )))
+}
+
+/// Stores `unionid` on `user`, or records a duplicate when another account already holds it.
+async fn link_unionid<C: ConnectionTrait>(
+    db: &C,
+    user: app_user::Model,
+    unionid: Option<&str>,
+) -> Result<app_user::Model, DbErr> {
+    let unionid = match unionid {
+        Some(unionid) if user.unionid.as_deref() != Some(unionid) => unionid,
+        _ => return Ok(user),
+    };
+
+    if let Some(current) = &user.unionid {
+        eprintln!(
+            "User {} has unionid {} but WeChat reported {}",
+            user.id, current, unionid
+        );
+        return Ok(user);
+    }
+
+    let holder = AppUser::find()
+        .filter(app_user::Column::Tenant.eq(user.tenant.clone()))
+        .filter(app_user::Column::Unionid.eq(unionid))
+        .one(db)
+        .await?;
+
+    match holder {
+        Some(holder) => {
+            let duplicate = duplicate_account::ActiveModel {
+                unionid: Set(unionid.to_owned()),
+                user_id: Set(holder.id),
+                duplicate_id: Set(user.id),
+                ..Default::default()
+            };
+            DuplicateAccount::insert(duplicate)
+                .on_conflict(
+                    OnConflict::columns([
+                        duplicate_account::Column::UserId,
+                        duplicate_account::Column::DuplicateId,
+                    ])
+                    .do_nothing()
+                    .to_owned(),
+                )
+                .exec_without_returning(db)
+                .await?;
+
+            Ok(user)
+        }
+        None => {
+            let mut user = user.into_active_model();
+            user.unionid = Set(Some(unionid.to_owned()));
+            user.update(db).await
+        }
+    }
+}
+
+/// Finds or creates the person behind `session`, a login to `appid` of `tenant`.
+///
+/// An openid only identifies someone within one app; the unionid is shared by every app
+/// under the same open platform account and is used to match logins across the apps of
+/// one tenant. The same person in another tenant gets a separate account.
+pub async fn resolve_user(
+    db: &DatabaseConnection,
+    tenant: &str,
+    appid: &str,
+    session: &Code2Session,
+) -> Result<app_user::Model, DbErr> {
+    let txn = db.begin().await?;
+
+    let identity = WeChatIdentity::find()
+        .filter(we_chat_identity::Column::Appid.eq(appid))
+        .filter(we_chat_identity::Column::Openid.eq(session.openid.clone()))
+        .one(&txn)
+        .await?;
+
+    let user = match &identity {
+        Some(identity) => AppUser::find_by_id(identity.user_id).one(&txn).await?,
+        None => {
+            // Accounts created before identities existed are keyed by `wechat_id` alone.
+            let mut user = AppUser::find()
+                .filter(app_user::Column::Tenant.eq(tenant))
+                .filter(app_user::Column::WechatId.eq(session.openid.clone()))
+                .one(&txn)
+                .await?;
+            if let (None, Some(unionid)) = (&user, &session.unionid) {
+                user = AppUser::find()
+                    .filter(app_user::Column::Tenant.eq(tenant))
+                    .filter(app_user::Column::Unionid.eq(unionid.clone()))
+                    .one(&txn)
+                    .await?;
+            }
+            user
+        }
+    };
+
+    let user = match user {
+        Some(user) => surviving_user(&txn, user).await?,
+        None => {
+            let user = app_user::ActiveModel {
+                wechat_id: Set(session.openid.clone()),
+                user_role: Set(UserRole::Normal),
+                unionid: Set(session.unionid.clone()),
+                tenant: Set(tenant.to_owned()),
+                ..Default::default()
+            };
+            user.insert(&txn).await?
+        }
+    };
+
+    if identity.is_none() {
+        let identity = we_chat_identity::ActiveModel {
+            user_id: Set(user.id),
+            appid: Set(appid.to_owned()),
+            openid: Set(session.openid.clone()),
+            ..Default::default()
+        };
+        identity.insert(&txn).await?;
+    }
+
+    let user = link_unionid(&txn, user, session.unionid.as_deref()).await?;
+
+    txn.commit().await?;
+
+    Ok(user)
+}
+
+#[derive(Serialize)]
+pub struct AccountSummary {
+    pub user_id: Uuid,
+    pub role: String,
+    pub phone: Option<String>,
+    pub appids: Vec<String>,
+    pub user_info_count: u64,
+}
+
+#[derive(Serialize)]
+pub struct DuplicateAccountResponse {
+    pub id: i32,
+    pub unionid: String,
+    pub created_at: chrono::NaiveDateTime,
+    pub accounts: Vec<AccountSummary>,
+}
+
+async fn account_summary(
+    db: &DatabaseConnection,
+    user_id: Uuid,
+) -> Result<Option<AccountSummary>, DbErr> {
+    let user = match AppUser::find_by_id(user_id).one(db).await? {
+        Some(user) => user,
+        None => return Ok(None),
+    };
+
+    let appids = WeChatIdentity::find()
+        .filter(we_chat_identity::Column::UserId.eq(user_id))
+        .order_by_asc(we_chat_identity::Column::CreatedAt)
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|x| x.appid)
+        .collect();
+
+    let user_info_count = UserInfo::find()
+        .filter(user_info::Column::Creator.eq(user_id))
+        .count(db)
+        .await?;
+
+    Ok(Some(AccountSummary {
+        user_id,
+        role: role_name(&user.user_role),
+        phone: user.phone,
+        appids,
+        user_info_count,
+    }))
+}
+
+#[get("/admin/duplicate-account/query?<start>&<count>")]
+pub async fn query_duplicate_accounts(
+    db: &State<DatabaseConnection>,
+    admin: AdminUser,
+    start: u64,
+    count: u64,
+) -> Result<Json<Vec<DuplicateAccountResponse>>, ApiError> {
+    let db = db as &DatabaseConnection;
+
+    // Both accounts of a duplicate always share a tenant.
+    let duplicates = DuplicateAccount::find()
+        .filter(duplicate_account::Column::UserId.in_subquery(tenant_users(&admin.0.user.tenant)))
+        .filter(duplicate_account::Column::ResolvedAt.is_null())
+        .order_by_asc(duplicate_account::Column::CreatedAt)
+        .offset(start)
+        .limit(count)
+        .all(db)
+        .await?;
+
+    let mut response = Vec::with_capacity(duplicates.len());
+    for duplicate in duplicates {
+        let mut accounts = Vec::new();
+        for user_id in [duplicate.user_id, duplicate.duplicate_id] {
+            accounts.extend(account_summary(db, user_id).await?);
+        }
+        response.push(DuplicateAccountResponse {
+            id: duplicate.id,
+            unionid: duplicate.unionid,
+            created_at: duplicate.created_at,
+            accounts,
+        });
+    }
+
+    Ok(Json(response))
+}
+
+#[derive(Deserialize)]
+pub struct MergingAccount {
+    pub id: i32,
+    /// The account that survives; must be one of the pair.
+    pub keep: Uuid,
+}
+
+fn account_merged() -> ApiError {
+    ApiError::new(
+        Status::Conflict,
+        "account_merged",
+        "one of the accounts has already been merged into another",
+    )
+}
+
+#[post("/admin/duplicate-account/merge", format = "json", data = "<merge>")]
+pub async fn merge_duplicate_account(
+    db: &State<DatabaseConnection>,
+    admin: AdminUser,
+    request_id: RequestId,
+    merge: Json<MergingAccount>,
+) -> Result<Status, ApiError> {
+    let db = db as &DatabaseConnection;
+
+    merge_accounts(db, &admin.0.user, merge.id, merge.keep, request_id).await?;
+
+    Ok(Status::Ok)
+}
+
+/// Merges the other account of duplicate `id` into `keep`.
+async fn merge_accounts(
+    db: &DatabaseConnection,
+    admin: &app_user::Model,
+    id: i32,
+    keep: Uuid,
+    request_id: RequestId,
+) -> Result<(), ApiError> {
+    let txn = db.begin().await?;
+
+    let duplicate = DuplicateAccount::find_by_id(id)
+        .filter(duplicate_account::Column::UserId.in_subquery(tenant_users(&admin.tenant)))
+        .filter(duplicate_account::Column::ResolvedAt.is_null())
+        .lock_exclusive()
+        .one(&txn)
+        .await?
+        .ok_or_else(|| ApiError::not_found("duplicate account"))?;
+
+    let merged = if keep == duplicate.user_id {
+        duplicate.duplicate_id
+    } else if keep == duplicate.duplicate_id {
+        duplicate.user_id
+    } else {
+        return Err(ApiError::validation(
+            "keep",
+            "keep must be one of the duplicate accounts",
+        ));
+    };
+
+    // Locked so a concurrent merge cannot chain either account; an account merged by an
+    // earlier, overlapping duplicate must not receive or hand over anything.
+    let users = AppUser::find()
+        .filter(app_user::Column::Id.is_in([keep, merged]))
+        .order_by_asc(app_user::Column::Id)
+        .lock_exclusive()
+        .all(&txn)
+        .await?;
+    if users.iter().any(|x| x.merged_into.is_some()) {
+        return Err(account_merged());
+    }
+    let (kept_user, merged_user) = match (
+        users.iter().find(|x| x.id == keep),
+        users.iter().find(|x| x.id == merged),
+    ) {
+        (Some(kept_user), Some(merged_user)) => (kept_user.clone(), merged_user.clone()),
+        _ => return Err(ApiError::not_found("user")),
+    };
+
+    WeChatIdentity::update_many()
+        .col_expr(we_chat_identity::Column::UserId, Expr::value(keep))
+        .filter(we_chat_identity::Column::UserId.eq(merged))
+        .exec(&txn)
+        .await?;
+
+    let moved = UserInfo::find()
+        .filter(user_info::Column::Creator.eq(merged))
+        .all(&txn)
+        .await?;
+    for record in &moved {
+        let diff = json!({ "creator": { "before": merged, "after": keep } });
+        record_audit(
+            &txn,
+            record.id,
+            admin.id,
+            AuditAction::Update,
+            diff,
+            request_id,
+        )
+        .await?;
+    }
+    UserInfo::update_many()
+        .col_expr(user_info::Column::Creator, Expr::value(keep))
+        .col_expr(
+            user_info::Column::Revision,
+            Expr::col(user_info::Column::Revision).add(1),
+        )
+        .filter(user_info::Column::Creator.eq(merged))
+        .exec(&txn)
+        .await?;
+
+    TravelRecord::update_many()
+        .col_expr(travel_record::Column::Creator, Expr::value(keep))
+        .filter(travel_record::Column::Creator.eq(merged))
+        .exec(&txn)
+        .await?;
+
+    Image::update_many()
+        .col_expr(image::Column::Owner, Expr::value(keep))
+        .filter(image::Column::Owner.eq(merged))
+        .exec(&txn)
+        .await?;
+
+    // Authorizations are unique per template, so counts of both accounts are added up.
+    let authorizations = SubscribeAuthorization::find()
+        .filter(subscribe_authorization::Column::UserId.eq(merged))
+        .all(&txn)
+        .await?;
+    for authorization in authorizations {
+        let moved = subscribe_authorization::ActiveModel {
+            user_id: Set(keep),
+            template_id: Set(authorization.template_id),
+            remaining: Set(authorization.remaining),
+            updated_at: Set(authorization.updated_at),
+            ..Default::default()
+        };
+        SubscribeAuthorization::insert(moved)
+            .on_conflict(
+                OnConflict::columns([
+                    subscribe_authorization::Column::UserId,
+                    subscribe_authorization::Column::TemplateId,
+                ])
+                .value(
+                    subscribe_authorization::Column::Remaining,
+                    Expr::col((
+                        SubscribeAuthorization,
+                        subscribe_authorization::Column::Remaining,
+                    ))
+                    .add(authorization.remaining),
+                )
+                .to_owned(),
+            )
+            .exec_without_returning(&txn)
+            .await?;
+    }
+    SubscribeAuthorization::delete_many()
+        .filter(subscribe_authorization::Column::UserId.eq(merged))
+        .exec(&txn)
+        .await?;
+
+    NotificationOutbox::update_many()
+        .col_expr(notification_outbox::Column::UserId, Expr::value(keep))
+        .filter(notification_outbox::Column::UserId.eq(merged))
+        .exec(&txn)
+        .await?;
+
+    // The merged account's devices must log in again and will then land on `keep`.
+    WeChatSession::update_many()
+        .col_expr(we_chat_session::Column::Revoked, Expr::value(true))
+        .filter(we_chat_session::Column::UserId.eq(merged))
+        .exec(&txn)
+        .await?;
+
+    // The row stays because login history references it and is append-only.
+    let phone = merged_user.phone.clone();
+    let phone_verified_at = merged_user.phone_verified_at;
+    let mut merged_user = merged_user.into_active_model();
+    merged_user.unionid = Set(None);
+    merged_user.merged_into = Set(Some(keep));
+    merged_user.update(&txn).await?;
+
+    let keeps_phone = kept_user.phone.is_some();
+    let mut kept_user = kept_user.into_active_model();
+    kept_user.unionid = Set(Some(duplicate.unionid.clone()));
+    if !keeps_phone {
+        kept_user.phone = Set(phone);
+        kept_user.phone_verified_at = Set(phone_verified_at);
+    }
+    let kept_user = kept_user.update(&txn).await?;
+    // Records moved over were flagged against the merged account's number.
+    refresh_phone_verified(&txn, keep, kept_user.phone.as_deref()).await?;
+
+    // The resolved duplicate is the merge's log entry: who merged which account into which.
+    let mut duplicate = duplicate.into_active_model();
+    duplicate.resolved_at = Set(Some(chrono::Local::now().naive_local()));
+    duplicate.resolved_by = Set(Some(admin.id));
+    duplicate.kept = Set(Some(keep));
+    duplicate.update(&txn).await?;
+
+    // Other open duplicates of the merged account now concern `keep`. Those that would
+    // pair `keep` with itself or repeat an existing pair are resolved instead.
+    let siblings = DuplicateAccount::find()
+        .filter(
+            Condition::any()
+                .add(duplicate_account::Column::UserId.eq(merged))
+                .add(duplicate_account::Column::DuplicateId.eq(merged)),
+        )
+        .filter(duplicate_account::Column::ResolvedAt.is_null())
+        .lock_exclusive()
+        .all(&txn)
+        .await?;
+    for sibling in siblings {
+        let other = if sibling.user_id == merged {
+            sibling.duplicate_id
+        } else {
+            sibling.user_id
+        };
+        let repeated = other == keep
+            || DuplicateAccount::find()
+                .filter(
+                    Condition::any()
+                        .add(
+                            Condition::all()
+                                .add(duplicate_account::Column::UserId.eq(keep))
+                                .add(duplicate_account::Column::DuplicateId.eq(other)),
+                        )
+                        .add(
+                            Condition::all()
+                                .add(duplicate_account::Column::UserId.eq(other))
+                                .add(duplicate_account::Column::DuplicateId.eq(keep)),
+                        ),
+                )
+                .count(&txn)
+                .await?
+                > 0;
+
+        let mut repointed = sibling.clone().into_active_model();
+        if repeated {
+            repointed.resolved_at = Set(Some(chrono::Local::now().naive_local()));
+            repointed.resolved_by = Set(Some(admin.id));
+            repointed.kept = Set((other == keep).then_some(keep));
+        } else if sibling.user_id == merged {
+            repointed.user_id = Set(keep);
+        } else {
+            repointed.duplicate_id = Set(keep);
+        }
+        repointed.update(&txn).await?;
+    }
+
+    txn.commit().await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::http::Status;
+    use sea_orm::{DatabaseBackend, MockDatabase};
+    use uuid::Uuid;
+
+    use super::{merge_accounts, surviving_user, MAX_MERGE_DEPTH};
+    use crate::api::RequestId;
+    use crate::orm::entities::{app_user, duplicate_account, sea_orm_active_enums::UserRole};
+
+    fn user(id: Uuid, merged_into: Option<Uuid>) -> app_user::Model {
+        app_user::Model {
+            id,
+            wechat_id: id.to_string(),
+            user_role: UserRole::Normal,
+            phone: None,
+            phone_verified_at: None,
+            unionid: None,
+            merged_into,
+            tenant: "default".to_owned(),
+        }
+    }
+
+    #[rocket::async_test]
+    async fn follows_merges_to_the_surviving_user() {
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([vec![user(b, None)]])
+            .into_connection();
+
+        let survivor = surviving_user(&db, user(a, Some(b))).await.unwrap();
+        assert_eq!(survivor.id, b);
+    }
+
+    #[rocket::async_test]
+    async fn stops_on_cyclic_merges() {
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+        let hops = (0..MAX_MERGE_DEPTH).map(|i| {
+            if i % 2 == 0 {
+                vec![user(b, Some(a))]
+            } else {
+                vec![user(a, Some(b))]
+            }
+        });
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results(hops)
+            .into_connection();
+
+        assert!(surviving_user(&db, user(a, Some(b))).await.is_err());
+    }
+
+    #[rocket::async_test]
+    async fn refuses_stale_duplicates_of_merged_accounts() {
+        // `b` was merged into `c` through another duplicate since this one was reported.
+        let (admin, a, b, c) = (
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+        );
+        let duplicate = duplicate_account::Model {
+            id: 1,
+            unionid: "unionid".to_owned(),
+            user_id: a,
+            duplicate_id: b,
+            created_at: chrono::Local::now().naive_local(),
+            resolved_at: None,
+            resolved_by: None,
+            kept: None,
+        };
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([vec![duplicate]])
+            .append_query_results([vec![user(a, None), user(b, Some(c))]])
+            .into_connection();
+
+        let err = merge_accounts(&db, &user(admin, None), 1, a, RequestId(Uuid::new_v4()))
+            .await
+            .unwrap_err();
+        assert_eq!(err.status, Status::Conflict);
+        assert_eq!(err.code, "account_merged");
+
+        let log = format!("{:?}", db.into_transaction_log()).replace('\\', "");
+        assert!(log.contains("FOR UPDATE"));
+        assert!(!log.contains(r#"UPDATE "we_chat_identity""#));
+    }
+}
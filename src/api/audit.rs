@@ -0,0 +1,299 @@
+use rocket::{get, serde::json::Json, FromForm, State};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, Condition, ConnectionTrait, DatabaseConnection, DbErr,
+    EntityTrait, ModelTrait, QueryFilter, QueryOrder, QuerySelect, Set, TransactionTrait,
+};
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use uuid::Uuid;
+
+use crate::error::ApiError;
+use crate::orm::entities::{
+    audit_log, prelude::*, sea_orm_active_enums::AuditAction, user_info as user_info_db,
+};
+
+use super::{
+    tenant_records, tenant_users,
+    user_info::{observation_status_name, validated_name, ObservationKind},
+    AdminUser, DateTimeParam, RequestId,
+};
+
+fn user_info_fields(x: &user_info_db::Model) -> Map<String, Value> {
+    let mut fields = Map::new();
+    fields.insert("creator".to_owned(), json!(x.creator));
+    fields.insert("id_no".to_owned(), json!(x.id_no));
+    fields.insert("name".to_owned(), json!(x.name));
+    fields.insert("phone".to_owned(), json!(x.phone));
+    fields.insert("phone_verified".to_owned(), json!(x.phone_verified));
+    fields.insert("address".to_owned(), json!(x.address));
+    fields.insert("address_region".to_owned(), json!(x.address_region));
+    fields.insert("image".to_owned(), json!(x.image));
+    fields.insert("validated".to_owned(), json!(validated_name(&x.validated)));
+    fields.insert("review_reason".to_owned(), json!(x.review_reason));
+    fields.insert(
+        "observation_type".to_owned(),
+        json!(x.observation_type.clone().map(ObservationKind::from)),
+    );
+    fields.insert("observation_start".to_owned(), json!(x.observation_start));
+    fields.insert("observation_end".to_owned(), json!(x.observation_end));
+    fields.insert(
+        "observation_status".to_owned(),
+        json!(x.observation_status.as_ref().map(observation_status_name)),
+    );
+    fields
+}
+
+/// Builds `{field: {"before": .., "after": ..}}` for every field that differs.
+pub fn user_info_diff(
+    before: Option<&user_info_db::Model>,
+    after: Option<&user_info_db::Model>,
+) -> Value {
+    let before = before.map(user_info_fields).unwrap_or_default();
+    let after = after.map(user_info_fields).unwrap_or_default();
+
+    let mut diff = Map::new();
+    for key in before.keys().chain(after.keys()) {
+        let old = before.get(key).cloned().unwrap_or(Value::Null);
+        let new = after.get(key).cloned().unwrap_or(Value::Null);
+        if old != new && !diff.contains_key(key) {
+            diff.insert(key.clone(), json!({ "before": old, "after": new }));
+        }
+    }
+
+    Value::Object(diff)
+}
+
+pub async fn record_audit<C: ConnectionTrait>(
+    db: &C,
+    record_id: Uuid,
+    actor: Uuid,
+    action: AuditAction,
+    diff: Value,
+    request_id: RequestId,
+) -> Result<(), DbErr> {
+    let entry = audit_log::ActiveModel {
+        record_id: Set(record_id),
+        actor: Set(actor),
+        action: Set(action),
+        diff: Set(diff),
+        request_id: Set(request_id.0),
+        ..Default::default()
+    };
+    entry.insert(db).await?;
+
+    Ok(())
+}
+
+pub async fn audited_insert(
+    db: &DatabaseConnection,
+    user_info: user_info_db::ActiveModel,
+    actor: Uuid,
+    request_id: RequestId,
+) -> Result<user_info_db::Model, DbErr> {
+    let txn = db.begin().await?;
+
+    let after = user_info.insert(&txn).await?;
+    let diff = user_info_diff(None, Some(&after));
+    record_audit(&txn, after.id, actor, AuditAction::Insert, diff, request_id).await?;
+
+    txn.commit().await?;
+
+    Ok(after)
+}
+
+/// Applies `user_info` on top of `before`. Fails with `RecordNotUpdated` if the record was
+/// changed since `before` was read, so no change is ever applied or audited against a
+/// stale record.
+pub async fn audited_update<C: TransactionTrait>(
+    db: &C,
+    before: &user_info_db::Model,
+    mut user_info: user_info_db::ActiveModel,
+    actor: Uuid,
+    action: AuditAction,
+    request_id: RequestId,
+) -> Result<user_info_db::Model, DbErr> {
+    let txn = db.begin().await?;
+
+    user_info.revision = Set(before.revision + 1);
+    let after = UserInfo::update_many()
+        .set(user_info)
+        .filter(user_info_db::Column::Id.eq(before.id))
+        .filter(user_info_db::Column::Revision.eq(before.revision))
+        .exec_with_returning(&txn)
+        .await?
+        .pop()
+        .ok_or(DbErr::RecordNotUpdated)?;
+    let diff = user_info_diff(Some(before), Some(&after));
+    record_audit(&txn, after.id, actor, action, diff, request_id).await?;
+
+    txn.commit().await?;
+
+    Ok(after)
+}
+
+pub async fn audited_delete(
+    db: &DatabaseConnection,
+    before: user_info_db::Model,
+    actor: Uuid,
+    request_id: RequestId,
+) -> Result<(), DbErr> {
+    let txn = db.begin().await?;
+
+    let diff = user_info_diff(Some(&before), None);
+    let record_id = before.id;
+    before.delete(&txn).await?;
+    record_audit(
+        &txn,
+        record_id,
+        actor,
+        AuditAction::Delete,
+        diff,
+        request_id,
+    )
+    .await?;
+
+    txn.commit().await?;
+
+    Ok(())
+}
+
+#[derive(Serialize)]
+pub struct AuditLogResponse {
+    pub id: i64,
+    pub record_id: Uuid,
+    pub actor: Uuid,
+    pub action: String,
+    pub diff: Value,
+    pub created_at: chrono::NaiveDateTime,
+    pub request_id: Uuid,
+}
+
+#[derive(FromForm)]
+pub struct AuditLogRequest {
+    pub record: Option<Uuid>,
+    pub actor: Option<Uuid>,
+    pub from: Option<DateTimeParam>,
+    pub to: Option<DateTimeParam>,
+    pub start: u64,
+    pub count: u64,
+}
+
+#[get("/audit/query?<query..>")]
+pub async fn query_audit_log(
+    db: &State<DatabaseConnection>,
+    admin: AdminUser,
+    query: AuditLogRequest,
+) -> Result<Json<Vec<AuditLogResponse>>, ApiError> {
+    let db = db as &DatabaseConnection;
+
+    // People act within their own tenant; automated checks only touch records.
+    let tenant = &admin.0.user.tenant;
+    let mut select = AuditLog::find().filter(
+        Condition::any()
+            .add(audit_log::Column::Actor.in_subquery(tenant_users(tenant)))
+            .add(audit_log::Column::RecordId.in_subquery(tenant_records(tenant))),
+    );
+    if let Some(record) = query.record {
+        select = select.filter(audit_log::Column::RecordId.eq(record));
+    }
+    if let Some(actor) = query.actor {
+        select = select.filter(audit_log::Column::Actor.eq(actor));
+    }
+    if let Some(from) = query.from {
+        select = select.filter(audit_log::Column::CreatedAt.gte(from.0));
+    }
+    if let Some(to) = query.to {
+        select = select.filter(audit_log::Column::CreatedAt.lt(to.0));
+    }
+
+    let entries = select
+        .order_by_desc(audit_log::Column::CreatedAt)
+        .offset(query.start)
+        .limit(query.count)
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|x| AuditLogResponse {
+            id: x.id,
+            record_id: x.record_id,
+            actor: x.actor,
+            action: match x.action {
+                AuditAction::Insert => "insert",
+                AuditAction::Update => "update",
+                AuditAction::Delete => "delete",
+                AuditAction::Review => "review",
+            }
+            .to_owned(),
+            diff: x.diff,
+            created_at: x.created_at,
+            request_id: x.request_id,
+        })
+        .collect::<Vec<_>>();
+
+    Ok(Json(entries))
+}
+
+#[cfg(test)]
+mod tests {
+    use sea_orm::{DatabaseBackend, DbErr, IntoActiveModel, MockDatabase, Set};
+    use uuid::Uuid;
+
+    use super::audited_update;
+    use crate::api::RequestId;
+    use crate::orm::entities::{
+        sea_orm_active_enums::{AuditAction, Validated},
+        user_info,
+    };
+
+    fn record() -> user_info::Model {
+        user_info::Model {
+            id: Uuid::new_v4(),
+            creator: Uuid::new_v4(),
+            id_no: "11010519491231002X".to_owned(),
+            name: "name".to_owned(),
+            phone: "13800000000".to_owned(),
+            address: "address".to_owned(),
+            image: None,
+            validated: Validated::Pending,
+            created_at: chrono::Local::now().naive_local(),
+            review_reason: None,
+            reviewer: None,
+            reviewed_at: None,
+            revision: 3,
+            birth_date: None,
+            gender: None,
+            region_code: None,
+            phone_verified: false,
+            tenant: "default".to_owned(),
+            observation_type: None,
+            observation_start: None,
+            observation_end: None,
+            observation_status: None,
+            address_region: None,
+        }
+    }
+
+    #[rocket::async_test]
+    async fn refuses_to_update_a_changed_record() {
+        let before = record();
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([Vec::<user_info::Model>::new()])
+            .into_connection();
+
+        let mut user_info = before.clone().into_active_model();
+        user_info.validated = Set(Validated::Pass);
+        let result = audited_update(
+            &db,
+            &before,
+            user_info,
+            Uuid::new_v4(),
+            AuditAction::Review,
+            RequestId(Uuid::new_v4()),
+        )
+        .await;
+
+        assert!(matches!(result, Err(DbErr::RecordNotUpdated)));
+        let log = format!("{:?}", db.into_transaction_log());
+        assert!(log.contains(r#"\"revision\" = $4"#));
+    }
+}
@@ -0,0 +1,255 @@
+#![allow(clippy::blocks_in_conditions)]
+
+use chrono::NaiveDate;
+use rocket::{get, http::Status, post, serde::json::Json, FromForm, State};
+use sea_orm::{
+    sea_query::{OnConflict, Query},
+    ColumnTrait, DatabaseConnection, DbErr, EntityTrait, JoinType, QueryFilter, QueryOrder,
+    QuerySelect, RelationTrait, Set,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::error::ApiError;
+use crate::observation::under_observation;
+use crate::orm::entities::prelude::{HealthCheckIn as HealthCheckInDb, UserInfo as UserInfoDb};
+use crate::orm::entities::sea_orm_active_enums::{ObservationStatus, TestResult, Validated};
+use crate::orm::entities::{health_check_in as health_check_in_db, user_info as user_info_db};
+
+use super::{region::Scope, user_info::UserInfoResponse, AuthUser, SubadminOrAdmin};
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum Test {
+    NotTested,
+    Negative,
+    Positive,
+}
+
+impl From<Test> for TestResult {
+    fn from(x: Test) -> Self {
+        match x {
+            Test::NotTested => TestResult::NotTested,
+            Test::Negative => TestResult::Negative,
+            Test::Positive => TestResult::Positive,
+        }
+    }
+}
+
+impl From<TestResult> for Test {
+    fn from(x: TestResult) -> Self {
+        match x {
+            TestResult::NotTested => Test::NotTested,
+            TestResult::Negative => Test::Negative,
+            TestResult::Positive => Test::Positive,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CheckInResponse {
+    pub check_in_id: i64,
+    pub user_info_id: Uuid,
+    pub check_date: NaiveDate,
+    pub temperature: f64,
+    pub symptoms: Option<String>,
+    pub test_result: Test,
+}
+
+impl From<health_check_in_db::Model> for CheckInResponse {
+    fn from(x: health_check_in_db::Model) -> Self {
+        CheckInResponse {
+            check_in_id: x.id,
+            user_info_id: x.user_info_id,
+            check_date: x.check_date,
+            temperature: x.temperature,
+            symptoms: x.symptoms,
+            test_result: x.test_result.into(),
+        }
+    }
+}
+
+#[derive(FromForm)]
+pub struct CheckInRequest {
+    pub start: u64,
+    pub count: u64,
+    pub user_info_id: Option<Uuid>,
+}
+
+#[get("/check-in/query?<query..>")]
+pub async fn query_check_in(
+    db: &State<DatabaseConnection>,
+    auth: AuthUser,
+    query: CheckInRequest,
+) -> Result<Json<Vec<CheckInResponse>>, ApiError> {
+    let db = db as &DatabaseConnection;
+    let user = auth.user;
+
+    let mut select = HealthCheckInDb::find()
+        .join(
+            JoinType::InnerJoin,
+            health_check_in_db::Relation::UserInfo.def(),
+        )
+        .filter(user_info_db::Column::Creator.eq(user.id));
+    if let Some(user_info_id) = query.user_info_id {
+        select = select.filter(health_check_in_db::Column::UserInfoId.eq(user_info_id));
+    }
+
+    let check_ins = select
+        .order_by_desc(health_check_in_db::Column::CheckDate)
+        .offset(query.start)
+        .limit(query.count)
+        .all(db)
+        .await?
+        .into_iter()
+        .map(CheckInResponse::from)
+        .collect::<Vec<_>>();
+
+    Ok(Json(check_ins))
+}
+
+#[derive(Deserialize)]
+pub struct AddingCheckIn {
+    pub user_info_id: Uuid,
+    pub temperature: f64,
+    pub symptoms: Option<String>,
+    pub test_result: Test,
+}
+
+#[post("/check-in/add", data = "<check_in>")]
+pub async fn add_check_in(
+    db: &State<DatabaseConnection>,
+    auth: AuthUser,
+    check_in: Json<AddingCheckIn>,
+) -> Result<Json<CheckInResponse>, ApiError> {
+    let db = db as &DatabaseConnection;
+    let user = auth.user;
+
+    let AddingCheckIn {
+        user_info_id,
+        temperature,
+        symptoms,
+        test_result,
+    } = check_in.into_inner();
+
+    if !(34.0..=43.0).contains(&temperature) {
+        return Err(ApiError::validation(
+            "temperature",
+            "temperature must be between 34.0 and 43.0",
+        ));
+    }
+
+    let symptoms = symptoms
+        .map(|x| x.trim().to_owned())
+        .filter(|x| !x.is_empty());
+    if symptoms.as_ref().is_some_and(|x| x.chars().count() > 256) {
+        return Err(ApiError::validation(
+            "symptoms",
+            "symptoms must be at most 256 characters",
+        ));
+    }
+
+    let user_info = UserInfoDb::find()
+        .filter(user_info_db::Column::Id.eq(user_info_id))
+        .filter(user_info_db::Column::Creator.eq(user.id))
+        .one(db)
+        .await?
+        .ok_or_else(|| ApiError::validation("user_info_id", "user info not found"))?;
+
+    let today = chrono::Local::now().date_naive();
+    match user_info.validated {
+        Validated::Pass if under_observation(&user_info, today) => {}
+        Validated::Pass => {
+            return Err(ApiError::validation(
+                "user_info_id",
+                "user info is not under observation",
+            ))
+        }
+        _ => {
+            return Err(ApiError::validation(
+                "user_info_id",
+                "user info has not passed review",
+            ))
+        }
+    }
+
+    let check_in = health_check_in_db::ActiveModel {
+        user_info_id: Set(user_info_id),
+        check_date: Set(today),
+        temperature: Set(temperature),
+        symptoms: Set(symptoms),
+        test_result: Set(test_result.into()),
+        ..Default::default()
+    };
+
+    // The unique index on (user_info_id, check_date) decides races between two submissions.
+    let inserted = HealthCheckInDb::insert(check_in)
+        .on_conflict(
+            OnConflict::columns([
+                health_check_in_db::Column::UserInfoId,
+                health_check_in_db::Column::CheckDate,
+            ])
+            .do_nothing()
+            .to_owned(),
+        )
+        .exec(db)
+        .await
+        .map_err(|e| match e {
+            DbErr::RecordNotInserted => ApiError::new(
+                Status::Conflict,
+                "check_in_exists",
+                "already checked in today",
+            ),
+            e => e.into(),
+        })?;
+
+    let check_in = HealthCheckInDb::find_by_id(inserted.last_insert_id)
+        .one(db)
+        .await?
+        .ok_or_else(|| ApiError::not_found("check-in"))?;
+
+    Ok(Json(check_in.into()))
+}
+
+#[derive(FromForm)]
+pub struct MissedCheckInRequest {
+    pub start: u64,
+    pub count: u64,
+}
+
+/// People of the reviewer's tenant under observation who have not checked in today.
+#[get("/review/check-in/missed?<query..>")]
+pub async fn query_missed_check_in(
+    db: &State<DatabaseConnection>,
+    reviewer: SubadminOrAdmin,
+    query: MissedCheckInRequest,
+) -> Result<Json<Vec<UserInfoResponse>>, ApiError> {
+    let db = db as &DatabaseConnection;
+    let today = chrono::Local::now().date_naive();
+    let scope = Scope::of(db, &reviewer.0.user).await?;
+
+    let checked_in = Query::select()
+        .column(health_check_in_db::Column::UserInfoId)
+        .from(HealthCheckInDb)
+        .and_where(health_check_in_db::Column::CheckDate.eq(today))
+        .to_owned();
+
+    let user_infos = UserInfoDb::find()
+        .filter(user_info_db::Column::Tenant.eq(reviewer.0.user.tenant))
+        .filter(user_info_db::Column::Validated.eq(Validated::Pass))
+        .filter(scope.condition())
+        .filter(user_info_db::Column::ObservationStatus.eq(ObservationStatus::Observing))
+        .filter(user_info_db::Column::ObservationStart.lte(today))
+        .filter(user_info_db::Column::ObservationEnd.gte(today))
+        .filter(user_info_db::Column::Id.not_in_subquery(checked_in))
+        .order_by_asc(user_info_db::Column::ObservationEnd)
+        .offset(query.start)
+        .limit(query.count)
+        .all(db)
+        .await?
+        .into_iter()
+        .map(UserInfoResponse::from)
+        .collect::<Vec<_>>();
+
+    Ok(Json(user_infos))
+}
@@ -0,0 +1,168 @@
+use rocket::{
+    form::Form,
+    fs::TempFile,
+    get,
+    http::{ContentType, Status},
+    post,
+    serde::json::Json,
+    tokio::io::AsyncReadExt,
+    FromForm, State,
+};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, PaginatorTrait,
+    QueryFilter, Set,
+};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::config::ImageConfig;
+use crate::error::ApiError;
+use crate::orm::entities::{image, prelude::*, sea_orm_active_enums::UserRole, user_info};
+use crate::storage::ImageStorage;
+
+use super::{region::Scope, AuthUser};
+
+/// Detects the image type from its magic bytes instead of trusting the client.
+fn sniff_image_type(bytes: &[u8]) -> Option<ContentType> {
+    if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some(ContentType::JPEG)
+    } else if bytes.starts_with(&[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A]) {
+        Some(ContentType::PNG)
+    } else if bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        Some(ContentType::WEBP)
+    } else {
+        None
+    }
+}
+
+pub async fn owns_image(
+    db: &DatabaseConnection,
+    owner: Uuid,
+    image_id: Uuid,
+) -> Result<bool, DbErr> {
+    let count = Image::find()
+        .filter(image::Column::Id.eq(image_id))
+        .filter(image::Column::Owner.eq(owner))
+        .count(db)
+        .await?;
+
+    Ok(count > 0)
+}
+
+#[derive(FromForm)]
+pub struct ImageUpload<'r> {
+    pub file: TempFile<'r>,
+}
+
+#[derive(Serialize)]
+pub struct ImageResponse {
+    pub image_id: Uuid,
+}
+
+#[post("/image/upload", data = "<upload>")]
+pub async fn upload_image(
+    db: &State<DatabaseConnection>,
+    storage: &State<Box<dyn ImageStorage>>,
+    config: &State<ImageConfig>,
+    auth: AuthUser,
+    upload: Form<ImageUpload<'_>>,
+) -> Result<Json<ImageResponse>, ApiError> {
+    let db = db as &DatabaseConnection;
+    let user = auth.user;
+
+    if upload.file.len() > config.image_max_size {
+        return Err(ApiError::new(
+            Status::PayloadTooLarge,
+            "image_too_large",
+            format!("image must not exceed {} bytes", config.image_max_size),
+        ));
+    }
+
+    let mut bytes = Vec::new();
+    let read = match upload.file.open().await {
+        Ok(mut file) => file.read_to_end(&mut bytes).await,
+        Err(e) => Err(e),
+    };
+    read.map_err(ApiError::internal)?;
+
+    let content_type = match sniff_image_type(&bytes) {
+        Some(content_type) => content_type,
+        None => {
+            return Err(ApiError::new(
+                Status::UnsupportedMediaType,
+                "unsupported_image_type",
+                "image must be JPEG, PNG or WebP",
+            ))
+        }
+    };
+
+    let image_id = Uuid::new_v4();
+
+    storage
+        .store(image_id, &bytes)
+        .await
+        .map_err(ApiError::internal)?;
+
+    let image = image::ActiveModel {
+        id: Set(image_id),
+        owner: Set(user.id),
+        content_type: Set(content_type.to_string()),
+        size: Set(bytes.len() as i64),
+        ..Default::default()
+    };
+
+    if let Err(e) = image.insert(db).await {
+        if let Err(e) = storage.remove(image_id).await {
+            eprintln!("Error: {}", e);
+        }
+        return Err(e.into());
+    }
+
+    Ok(Json(ImageResponse { image_id }))
+}
+
+#[get("/image/<image_id>")]
+pub async fn download_image(
+    db: &State<DatabaseConnection>,
+    storage: &State<Box<dyn ImageStorage>>,
+    auth: AuthUser,
+    image_id: Uuid,
+) -> Result<(ContentType, Vec<u8>), ApiError> {
+    let db = db as &DatabaseConnection;
+    let user = auth.user;
+
+    let image = Image::find()
+        .filter(image::Column::Id.eq(image_id))
+        .one(db)
+        .await?;
+
+    let image = match image {
+        Some(image) if image.owner == user.id => image,
+        // Reviewers see the images of records they may review.
+        Some(image) if matches!(user.user_role, UserRole::Admin | UserRole::Subadmin) => {
+            let scope = Scope::of(db, &user).await?;
+            let shown = UserInfo::find()
+                .filter(user_info::Column::Image.eq(image.id))
+                .filter(user_info::Column::Tenant.eq(user.tenant.clone()))
+                .filter(scope.condition())
+                .count(db)
+                .await?;
+            if shown == 0 {
+                return Err(match scope {
+                    Scope::Tenant => ApiError::forbidden(),
+                    Scope::Regions(_) => ApiError::out_of_scope(),
+                });
+            }
+            image
+        }
+        Some(_) => return Err(ApiError::forbidden()),
+        None => return Err(ApiError::not_found("image")),
+    };
+
+    let bytes = storage.load(image.id).await.map_err(ApiError::internal)?;
+
+    let content_type =
+        ContentType::parse_flexible(&image.content_type).unwrap_or(ContentType::Binary);
+
+    Ok((content_type, bytes))
+}
@@ -1,14 +1,41 @@
 use rocket::{
-    http::Status,
+    fairing::AdHoc,
+    form::{self, FromFormField, ValueField},
+    outcome::try_outcome,
     request::{self, FromRequest, Outcome},
-    Request,
+    Request, State,
+};
+use sea_orm::{
+    sea_query::{Query, SelectStatement},
+    ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter,
 };
-use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter};
 use uuid::Uuid;
 
-use crate::orm::entities::{prelude::WeChatSession, we_chat_session};
+use crate::{
+    config::{SessionConfig, WeChatApp, WeChatAppKind, WeChatConfig},
+    error::{stash_guard_error, ApiError},
+    orm::entities::{
+        app_user,
+        prelude::{AppUser, UserInfo, WeChatIdentity, WeChatSession},
+        sea_orm_active_enums::UserRole,
+        user_info as user_info_db, we_chat_identity, we_chat_session,
+    },
+};
 
+pub mod account;
+pub mod audit;
+pub mod check_in;
+pub mod image;
+pub mod pass;
+pub mod phone;
+pub mod region;
+pub mod review;
+pub mod security;
+pub mod session;
+pub mod subscribe;
+pub mod travel;
 pub mod user_info;
+pub mod wechat_data;
 pub mod wechat_login;
 
 pub struct BearerToken {
@@ -17,37 +44,438 @@ pub struct BearerToken {
 
 #[rocket::async_trait]
 impl<'r> FromRequest<'r> for BearerToken {
-    type Error = ();
+    type Error = ApiError;
 
     async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
         match request.headers().get_one("Authorization") {
             Some(header) if header.starts_with("Bearer ") => match Uuid::parse_str(&header[7..]) {
                 Ok(token) => Outcome::Success(BearerToken { token }),
-                Err(_) => Outcome::Error((Status::Unauthorized, ())),
+                Err(_) => guard_error(request, ApiError::unauthorized()),
             },
-            _ => Outcome::Error((Status::Unauthorized, ())),
+            _ => guard_error(request, ApiError::unauthorized()),
+        }
+    }
+}
+
+/// Correlates a request with the audit entries it writes; echoed back as `X-Request-Id`.
+#[derive(Clone, Copy)]
+pub struct RequestId(pub Uuid);
+
+impl RequestId {
+    fn of(request: &Request<'_>) -> RequestId {
+        *request.local_cache(|| {
+            RequestId(
+                request
+                    .headers()
+                    .get_one("X-Request-Id")
+                    .and_then(|id| Uuid::parse_str(id).ok())
+                    .unwrap_or_else(Uuid::new_v4),
+            )
+        })
+    }
+
+    pub fn fairing() -> AdHoc {
+        AdHoc::on_response("Request ID", |request, response| {
+            Box::pin(async move {
+                response.set_raw_header("X-Request-Id", RequestId::of(request).0.to_string());
+            })
+        })
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for RequestId {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        Outcome::Success(RequestId::of(request))
+    }
+}
+
+/// A query-string timestamp, accepted as `2024-01-31T08:00:00` or `2024-01-31`.
+pub struct DateTimeParam(pub chrono::NaiveDateTime);
+
+impl<'v> FromFormField<'v> for DateTimeParam {
+    fn from_value(field: ValueField<'v>) -> form::Result<'v, Self> {
+        if let Ok(datetime) =
+            chrono::NaiveDateTime::parse_from_str(field.value, "%Y-%m-%dT%H:%M:%S")
+        {
+            return Ok(DateTimeParam(datetime));
+        }
+
+        match chrono::NaiveDate::parse_from_str(field.value, "%Y-%m-%d") {
+            Ok(date) => Ok(DateTimeParam(date.and_time(chrono::NaiveTime::MIN))),
+            Err(_) => Err(form::Error::validation("expected YYYY-MM-DD[THH:MM:SS]").into()),
+        }
+    }
+}
+
+/// The district a request is for, named by the `X-Tenant` header and defaulting to the
+/// primary app's tenant.
+pub struct Tenant(pub String);
+
+impl Tenant {
+    fn requested(request: &Request<'_>) -> Option<String> {
+        request.headers().get_one("X-Tenant").map(str::to_owned)
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Tenant {
+    type Error = ApiError;
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let config = match request.guard::<&State<WeChatConfig>>().await {
+            Outcome::Success(config) => config,
+            _ => {
+                let error = ApiError::internal("WeChat config is not managed");
+                return guard_error(request, error);
+            }
+        };
+
+        match Tenant::requested(request) {
+            None => Outcome::Success(Tenant(config.wechat_tenant.clone())),
+            Some(tenant) if config.has_tenant(&tenant) => Outcome::Success(Tenant(tenant)),
+            Some(_) => guard_error(request, ApiError::tenant_unknown()),
         }
     }
 }
 
+/// Ids of the users of `tenant`, for scoping rows that only reference a user.
+pub fn tenant_users(tenant: &str) -> SelectStatement {
+    Query::select()
+        .column(app_user::Column::Id)
+        .from(AppUser)
+        .and_where(app_user::Column::Tenant.eq(tenant))
+        .to_owned()
+}
+
+/// Ids of the `user_info` records of `tenant`.
+pub fn tenant_records(tenant: &str) -> SelectStatement {
+    Query::select()
+        .column(user_info_db::Column::Id)
+        .from(UserInfo)
+        .and_where(user_info_db::Column::Tenant.eq(tenant))
+        .to_owned()
+}
+
+pub struct ClientInfo {
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ClientInfo {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        Outcome::Success(ClientInfo {
+            ip: request.client_ip().map(|ip| ip.to_string()),
+            user_agent: request
+                .headers()
+                .get_one("User-Agent")
+                .map(|ua| ua.chars().take(256).collect()),
+        })
+    }
+}
+
+pub fn role_name(role: &UserRole) -> String {
+    match role {
+        UserRole::Admin => "admin",
+        UserRole::Inspector => "inspector",
+        UserRole::Subadmin => "subadmin",
+        UserRole::Normal => "normal",
+    }
+    .to_owned()
+}
+
+pub fn session_expired(
+    last_login: chrono::NaiveDateTime,
+    now: chrono::NaiveDateTime,
+    ttl: chrono::Duration,
+) -> bool {
+    now - last_login >= ttl
+}
+
 pub async fn validate_token(
     db: &DatabaseConnection,
+    config: &SessionConfig,
     token: &Uuid,
-) -> Result<we_chat_session::Model, Status> {
-    match WeChatSession::find()
+) -> Result<we_chat_session::Model, ApiError> {
+    let session = WeChatSession::find()
         .filter(we_chat_session::Column::LastToken.eq(*token))
         .one(db)
-        .await
-    {
-        Ok(Some(op))
-            if chrono::Local::now().naive_local() - op.last_login > chrono::Duration::hours(6) =>
+        .await?
+        .ok_or_else(ApiError::token_invalid)?;
+
+    if session.revoked {
+        return Err(ApiError::session_revoked());
+    }
+
+    if session_expired(
+        session.last_login,
+        chrono::Local::now().naive_local(),
+        config.ttl(),
+    ) {
+        return Err(ApiError::token_expired());
+    }
+
+    Ok(session)
+}
+
+async fn authenticate(request: &Request<'_>) -> Result<AuthUser, ApiError> {
+    let token = match request.guard::<BearerToken>().await {
+        Outcome::Success(token) => token.token,
+        _ => return Err(ApiError::unauthorized()),
+    };
+    let db = match request.guard::<&State<DatabaseConnection>>().await {
+        Outcome::Success(db) => db as &DatabaseConnection,
+        _ => return Err(ApiError::internal("database connection is not managed")),
+    };
+    let config = match request.guard::<&State<SessionConfig>>().await {
+        Outcome::Success(config) => config as &SessionConfig,
+        _ => return Err(ApiError::internal("session config is not managed")),
+    };
+
+    let session = validate_token(db, config, &token).await?;
+
+    // The token alone decides the tenant; a header naming another one is refused.
+    if Tenant::requested(request).is_some_and(|tenant| tenant != session.tenant) {
+        return Err(ApiError::tenant_mismatch());
+    }
+
+    let user = AppUser::find()
+        .filter(app_user::Column::Id.eq(session.user_id))
+        .one(db)
+        .await?
+        .ok_or_else(ApiError::token_invalid)?;
+
+    Ok(AuthUser { user, session })
+}
+
+fn guard_error<T>(request: &Request<'_>, error: ApiError) -> request::Outcome<T, ApiError> {
+    stash_guard_error(request, &error);
+    Outcome::Error((error.status, error))
+}
+
+/// The user behind a valid bearer token, loaded once per request.
+#[derive(Clone)]
+pub struct AuthUser {
+    pub user: app_user::Model,
+    pub session: we_chat_session::Model,
+}
+
+impl AuthUser {
+    /// The app this session was logged in with. Sessions opened before the appid was
+    /// recorded get the tenant's first mini-program.
+    pub fn app(&self, config: &WeChatConfig) -> Result<WeChatApp, ApiError> {
+        config
+            .app(&self.session.tenant, self.session.appid.as_deref())
+            .ok_or_else(|| ApiError::internal("session app is not configured"))
+    }
+
+    /// The mini-program this session was logged in with, or the tenant's first one for
+    /// sessions opened in an official account.
+    pub fn mini_program(&self, config: &WeChatConfig) -> Result<WeChatApp, ApiError> {
+        match self.app(config)? {
+            app if app.kind == WeChatAppKind::MiniProgram => Ok(app),
+            _ => config
+                .app(&self.session.tenant, None)
+                .ok_or_else(|| ApiError::internal("tenant has no mini-program configured")),
+        }
+    }
+
+    /// The openid the user has in `appid`; accounts created before identities existed
+    /// only have the one in `wechat_id`.
+    pub async fn openid_in(&self, db: &DatabaseConnection, appid: &str) -> Result<String, DbErr> {
+        let identity = WeChatIdentity::find()
+            .filter(we_chat_identity::Column::UserId.eq(self.user.id))
+            .filter(we_chat_identity::Column::Appid.eq(appid))
+            .one(db)
+            .await?;
+
+        Ok(identity.map_or_else(|| self.user.wechat_id.clone(), |x| x.openid))
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AuthUser {
+    type Error = ApiError;
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        match request
+            .local_cache_async(async { authenticate(request).await })
+            .await
         {
-            Ok(op)
+            Ok(auth) => Outcome::Success(auth.clone()),
+            Err(error) => guard_error(request, error.clone()),
         }
-        Err(e) => {
-            eprintln!("Error: {}", e);
-            Err(Status::InternalServerError)
+    }
+}
+
+async fn require_role(
+    request: &Request<'_>,
+    roles: &[UserRole],
+) -> request::Outcome<AuthUser, ApiError> {
+    let auth = try_outcome!(request.guard::<AuthUser>().await);
+
+    if roles.contains(&auth.user.user_role) {
+        Outcome::Success(auth)
+    } else {
+        guard_error(request, ApiError::forbidden())
+    }
+}
+
+pub struct AdminUser(pub AuthUser);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AdminUser {
+    type Error = ApiError;
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        require_role(request, &[UserRole::Admin])
+            .await
+            .map(AdminUser)
+    }
+}
+
+pub struct SubadminOrAdmin(pub AuthUser);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for SubadminOrAdmin {
+    type Error = ApiError;
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        require_role(request, &[UserRole::Subadmin, UserRole::Admin])
+            .await
+            .map(SubadminOrAdmin)
+    }
+}
+
+pub struct InspectorOrAdmin(pub AuthUser);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for InspectorOrAdmin {
+    type Error = ApiError;
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        require_role(request, &[UserRole::Inspector, UserRole::Admin])
+            .await
+            .map(InspectorOrAdmin)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, NaiveDate};
+    use uuid::Uuid;
+
+    use super::{session_expired, AuthUser};
+    use crate::config::{WeChatApp, WeChatAppKind, WeChatConfig};
+    use crate::orm::entities::{app_user, sea_orm_active_enums::UserRole, we_chat_session};
+
+    fn login_time() -> chrono::NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 1, 1)
+            .unwrap()
+            .and_hms_opt(8, 0, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn session_valid_just_before_ttl() {
+        let ttl = Duration::hours(6);
+        let now = login_time() + ttl - Duration::seconds(1);
+        assert!(!session_expired(login_time(), now, ttl));
+    }
+
+    #[test]
+    fn session_expires_at_ttl() {
+        let ttl = Duration::hours(6);
+        let now = login_time() + ttl;
+        assert!(session_expired(login_time(), now, ttl));
+    }
+
+    #[test]
+    fn session_expired_after_ttl() {
+        let ttl = Duration::hours(6);
+        let now = login_time() + ttl + Duration::seconds(1);
+        assert!(session_expired(login_time(), now, ttl));
+    }
+
+    #[test]
+    fn fresh_session_is_valid() {
+        assert!(!session_expired(
+            login_time(),
+            login_time(),
+            Duration::hours(6)
+        ));
+    }
+
+    fn app(appid: &str, kind: WeChatAppKind) -> WeChatApp {
+        WeChatApp {
+            appid: appid.to_owned(),
+            secret: "secret".to_owned(),
+            kind,
+            tenant: None,
+            review_template: None,
         }
-        _ => Err(Status::InternalServerError),
+    }
+
+    fn auth(appid: Option<&str>) -> AuthUser {
+        let user = app_user::Model {
+            id: Uuid::new_v4(),
+            wechat_id: "openid".to_owned(),
+            user_role: UserRole::Normal,
+            phone: None,
+            phone_verified_at: None,
+            unionid: None,
+            merged_into: None,
+            tenant: "default".to_owned(),
+        };
+        let session = we_chat_session::Model {
+            id: 1,
+            user_id: user.id,
+            last_login: login_time(),
+            last_session: String::new(),
+            last_token: Uuid::new_v4(),
+            refresh_token: Uuid::new_v4(),
+            refresh_family: Uuid::new_v4(),
+            refresh_expires: login_time(),
+            revoked: false,
+            created_at: login_time(),
+            client_ip: None,
+            user_agent: None,
+            appid: appid.map(str::to_owned),
+            tenant: "default".to_owned(),
+        };
+        AuthUser { user, session }
+    }
+
+    #[test]
+    fn uses_the_app_the_session_logged_in_with() {
+        let config = WeChatConfig {
+            wechat_appid: "appid".to_owned(),
+            wechat_secret: "secret".to_owned(),
+            wechat_api: String::new(),
+            wechat_token_margin: 300,
+            wechat_watermark_max_age: 600,
+            wechat_tenant: "default".to_owned(),
+            wechat_apps: vec![
+                app("second-appid", WeChatAppKind::MiniProgram),
+                app("oa-appid", WeChatAppKind::OfficialAccount),
+            ],
+            wechat_review_template: None,
+            wechat_push_token: None,
+            wechat_media_base_url: None,
+        };
+
+        let second = auth(Some("second-appid"));
+        assert_eq!(second.mini_program(&config).unwrap().appid, "second-appid");
+
+        let official = auth(Some("oa-appid"));
+        assert_eq!(official.app(&config).unwrap().appid, "oa-appid");
+        assert_eq!(official.mini_program(&config).unwrap().appid, "appid");
+
+        assert_eq!(auth(None).mini_program(&config).unwrap().appid, "appid");
     }
 }
@@ -0,0 +1,144 @@
+#![allow(clippy::blocks_in_conditions)]
+
+use rocket::{
+    get,
+    http::{ContentType, Status},
+    post,
+    serde::json::Json,
+    FromFormField, State,
+};
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::config::PassConfig;
+use crate::error::ApiError;
+use crate::orm::entities::prelude::UserInfo as UserInfoDb;
+use crate::orm::entities::sea_orm_active_enums::Validated;
+use crate::orm::entities::user_info as user_info_db;
+use crate::pass::{self, PassClaims};
+
+use super::{
+    user_info::{validated_name, ObservationResponse, UserInfoResponse},
+    AuthUser, InspectorOrAdmin,
+};
+
+fn pass_key(config: &PassConfig) -> Result<&[u8], ApiError> {
+    config
+        .pass_key
+        .as_deref()
+        .map(str::as_bytes)
+        .ok_or_else(|| {
+            ApiError::new(
+                Status::ServiceUnavailable,
+                "pass_unavailable",
+                "passes are not configured",
+            )
+        })
+}
+
+#[derive(FromFormField)]
+pub enum PassFormat {
+    Png,
+    Svg,
+}
+
+/// A QR code of a signed pass for one of the caller's approved records; PNG by default.
+#[get("/pass/issue?<user_info_id>&<format>")]
+pub async fn issue_pass(
+    db: &State<DatabaseConnection>,
+    config: &State<PassConfig>,
+    auth: AuthUser,
+    user_info_id: Uuid,
+    format: Option<PassFormat>,
+) -> Result<(ContentType, Vec<u8>), ApiError> {
+    let db = db as &DatabaseConnection;
+    let user = auth.user;
+    let key = pass_key(config)?;
+
+    let user_info = UserInfoDb::find()
+        .filter(user_info_db::Column::Id.eq(user_info_id))
+        .filter(user_info_db::Column::Creator.eq(user.id))
+        .one(db)
+        .await?
+        .ok_or_else(|| ApiError::not_found("user info"))?;
+    if user_info.validated != Validated::Pass {
+        return Err(ApiError::validation(
+            "user_info_id",
+            "user info has not passed review",
+        ));
+    }
+
+    let claims = PassClaims {
+        user_info_id: user_info.id,
+        status: validated_name(&user_info.validated),
+        expires_at: (chrono::Local::now() + config.ttl()).timestamp(),
+    };
+    let signed = pass::sign(key, &claims);
+
+    match format.unwrap_or(PassFormat::Png) {
+        PassFormat::Png => Ok((
+            ContentType::PNG,
+            pass::render_png(&signed).map_err(ApiError::internal)?,
+        )),
+        PassFormat::Svg => Ok((
+            ContentType::SVG,
+            pass::render_svg(&signed)
+                .map_err(ApiError::internal)?
+                .into_bytes(),
+        )),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct VerifyingPass {
+    pub pass: String,
+}
+
+#[derive(Serialize)]
+pub struct PassVerification {
+    pub user_info_id: Uuid,
+    pub name: String,
+    /// Review status now, which may differ from the one the pass was issued with.
+    pub validated: String,
+    pub observation: Option<ObservationResponse>,
+    pub expires_at: chrono::NaiveDateTime,
+    /// Whether the holder may pass: the signature holds and the record still passes review.
+    pub valid: bool,
+}
+
+#[post("/pass/verify", data = "<pass>")]
+pub async fn verify_pass(
+    db: &State<DatabaseConnection>,
+    config: &State<PassConfig>,
+    inspector: InspectorOrAdmin,
+    pass: Json<VerifyingPass>,
+) -> Result<Json<PassVerification>, ApiError> {
+    let db = db as &DatabaseConnection;
+    let key = pass_key(config)?;
+
+    let now = chrono::Local::now();
+    let claims = pass::verify(key, &pass.pass, now.timestamp())?;
+
+    let user_info = UserInfoDb::find()
+        .filter(user_info_db::Column::Id.eq(claims.user_info_id))
+        .filter(user_info_db::Column::Tenant.eq(inspector.0.user.tenant))
+        .one(db)
+        .await?
+        .ok_or_else(|| ApiError::not_found("user info"))?;
+    let valid = user_info.validated == Validated::Pass;
+    let user_info = UserInfoResponse::from(user_info);
+
+    let expires_at = chrono::DateTime::from_timestamp(claims.expires_at, 0)
+        .map(|x| x.with_timezone(now.offset()).naive_local())
+        .ok_or_else(|| ApiError::internal("pass expiry out of range"))?;
+
+    Ok(Json(PassVerification {
+        user_info_id: user_info.user_info_id,
+        name: user_info.name,
+        validated: user_info.validated,
+        observation: user_info.observation,
+        expires_at,
+        valid,
+    }))
+}
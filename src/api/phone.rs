@@ -0,0 +1,121 @@
+use std::sync::Arc;
+
+use rocket::{post, serde::json::Json, State};
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ColumnTrait, ConnectionTrait, DatabaseConnection, DbErr,
+    EntityTrait, IntoActiveModel, QueryFilter, Set, TransactionTrait,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::config::WeChatConfig;
+use crate::error::ApiError;
+use crate::orm::entities::{app_user, prelude::*, user_info};
+use crate::wechat::{with_access_token, AccessTokenProvider, WeChatClient};
+
+use super::AuthUser;
+
+/// Whether `phone`, already trimmed, is the number WeChat verified for `user`.
+pub fn is_verified_phone(user: &app_user::Model, phone: &str) -> bool {
+    user.phone.as_deref() == Some(phone)
+}
+
+/// Re-derives `phone_verified` on the records of `user_id` after its verified number
+/// became `phone`. Records whose flag flips get a new revision, as reviewers saw the old one.
+pub async fn refresh_phone_verified<C: ConnectionTrait>(
+    db: &C,
+    user_id: uuid::Uuid,
+    phone: Option<&str>,
+) -> Result<(), DbErr> {
+    let verified = match phone {
+        Some(phone) => Expr::col(user_info::Column::Phone).eq(phone),
+        None => Expr::value(false),
+    };
+
+    UserInfo::update_many()
+        .col_expr(user_info::Column::PhoneVerified, verified.clone())
+        .col_expr(
+            user_info::Column::Revision,
+            Expr::col(user_info::Column::Revision).add(1),
+        )
+        .filter(user_info::Column::Creator.eq(user_id))
+        .filter(Expr::col(user_info::Column::PhoneVerified).ne(verified))
+        .exec(db)
+        .await?;
+
+    Ok(())
+}
+
+#[derive(Deserialize)]
+pub struct BindPhoneRequest {
+    /// The `code` from the mini-program's `getPhoneNumber` button callback.
+    pub code: String,
+}
+
+#[derive(Serialize)]
+pub struct PhoneResponse {
+    pub phone: String,
+    pub verified_at: chrono::NaiveDateTime,
+}
+
+#[post("/phone/bind", format = "json", data = "<info>")]
+pub async fn bind_phone(
+    db: &State<DatabaseConnection>,
+    wechat_config: &State<WeChatConfig>,
+    wechat: &State<Arc<dyn WeChatClient>>,
+    access_token: &State<Arc<dyn AccessTokenProvider>>,
+    auth: AuthUser,
+    info: Json<BindPhoneRequest>,
+) -> Result<Json<PhoneResponse>, ApiError> {
+    let db = db as &DatabaseConnection;
+    let app = auth.mini_program(wechat_config)?;
+
+    let phone = with_access_token(access_token.as_ref(), db, &app, |token| {
+        let wechat = wechat.as_ref();
+        let code = &info.code;
+        async move { wechat.get_phone_number(&token, code).await }
+    })
+    .await?;
+
+    let now = chrono::Local::now().naive_local();
+    let txn = db.begin().await?;
+
+    let mut user = auth.user.into_active_model();
+    user.phone = Set(Some(phone.phone_number.clone()));
+    user.phone_verified_at = Set(Some(now));
+    let user = user.update(&txn).await?;
+    refresh_phone_verified(&txn, user.id, user.phone.as_deref()).await?;
+
+    txn.commit().await?;
+
+    Ok(Json(PhoneResponse {
+        phone: phone.phone_number,
+        verified_at: now,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use sea_orm::{DatabaseBackend, MockDatabase, MockExecResult};
+    use uuid::Uuid;
+
+    use super::refresh_phone_verified;
+
+    #[rocket::async_test]
+    async fn rederives_flags_against_the_new_number() {
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_exec_results([MockExecResult {
+                last_insert_id: 0,
+                rows_affected: 2,
+            }])
+            .into_connection();
+
+        refresh_phone_verified(&db, Uuid::new_v4(), Some("13800000000"))
+            .await
+            .unwrap();
+
+        let log = format!("{:?}", db.into_transaction_log()).replace('\\', "");
+        assert!(log.contains(r#"SET "phone_verified" = "phone" = $1"#));
+        assert!(log.contains(r#""revision" = "revision" + $2"#));
+        assert!(log.contains(r#""phone_verified" <> ("phone" = $4)"#));
+    }
+}
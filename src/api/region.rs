@@ -0,0 +1,238 @@
+use rocket::{delete, get, http::Status, post, serde::json::Json, State};
+use sea_orm::{
+    sea_query::OnConflict, ColumnTrait, Condition, ConnectionTrait, DatabaseConnection, DbErr,
+    EntityTrait, QueryFilter, QueryOrder, Set,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::error::ApiError;
+use crate::orm::entities::{
+    app_user, prelude::*, sea_orm_active_enums::UserRole, subadmin_region, user_info,
+};
+
+use super::AdminUser;
+
+/// Lengths of province, city, district, street and community codes.
+const REGION_CODE_LENGTHS: [usize; 5] = [2, 4, 6, 9, 12];
+
+pub fn check_region_code(field: &str, code: &str) -> Result<(), ApiError> {
+    if !REGION_CODE_LENGTHS.contains(&code.len()) || !code.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(ApiError::validation(
+            field,
+            "region code must be 2, 4, 6, 9 or 12 digits",
+        ));
+    }
+
+    Ok(())
+}
+
+/// The records a reviewer may see within their tenant.
+pub enum Scope {
+    Tenant,
+    /// Records whose address region starts with one of these codes.
+    Regions(Vec<String>),
+}
+
+impl Scope {
+    pub async fn of<C: ConnectionTrait>(db: &C, user: &app_user::Model) -> Result<Scope, DbErr> {
+        if user.user_role != UserRole::Subadmin {
+            return Ok(Scope::Tenant);
+        }
+
+        let regions = SubadminRegion::find()
+            .filter(subadmin_region::Column::UserId.eq(user.id))
+            .all(db)
+            .await?
+            .into_iter()
+            .map(|x| x.region_code)
+            .collect();
+
+        Ok(Scope::Regions(regions))
+    }
+
+    /// Filters `user_info` to the scope; a subadmin without regions matches nothing.
+    pub fn condition(&self) -> Condition {
+        match self {
+            Scope::Tenant => Condition::all(),
+            Scope::Regions(regions) => regions.iter().fold(Condition::any(), |cond, code| {
+                cond.add(user_info::Column::AddressRegion.starts_with(code))
+            }),
+        }
+    }
+
+    pub fn covers(&self, x: &user_info::Model) -> bool {
+        match (self, &x.address_region) {
+            (Scope::Tenant, _) => true,
+            (Scope::Regions(regions), Some(region)) => {
+                regions.iter().any(|code| region.starts_with(code.as_str()))
+            }
+            (Scope::Regions(_), None) => false,
+        }
+    }
+
+    pub fn check(&self, x: &user_info::Model) -> Result<(), ApiError> {
+        if self.covers(x) {
+            Ok(())
+        } else {
+            Err(ApiError::out_of_scope())
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct SubadminRegionsResponse {
+    pub user_id: Uuid,
+    pub regions: Vec<String>,
+}
+
+/// The subadmin `user_id` of the admin's tenant.
+async fn tenant_subadmin(
+    db: &DatabaseConnection,
+    admin: &app_user::Model,
+    user_id: Uuid,
+) -> Result<app_user::Model, ApiError> {
+    let user = AppUser::find_by_id(user_id)
+        .filter(app_user::Column::Tenant.eq(admin.tenant.clone()))
+        .one(db)
+        .await?
+        .ok_or_else(|| ApiError::not_found("user"))?;
+
+    if user.user_role != UserRole::Subadmin {
+        return Err(ApiError::validation("user_id", "user is not a subadmin"));
+    }
+
+    Ok(user)
+}
+
+async fn regions_of(
+    db: &DatabaseConnection,
+    user_id: Uuid,
+) -> Result<Json<SubadminRegionsResponse>, ApiError> {
+    let regions = SubadminRegion::find()
+        .filter(subadmin_region::Column::UserId.eq(user_id))
+        .order_by_asc(subadmin_region::Column::RegionCode)
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|x| x.region_code)
+        .collect();
+
+    Ok(Json(SubadminRegionsResponse { user_id, regions }))
+}
+
+#[get("/admin/subadmin-regions?<user_id>")]
+pub async fn query_subadmin_regions(
+    db: &State<DatabaseConnection>,
+    admin: AdminUser,
+    user_id: Uuid,
+) -> Result<Json<SubadminRegionsResponse>, ApiError> {
+    let db = db as &DatabaseConnection;
+
+    tenant_subadmin(db, &admin.0.user, user_id).await?;
+
+    regions_of(db, user_id).await
+}
+
+#[derive(Deserialize)]
+pub struct AssigningRegion {
+    pub user_id: Uuid,
+    pub region_code: String,
+}
+
+#[post("/admin/subadmin-regions", data = "<assignment>")]
+pub async fn assign_subadmin_region(
+    db: &State<DatabaseConnection>,
+    admin: AdminUser,
+    assignment: Json<AssigningRegion>,
+) -> Result<Json<SubadminRegionsResponse>, ApiError> {
+    let db = db as &DatabaseConnection;
+
+    let AssigningRegion {
+        user_id,
+        region_code,
+    } = assignment.into_inner();
+
+    check_region_code("region_code", &region_code)?;
+    tenant_subadmin(db, &admin.0.user, user_id).await?;
+
+    let region = subadmin_region::ActiveModel {
+        user_id: Set(user_id),
+        region_code: Set(region_code),
+        ..Default::default()
+    };
+    // Assigning a region twice is harmless.
+    SubadminRegion::insert(region)
+        .on_conflict(
+            OnConflict::columns([
+                subadmin_region::Column::UserId,
+                subadmin_region::Column::RegionCode,
+            ])
+            .do_nothing()
+            .to_owned(),
+        )
+        .exec_without_returning(db)
+        .await?;
+
+    regions_of(db, user_id).await
+}
+
+#[delete("/admin/subadmin-regions?<user_id>&<region_code>")]
+pub async fn revoke_subadmin_region(
+    db: &State<DatabaseConnection>,
+    admin: AdminUser,
+    user_id: Uuid,
+    region_code: &str,
+) -> Result<Status, ApiError> {
+    let db = db as &DatabaseConnection;
+
+    tenant_subadmin(db, &admin.0.user, user_id).await?;
+
+    let result = SubadminRegion::delete_many()
+        .filter(subadmin_region::Column::UserId.eq(user_id))
+        .filter(subadmin_region::Column::RegionCode.eq(region_code))
+        .exec(db)
+        .await?;
+    if result.rows_affected == 0 {
+        return Err(ApiError::not_found("region assignment"));
+    }
+
+    Ok(Status::Ok)
+}
+
+#[cfg(test)]
+mod tests {
+    use sea_orm::{DbBackend, EntityTrait, QueryFilter, QueryTrait};
+
+    use super::{check_region_code, Scope};
+    use crate::orm::entities::prelude::UserInfo;
+
+    fn sql(scope: Scope) -> String {
+        UserInfo::find()
+            .filter(scope.condition())
+            .build(DbBackend::Postgres)
+            .to_string()
+    }
+
+    #[test]
+    fn subadmin_sees_records_under_assigned_regions_only() {
+        let regions = Scope::Regions(vec!["1101".to_owned(), "310104".to_owned()]);
+        let scoped = sql(regions);
+        assert!(scoped.contains(r#""address_region" LIKE '1101%'"#));
+        assert!(scoped.contains(r#""address_region" LIKE '310104%'"#));
+        assert!(scoped.contains(" OR "));
+
+        assert!(sql(Scope::Regions(Vec::new())).contains("FALSE"));
+        assert!(sql(Scope::Tenant).contains("TRUE"));
+    }
+
+    #[test]
+    fn accepts_region_codes_of_every_level() {
+        for code in ["11", "1101", "110105", "110105001", "110105001001"] {
+            assert!(check_region_code("region_code", code).is_ok());
+        }
+        for code in ["1", "110", "11010500", "11010a"] {
+            assert!(check_region_code("region_code", code).is_err());
+        }
+    }
+}
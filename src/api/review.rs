@@ -0,0 +1,275 @@
+use chrono::NaiveDate;
+use rocket::{get, put, serde::json::Json, FromForm, FromFormField, State};
+use sea_orm::{
+    ColumnTrait, DatabaseConnection, EntityTrait, IntoActiveModel, QueryFilter, QueryOrder,
+    QuerySelect, Set, TransactionTrait,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::config::{ObservationConfig, WeChatConfig};
+use crate::error::ApiError;
+use crate::notify::enqueue_review;
+use crate::observation;
+use crate::orm::entities::prelude::{
+    ObservationTransition as ObservationTransitionDb, UserInfo as UserInfoDb,
+};
+use crate::orm::entities::sea_orm_active_enums::{
+    AuditAction, Gender, ObservationStatus, ObservationType, Validated,
+};
+use crate::orm::entities::{
+    observation_transition as observation_transition_db, user_info as user_info_db,
+};
+
+use super::{
+    audit::audited_update,
+    region::Scope,
+    user_info::{observation_status_name, ObservationKind, UserInfoResponse},
+    DateTimeParam, RequestId, SubadminOrAdmin,
+};
+
+#[derive(FromFormField)]
+pub enum GenderParam {
+    Male,
+    Female,
+}
+
+#[derive(FromForm)]
+pub struct PendingUserInfoRequest {
+    pub start: u64,
+    pub count: u64,
+    /// Leading digits of the ID region code, e.g. `11` or `110105`.
+    pub region: Option<String>,
+    pub gender: Option<GenderParam>,
+    pub born_from: Option<DateTimeParam>,
+    pub born_to: Option<DateTimeParam>,
+}
+
+#[get("/review/pending?<query..>")]
+pub async fn query_pending_user_info(
+    db: &State<DatabaseConnection>,
+    reviewer: SubadminOrAdmin,
+    query: PendingUserInfoRequest,
+) -> Result<Json<Vec<UserInfoResponse>>, ApiError> {
+    let db = db as &DatabaseConnection;
+    let scope = Scope::of(db, &reviewer.0.user).await?;
+
+    let mut select = UserInfoDb::find()
+        .filter(user_info_db::Column::Tenant.eq(reviewer.0.user.tenant))
+        .filter(user_info_db::Column::Validated.eq(Validated::Pending))
+        .filter(scope.condition());
+    if let Some(region) = query.region {
+        if region.is_empty() || region.len() > 6 || !region.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(ApiError::validation(
+                "region",
+                "region must be 1 to 6 digits",
+            ));
+        }
+        select = select.filter(user_info_db::Column::RegionCode.starts_with(&region));
+    }
+    if let Some(gender) = query.gender {
+        select = select.filter(user_info_db::Column::Gender.eq(match gender {
+            GenderParam::Male => Gender::Male,
+            GenderParam::Female => Gender::Female,
+        }));
+    }
+    if let Some(born_from) = query.born_from {
+        select = select.filter(user_info_db::Column::BirthDate.gte(born_from.0.date()));
+    }
+    if let Some(born_to) = query.born_to {
+        select = select.filter(user_info_db::Column::BirthDate.lte(born_to.0.date()));
+    }
+
+    let user_infos = select
+        .order_by_asc(user_info_db::Column::CreatedAt)
+        .offset(query.start)
+        .limit(query.count)
+        .all(db)
+        .await?
+        .into_iter()
+        .map(UserInfoResponse::from)
+        .collect::<Vec<_>>();
+
+    Ok(Json(user_infos))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReviewDecision {
+    Pass,
+    Blocked,
+}
+
+/// Both days are included in the period.
+#[derive(Deserialize)]
+pub struct ObservationPeriod {
+    pub kind: ObservationKind,
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+}
+
+#[derive(Deserialize)]
+pub struct ReviewingUserInfo {
+    pub id: Uuid,
+    pub decision: ReviewDecision,
+    pub reason: String,
+    /// Only read when passing; defaults to `observation_days` at home from today.
+    pub observation: Option<ObservationPeriod>,
+    /// The revision of the record the reviewer decided on.
+    pub revision: i32,
+}
+
+#[put("/review/set", data = "<review>")]
+pub async fn review_user_info(
+    db: &State<DatabaseConnection>,
+    wechat_config: &State<WeChatConfig>,
+    observation_config: &State<ObservationConfig>,
+    reviewer: SubadminOrAdmin,
+    request_id: RequestId,
+    review: Json<ReviewingUserInfo>,
+) -> Result<Json<UserInfoResponse>, ApiError> {
+    let db = db as &DatabaseConnection;
+    let reviewer = reviewer.0.user;
+
+    let ReviewingUserInfo {
+        id,
+        decision,
+        reason,
+        observation,
+        revision,
+    } = review.into_inner();
+
+    let reason = reason.trim().to_owned();
+    if reason.is_empty() || reason.chars().count() > 256 {
+        return Err(ApiError::validation(
+            "reason",
+            "reason must be 1 to 256 characters",
+        ));
+    }
+
+    let today = chrono::Local::now().date_naive();
+    let observation = match (&decision, observation) {
+        (ReviewDecision::Pass, Some(period)) => {
+            if period.start > period.end {
+                return Err(ApiError::validation(
+                    "observation",
+                    "observation must not end before it starts",
+                ));
+            }
+            if period.end < today {
+                return Err(ApiError::validation(
+                    "observation",
+                    "observation must not end before today",
+                ));
+            }
+            Some((period.kind.into(), period.start, period.end))
+        }
+        (ReviewDecision::Pass, None) => Some((
+            ObservationType::Home,
+            today,
+            today + observation_config.period() - chrono::Duration::days(1),
+        )),
+        (ReviewDecision::Blocked, _) => None,
+    };
+
+    let txn = db.begin().await?;
+
+    let before = UserInfoDb::find()
+        .filter(user_info_db::Column::Id.eq(id))
+        .filter(user_info_db::Column::Tenant.eq(reviewer.tenant.clone()))
+        .lock_exclusive()
+        .one(&txn)
+        .await?
+        .ok_or_else(|| ApiError::not_found("user info"))?;
+    Scope::of(&txn, &reviewer).await?.check(&before)?;
+    // The owner changed the record after the reviewer looked at it.
+    if before.revision != revision {
+        return Err(ApiError::record_changed());
+    }
+    let mut user_info = before.clone().into_active_model();
+
+    user_info.validated = Set(match decision {
+        ReviewDecision::Pass => Validated::Pass,
+        ReviewDecision::Blocked => Validated::Blocked,
+    });
+    user_info.review_reason = Set(Some(reason));
+    user_info.reviewer = Set(Some(reviewer.id));
+    user_info.reviewed_at = Set(Some(chrono::Local::now().naive_local()));
+    match observation {
+        Some((kind, start, end)) => {
+            user_info.observation_type = Set(Some(kind));
+            user_info.observation_start = Set(Some(start));
+            user_info.observation_end = Set(Some(end));
+            user_info.observation_status = Set(Some(ObservationStatus::Observing));
+        }
+        None => observation::cancel(&before, &mut user_info),
+    }
+
+    let after = audited_update(
+        &txn,
+        &before,
+        user_info,
+        reviewer.id,
+        AuditAction::Review,
+        request_id,
+    )
+    .await?;
+
+    observation::record_change(&txn, &before, &after, reviewer.id).await?;
+
+    // Queued with the review itself, so a committed decision is never left unannounced.
+    if after.validated != before.validated {
+        enqueue_review(&txn, wechat_config, &after).await?;
+    }
+
+    txn.commit().await?;
+
+    Ok(Json(after.into()))
+}
+
+#[derive(Serialize)]
+pub struct ObservationTransitionResponse {
+    pub id: i64,
+    pub user_info_id: Uuid,
+    pub from_status: Option<String>,
+    pub to_status: String,
+    pub actor: Uuid,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+/// Status changes of one record's observation, oldest first.
+#[get("/review/observation/transitions?<user_info_id>")]
+pub async fn query_observation_transitions(
+    db: &State<DatabaseConnection>,
+    reviewer: SubadminOrAdmin,
+    user_info_id: Uuid,
+) -> Result<Json<Vec<ObservationTransitionResponse>>, ApiError> {
+    let db = db as &DatabaseConnection;
+    let reviewer = reviewer.0.user;
+
+    let user_info = UserInfoDb::find()
+        .filter(user_info_db::Column::Id.eq(user_info_id))
+        .filter(user_info_db::Column::Tenant.eq(reviewer.tenant.clone()))
+        .one(db)
+        .await?
+        .ok_or_else(|| ApiError::not_found("user info"))?;
+    Scope::of(db, &reviewer).await?.check(&user_info)?;
+
+    let transitions = ObservationTransitionDb::find()
+        .filter(observation_transition_db::Column::UserInfoId.eq(user_info_id))
+        .order_by_asc(observation_transition_db::Column::Id)
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|x| ObservationTransitionResponse {
+            id: x.id,
+            user_info_id: x.user_info_id,
+            from_status: x.from_status.as_ref().map(observation_status_name),
+            to_status: observation_status_name(&x.to_status),
+            actor: x.actor,
+            created_at: x.created_at,
+        })
+        .collect::<Vec<_>>();
+
+    Ok(Json(transitions))
+}
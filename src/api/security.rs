@@ -0,0 +1,709 @@
+use std::sync::Arc;
+
+use rocket::{
+    get,
+    http::{ContentType, Status},
+    post,
+    request::{self, FromRequest, Outcome},
+    serde::json::Json,
+    Request, State,
+};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, DatabaseConnection, DbErr, EntityTrait,
+    IntoActiveModel, QueryFilter, QuerySelect, Set, TransactionTrait,
+};
+use serde::Deserialize;
+use serde_json::json;
+use sha1::{Digest, Sha1};
+use uuid::Uuid;
+
+use crate::config::WeChatConfig;
+use crate::error::ApiError;
+use crate::notify::enqueue_review;
+use crate::observation;
+use crate::orm::entities::{
+    media_check, prelude::*, sea_orm_active_enums::AuditAction, sea_orm_active_enums::Validated,
+    user_info,
+};
+use crate::storage::ImageStorage;
+use crate::wechat::{
+    with_access_token, AccessTokenProvider, SecCheckResult, SecSuggest, WeChatClient,
+};
+
+use super::{audit::audited_update, guard_error, AuthUser, RequestId};
+
+/// Recorded as the reviewer of decisions made by automated checks rather than a person.
+pub const SYSTEM_ACTOR: Uuid = Uuid::nil();
+
+/// Whether `record` was blocked because of its image rather than by a reviewer.
+pub fn blocked_by_screening(record: &user_info::Model) -> bool {
+    record.validated == Validated::Blocked && record.reviewer.is_none()
+}
+
+fn content_risky(field: &str, label: i32) -> ApiError {
+    ApiError::new(
+        Status::UnprocessableEntity,
+        "content_risky",
+        "content was rejected by WeChat content security",
+    )
+    .with_details(json!({ "field": field, "label": label }))
+}
+
+fn suggest_name(suggest: SecSuggest) -> &'static str {
+    match suggest {
+        SecSuggest::Pass => "pass",
+        SecSuggest::Review => "review",
+        SecSuggest::Risky => "risky",
+    }
+}
+
+/// Screens user content with WeChat before it is stored.
+pub struct ContentScreen<'r> {
+    wechat: &'r Arc<dyn WeChatClient>,
+    access_token: &'r Arc<dyn AccessTokenProvider>,
+    config: &'r WeChatConfig,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ContentScreen<'r> {
+    type Error = ApiError;
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let wechat = request.guard::<&State<Arc<dyn WeChatClient>>>().await;
+        let access_token = request
+            .guard::<&State<Arc<dyn AccessTokenProvider>>>()
+            .await;
+        let config = request.guard::<&State<WeChatConfig>>().await;
+
+        match (wechat, access_token, config) {
+            (
+                Outcome::Success(wechat),
+                Outcome::Success(access_token),
+                Outcome::Success(config),
+            ) => Outcome::Success(ContentScreen {
+                wechat: wechat.inner(),
+                access_token: access_token.inner(),
+                config: config.inner(),
+            }),
+            _ => guard_error(request, ApiError::internal("WeChat state is not managed")),
+        }
+    }
+}
+
+impl ContentScreen<'_> {
+    /// Rejects the request if WeChat judges any of the named `fields` risky.
+    pub async fn text(
+        &self,
+        db: &DatabaseConnection,
+        auth: &AuthUser,
+        fields: &[(&str, &str)],
+    ) -> Result<(), ApiError> {
+        let app = auth.mini_program(self.config)?;
+        let openid = auth.openid_in(db, &app.appid).await?;
+
+        for &(field, content) in fields {
+            if content.trim().is_empty() {
+                continue;
+            }
+
+            let result = with_access_token(self.access_token.as_ref(), db, &app, |token| {
+                let wechat = self.wechat.as_ref();
+                let openid = &openid;
+                async move { wechat.msg_sec_check(&token, openid, content).await }
+            })
+            .await?;
+
+            // `review` is left to the human reviewer every record goes through anyway.
+            if result.suggest == SecSuggest::Risky {
+                return Err(content_risky(field, result.label));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Submits `image_id` for asynchronous screening, once per image; the verdict arrives
+    /// at [`accept_push`]. An image already judged risky is rejected outright.
+    pub async fn image(
+        &self,
+        db: &DatabaseConnection,
+        auth: &AuthUser,
+        image_id: Uuid,
+    ) -> Result<(), ApiError> {
+        let check = MediaCheck::find()
+            .filter(media_check::Column::ImageId.eq(image_id))
+            .one(db)
+            .await?;
+
+        if let Some(check) = &check {
+            if check.suggest.as_deref() == Some(suggest_name(SecSuggest::Risky)) {
+                return Err(content_risky("image", check.label.unwrap_or_default()));
+            }
+            if check.trace_id.is_some() {
+                return Ok(());
+            }
+        }
+
+        let base_url = match &self.config.wechat_media_base_url {
+            Some(base_url) => base_url,
+            None => return Ok(()),
+        };
+
+        // The token lets WeChat download the image without a bearer token.
+        let check = match check {
+            Some(check) => check,
+            None => {
+                let check = media_check::ActiveModel {
+                    image_id: Set(image_id),
+                    token: Set(Uuid::new_v4()),
+                    ..Default::default()
+                };
+                check.insert(db).await?
+            }
+        };
+
+        let app = auth.mini_program(self.config)?;
+        let openid = auth.openid_in(db, &app.appid).await?;
+        let media_url = format!(
+            "{}/media-check/{}",
+            base_url.trim_end_matches('/'),
+            check.token
+        );
+
+        let trace_id = with_access_token(self.access_token.as_ref(), db, &app, |token| {
+            let wechat = self.wechat.as_ref();
+            let openid = &openid;
+            let media_url = &media_url;
+            async move { wechat.media_check_async(&token, openid, media_url).await }
+        })
+        .await?;
+
+        let mut check = check.into_active_model();
+        check.trace_id = Set(Some(trace_id));
+        check.update(db).await?;
+
+        Ok(())
+    }
+}
+
+/// Serves an image to WeChat while its screening is pending.
+#[get("/media-check/<token>")]
+pub async fn media_for_check(
+    db: &State<DatabaseConnection>,
+    storage: &State<Box<dyn ImageStorage>>,
+    token: Uuid,
+) -> Result<(ContentType, Vec<u8>), ApiError> {
+    let db = db as &DatabaseConnection;
+
+    let check = MediaCheck::find()
+        .filter(media_check::Column::Token.eq(token))
+        .filter(media_check::Column::CheckedAt.is_null())
+        .one(db)
+        .await?
+        .ok_or_else(|| ApiError::not_found("image"))?;
+
+    let image = Image::find_by_id(check.image_id)
+        .one(db)
+        .await?
+        .ok_or_else(|| ApiError::not_found("image"))?;
+
+    let bytes = storage.load(image.id).await.map_err(ApiError::internal)?;
+
+    let content_type =
+        ContentType::parse_flexible(&image.content_type).unwrap_or(ContentType::Binary);
+
+    Ok((content_type, bytes))
+}
+
+/// Checks `signature == sha1(sort(token, timestamp, nonce))` on a message push.
+fn verify_push(
+    config: &WeChatConfig,
+    signature: &str,
+    timestamp: &str,
+    nonce: &str,
+) -> Result<(), ApiError> {
+    let invalid = || {
+        ApiError::new(
+            Status::Forbidden,
+            "wechat_push_signature_invalid",
+            "message push signature does not match",
+        )
+    };
+
+    let token = config.wechat_push_token.as_deref().ok_or_else(invalid)?;
+    let mut parts = [token, timestamp, nonce];
+    parts.sort_unstable();
+
+    let mut hasher = Sha1::new();
+    hasher.update(parts.concat().as_bytes());
+    if hex::encode(hasher.finalize()) != signature.to_ascii_lowercase() {
+        return Err(invalid());
+    }
+
+    Ok(())
+}
+
+/// The check WeChat makes when the message push URL is saved in the console.
+#[get("/wechat/push?<signature>&<timestamp>&<nonce>&<echostr>")]
+pub fn verify_push_url(
+    config: &State<WeChatConfig>,
+    signature: &str,
+    timestamp: &str,
+    nonce: &str,
+    echostr: String,
+) -> Result<String, ApiError> {
+    verify_push(config, signature, timestamp, nonce)?;
+
+    Ok(echostr)
+}
+
+/// A message push in JSON format; only the fields of `wxa_media_check` are read.
+#[derive(Deserialize)]
+pub struct PushEvent {
+    #[serde(rename = "Event")]
+    pub event: Option<String>,
+    pub trace_id: Option<String>,
+    pub result: Option<SecCheckResult>,
+}
+
+/// Blocks every record showing `image_id`, which WeChat found risky.
+async fn block_records<C: ConnectionTrait + TransactionTrait>(
+    db: &C,
+    config: &WeChatConfig,
+    request_id: RequestId,
+    image_id: Uuid,
+    label: i32,
+) -> Result<(), DbErr> {
+    let records = UserInfo::find()
+        .filter(user_info::Column::Image.eq(image_id))
+        .filter(user_info::Column::Validated.ne(Validated::Blocked))
+        .all(db)
+        .await?;
+
+    for before in records {
+        let mut record = before.clone().into_active_model();
+        record.validated = Set(Validated::Blocked);
+        record.review_reason = Set(Some(format!(
+            "image failed WeChat content security check (label {})",
+            label
+        )));
+        record.reviewer = Set(None);
+        record.reviewed_at = Set(Some(chrono::Local::now().naive_local()));
+        observation::cancel(&before, &mut record);
+
+        let after = audited_update(
+            db,
+            &before,
+            record,
+            SYSTEM_ACTOR,
+            AuditAction::Review,
+            request_id,
+        )
+        .await?;
+        observation::record_change(db, &before, &after, SYSTEM_ACTOR).await?;
+        enqueue_review(db, config, &after).await?;
+    }
+
+    Ok(())
+}
+
+#[post("/wechat/push?<signature>&<timestamp>&<nonce>", data = "<event>")]
+pub async fn accept_push(
+    db: &State<DatabaseConnection>,
+    config: &State<WeChatConfig>,
+    request_id: RequestId,
+    signature: &str,
+    timestamp: &str,
+    nonce: &str,
+    event: Json<PushEvent>,
+) -> Result<&'static str, ApiError> {
+    let db = db as &DatabaseConnection;
+
+    verify_push(config, signature, timestamp, nonce)?;
+
+    let (trace_id, result) = match event.into_inner() {
+        PushEvent {
+            event: Some(event),
+            trace_id: Some(trace_id),
+            result: Some(result),
+        } if event == "wxa_media_check" => (trace_id, result),
+        // Anything else is acknowledged so WeChat does not push it again.
+        _ => return Ok("success"),
+    };
+
+    record_verdict(db, config, request_id, trace_id, result).await?;
+
+    Ok("success")
+}
+
+/// Stores the verdict of media check `trace_id`, unless it was already stored.
+///
+/// The verdict is only stored together with the blocks it causes, so a failed push is
+/// redelivered by WeChat and handled again instead of being skipped as already checked.
+async fn record_verdict(
+    db: &DatabaseConnection,
+    config: &WeChatConfig,
+    request_id: RequestId,
+    trace_id: String,
+    result: SecCheckResult,
+) -> Result<(), DbErr> {
+    let txn = db.begin().await?;
+
+    let check = MediaCheck::find()
+        .filter(media_check::Column::TraceId.eq(trace_id))
+        .filter(media_check::Column::CheckedAt.is_null())
+        .lock_exclusive()
+        .one(&txn)
+        .await?;
+    let check = match check {
+        Some(check) => check,
+        None => return Ok(()),
+    };
+    let image_id = check.image_id;
+
+    let mut check = check.into_active_model();
+    check.suggest = Set(Some(suggest_name(result.suggest).to_owned()));
+    check.label = Set(Some(result.label));
+    check.checked_at = Set(Some(chrono::Local::now().naive_local()));
+    check.update(&txn).await?;
+
+    if result.suggest == SecSuggest::Risky {
+        block_records(&txn, config, request_id, image_id, result.label).await?;
+    }
+
+    txn.commit().await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use rocket::http::{ContentType, Status};
+    use rocket::local::asynchronous::Client;
+    use rocket::routes;
+    use sea_orm::{DatabaseBackend, DbErr, MockDatabase};
+    use serde_json::json;
+    use uuid::Uuid;
+
+    use super::{accept_push, record_verdict, verify_push_url, ContentScreen, SYSTEM_ACTOR};
+    use crate::api::{AuthUser, RequestId};
+    use crate::config::WeChatConfig;
+    use crate::orm::entities::{
+        app_user, audit_log, media_check,
+        sea_orm_active_enums::{AuditAction, UserRole, Validated},
+        user_info, we_chat_access_token, we_chat_identity, we_chat_session,
+    };
+    use crate::wechat::{
+        fake::FakeWeChatClient, AccessTokenProvider, DbAccessTokenManager, SecCheckResult,
+        SecSuggest, WeChatClient,
+    };
+
+    // sha1 of "1713772800" + "nonce-1" + "push-token", the sorted parts.
+    const SIGNATURE: &str = "69485f34ac621e42c94cde2379a9464ac6aa43a2";
+
+    fn config() -> WeChatConfig {
+        WeChatConfig {
+            wechat_appid: "appid".to_owned(),
+            wechat_secret: "secret".to_owned(),
+            wechat_api: String::new(),
+            wechat_token_margin: 300,
+            wechat_watermark_max_age: 600,
+            wechat_tenant: "default".to_owned(),
+            wechat_apps: vec![],
+            wechat_review_template: None,
+            wechat_push_token: Some("push-token".to_owned()),
+            wechat_media_base_url: Some("https://example.com/".to_owned()),
+        }
+    }
+
+    fn auth() -> AuthUser {
+        let now = chrono::Local::now().naive_local();
+        let user = app_user::Model {
+            id: Uuid::new_v4(),
+            wechat_id: "openid".to_owned(),
+            user_role: UserRole::Normal,
+            phone: None,
+            phone_verified_at: None,
+            unionid: None,
+            merged_into: None,
+            tenant: "default".to_owned(),
+        };
+        let session = we_chat_session::Model {
+            id: 1,
+            user_id: user.id,
+            last_login: now,
+            last_session: "session-key".to_owned(),
+            last_token: Uuid::new_v4(),
+            refresh_token: Uuid::new_v4(),
+            refresh_family: Uuid::new_v4(),
+            refresh_expires: now,
+            revoked: false,
+            created_at: now,
+            client_ip: None,
+            user_agent: None,
+            appid: Some("appid".to_owned()),
+            tenant: "default".to_owned(),
+        };
+
+        AuthUser { user, session }
+    }
+
+    fn access_token() -> we_chat_access_token::Model {
+        let now = chrono::Local::now().naive_local();
+        we_chat_access_token::Model {
+            appid: "appid".to_owned(),
+            access_token: "token".to_owned(),
+            expires_at: now + chrono::Duration::hours(1),
+            updated_at: now,
+        }
+    }
+
+    fn token_manager(wechat: Arc<dyn WeChatClient>) -> Arc<dyn AccessTokenProvider> {
+        Arc::new(DbAccessTokenManager::new(
+            wechat,
+            chrono::Duration::seconds(300),
+        ))
+    }
+
+    #[rocket::async_test]
+    async fn rejects_text_with_risky_content() {
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([Vec::<we_chat_identity::Model>::new()])
+            .append_query_results([vec![access_token()]])
+            .into_connection();
+        let fake = Arc::new(FakeWeChatClient::default().with_risky_word("gamble"));
+        let wechat: Arc<dyn WeChatClient> = fake;
+        let access_token = token_manager(wechat.clone());
+        let config = config();
+        let screen = ContentScreen {
+            wechat: &wechat,
+            access_token: &access_token,
+            config: &config,
+        };
+
+        let err = screen
+            .text(
+                &db,
+                &auth(),
+                &[("name", "Alice"), ("address", "gamble street")],
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(err.code, "content_risky");
+        assert_eq!(
+            err.details,
+            Some(json!({ "field": "address", "label": 20001 }))
+        );
+    }
+
+    #[rocket::async_test]
+    async fn submits_each_image_for_screening_once() {
+        let now = chrono::Local::now().naive_local();
+        let check = media_check::Model {
+            id: 1,
+            image_id: Uuid::new_v4(),
+            token: Uuid::new_v4(),
+            trace_id: None,
+            suggest: None,
+            label: None,
+            created_at: now,
+            checked_at: None,
+        };
+        let traced = media_check::Model {
+            trace_id: Some("trace-1".to_owned()),
+            ..check.clone()
+        };
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([Vec::<media_check::Model>::new()])
+            .append_query_results([vec![check.clone()]])
+            .append_query_results([Vec::<we_chat_identity::Model>::new()])
+            .append_query_results([vec![access_token()]])
+            .append_query_results([vec![traced.clone()]])
+            // A later submission finds the check already traced.
+            .append_query_results([vec![traced]])
+            .into_connection();
+        let fake = Arc::new(FakeWeChatClient::default());
+        let wechat: Arc<dyn WeChatClient> = fake.clone();
+        let access_token = token_manager(wechat.clone());
+        let config = config();
+        let screen = ContentScreen {
+            wechat: &wechat,
+            access_token: &access_token,
+            config: &config,
+        };
+
+        screen.image(&db, &auth(), check.image_id).await.unwrap();
+        screen.image(&db, &auth(), check.image_id).await.unwrap();
+
+        assert_eq!(
+            fake.media_checks(),
+            vec![format!("https://example.com/media-check/{}", check.token)]
+        );
+    }
+
+    async fn client(db: MockDatabase) -> Client {
+        let rocket = rocket::build()
+            .manage(db.into_connection())
+            .manage(config())
+            .mount("/", routes![verify_push_url, accept_push]);
+
+        Client::untracked(rocket).await.unwrap()
+    }
+
+    fn push_uri(path: &str, signature: &str) -> String {
+        format!(
+            "{}signature={}&timestamp=1713772800&nonce=nonce-1",
+            path, signature
+        )
+    }
+
+    #[rocket::async_test]
+    async fn echoes_push_url_check_only_with_valid_signature() {
+        let client = client(MockDatabase::new(DatabaseBackend::Postgres)).await;
+
+        let response = client
+            .get(push_uri("/wechat/push?echostr=hello&", SIGNATURE))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.into_string().await.unwrap(), "hello");
+
+        let response = client
+            .get(push_uri("/wechat/push?echostr=hello&", &"0".repeat(40)))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Forbidden);
+    }
+
+    #[rocket::async_test]
+    async fn risky_media_verdict_blocks_records_showing_the_image() {
+        let now = chrono::Local::now().naive_local();
+        let image_id = Uuid::new_v4();
+        let check = media_check::Model {
+            id: 1,
+            image_id,
+            token: Uuid::new_v4(),
+            trace_id: Some("trace-1".to_owned()),
+            suggest: None,
+            label: None,
+            created_at: now,
+            checked_at: None,
+        };
+        let checked = media_check::Model {
+            suggest: Some("risky".to_owned()),
+            label: Some(20002),
+            checked_at: Some(now),
+            ..check.clone()
+        };
+        let record = user_info::Model {
+            id: Uuid::new_v4(),
+            creator: Uuid::new_v4(),
+            id_no: "11010519491231002X".to_owned(),
+            name: "name".to_owned(),
+            phone: "13800000000".to_owned(),
+            address: "address".to_owned(),
+            image: Some(image_id),
+            validated: Validated::Pass,
+            created_at: now,
+            review_reason: None,
+            reviewer: None,
+            reviewed_at: None,
+            revision: 0,
+            birth_date: None,
+            gender: None,
+            region_code: None,
+            phone_verified: false,
+            tenant: "default".to_owned(),
+            observation_type: None,
+            observation_start: None,
+            observation_end: None,
+            observation_status: None,
+            address_region: None,
+        };
+        let blocked = user_info::Model {
+            validated: Validated::Blocked,
+            review_reason: Some("image failed".to_owned()),
+            reviewed_at: Some(now),
+            ..record.clone()
+        };
+        let audit = audit_log::Model {
+            id: 1,
+            record_id: record.id,
+            actor: SYSTEM_ACTOR,
+            action: AuditAction::Review,
+            diff: json!({}),
+            created_at: now,
+            request_id: Uuid::new_v4(),
+        };
+
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([vec![check]])
+            .append_query_results([vec![checked]])
+            .append_query_results([vec![record]])
+            .append_query_results([vec![blocked]])
+            .append_query_results([vec![audit]]);
+        let client = client(db).await;
+
+        let response = client
+            .post(push_uri("/wechat/push?", SIGNATURE))
+            .header(ContentType::JSON)
+            .body(
+                json!({
+                    "Event": "wxa_media_check",
+                    "trace_id": "trace-1",
+                    "result": { "suggest": "risky", "label": 20002 },
+                })
+                .to_string(),
+            )
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.into_string().await.unwrap(), "success");
+    }
+
+    #[rocket::async_test]
+    async fn keeps_the_verdict_unchecked_when_blocking_fails() {
+        let now = chrono::Local::now().naive_local();
+        let check = media_check::Model {
+            id: 1,
+            image_id: Uuid::new_v4(),
+            token: Uuid::new_v4(),
+            trace_id: Some("trace-1".to_owned()),
+            suggest: None,
+            label: None,
+            created_at: now,
+            checked_at: None,
+        };
+        let checked = media_check::Model {
+            suggest: Some("risky".to_owned()),
+            label: Some(20002),
+            checked_at: Some(now),
+            ..check.clone()
+        };
+
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([vec![check]])
+            .append_query_results([vec![checked]])
+            .append_query_errors([DbErr::Custom("connection lost".to_owned())])
+            .into_connection();
+
+        let result = SecCheckResult {
+            suggest: SecSuggest::Risky,
+            label: 20002,
+        };
+        let err = record_verdict(
+            &db,
+            &config(),
+            RequestId(Uuid::new_v4()),
+            "trace-1".to_owned(),
+            result,
+        )
+        .await;
+        assert!(err.is_err());
+
+        // WeChat pushes the verdict again, which must still find the check unchecked.
+        let log = format!("{:?}", db.into_transaction_log());
+        assert!(log.contains("ROLLBACK"));
+        assert!(!log.contains("COMMIT"));
+    }
+}
@@ -0,0 +1,322 @@
+use rocket::{delete, get, http::Status, post, serde::json::Json, State};
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter,
+    QueryOrder, QuerySelect, Set, TransactionTrait,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::error::ApiError;
+use crate::orm::entities::{
+    app_user, login_history, prelude::*, retired_refresh_token, sea_orm_active_enums::LoginOutcome,
+    we_chat_session,
+};
+
+use super::{role_name, wechat_login::WeChatLoginResponse, AdminUser, AuthUser};
+
+fn refresh_token_invalid() -> ApiError {
+    ApiError::new(
+        Status::Unauthorized,
+        "refresh_token_invalid",
+        "refresh token is invalid, expired or already used",
+    )
+}
+
+#[derive(Deserialize)]
+pub struct RefreshTokenRequest {
+    pub refresh_token: Uuid,
+}
+
+/// Revokes the whole refresh token family if `refresh_token` was rotated out of it earlier,
+/// however many rotations ago.
+async fn revoke_reused_refresh_token(
+    db: &DatabaseConnection,
+    refresh_token: Uuid,
+) -> Result<(), ApiError> {
+    let Some(retired) = RetiredRefreshToken::find_by_id(refresh_token)
+        .one(db)
+        .await?
+    else {
+        return Ok(());
+    };
+
+    let revoked = revoke_sessions(
+        db,
+        we_chat_session::Column::RefreshFamily,
+        retired.refresh_family,
+    )
+    .await?;
+
+    if revoked > 0 {
+        eprintln!("Refresh token reuse detected, session revoked");
+    }
+
+    Ok(())
+}
+
+#[post("/token/refresh", format = "json", data = "<info>")]
+pub async fn refresh_token(
+    db: &State<DatabaseConnection>,
+    info: Json<RefreshTokenRequest>,
+) -> Result<Json<WeChatLoginResponse>, ApiError> {
+    let db = db as &DatabaseConnection;
+    let refresh_token = info.refresh_token;
+    let now = chrono::Local::now().naive_local();
+
+    let ret = WeChatSession::find()
+        .filter(we_chat_session::Column::RefreshToken.eq(refresh_token))
+        .one(db)
+        .await?;
+
+    let session = match ret {
+        Some(session) if !session.revoked && session.refresh_expires > now => session,
+        Some(_) => return Err(refresh_token_invalid()),
+        None => {
+            revoke_reused_refresh_token(db, refresh_token).await?;
+            return Err(refresh_token_invalid());
+        }
+    };
+
+    let token = Uuid::new_v4();
+    let next_refresh_token = Uuid::new_v4();
+
+    let txn = db.begin().await?;
+
+    // Guard on the presented refresh token so two concurrent refreshes cannot both rotate it.
+    let rotated = WeChatSession::update_many()
+        .col_expr(we_chat_session::Column::LastToken, Expr::value(token))
+        .col_expr(we_chat_session::Column::LastLogin, Expr::value(now))
+        .col_expr(
+            we_chat_session::Column::RefreshToken,
+            Expr::value(next_refresh_token),
+        )
+        .filter(we_chat_session::Column::Id.eq(session.id))
+        .filter(we_chat_session::Column::RefreshToken.eq(refresh_token))
+        .filter(we_chat_session::Column::Revoked.eq(false))
+        .exec(&txn)
+        .await?;
+
+    if rotated.rows_affected == 0 {
+        txn.rollback().await?;
+        revoke_reused_refresh_token(db, refresh_token).await?;
+        return Err(refresh_token_invalid());
+    }
+
+    retired_refresh_token::ActiveModel {
+        refresh_token: Set(refresh_token),
+        refresh_family: Set(session.refresh_family),
+        retired_at: Set(now),
+    }
+    .insert(&txn)
+    .await?;
+
+    txn.commit().await?;
+
+    let ret = AppUser::find()
+        .filter(app_user::Column::Id.eq(session.user_id))
+        .one(db)
+        .await?;
+
+    let user = ret.ok_or_else(refresh_token_invalid)?;
+
+    Ok(Json(WeChatLoginResponse {
+        token,
+        refresh_token: next_refresh_token,
+        role: role_name(&user.user_role),
+    }))
+}
+
+async fn revoke_sessions(
+    db: &DatabaseConnection,
+    column: we_chat_session::Column,
+    value: impl Into<sea_orm::Value>,
+) -> Result<u64, ApiError> {
+    let revoked = WeChatSession::update_many()
+        .col_expr(we_chat_session::Column::Revoked, Expr::value(true))
+        .filter(column.eq(value))
+        .filter(we_chat_session::Column::Revoked.eq(false))
+        .exec(db)
+        .await?;
+
+    Ok(revoked.rows_affected)
+}
+
+#[post("/logout")]
+pub async fn logout(db: &State<DatabaseConnection>, auth: AuthUser) -> Result<Status, ApiError> {
+    let db = db as &DatabaseConnection;
+
+    revoke_sessions(db, we_chat_session::Column::Id, auth.session.id).await?;
+
+    Ok(Status::Ok)
+}
+
+#[post("/admin/session/revoke?<user_id>")]
+pub async fn revoke_user_sessions(
+    db: &State<DatabaseConnection>,
+    admin: AdminUser,
+    user_id: Uuid,
+) -> Result<Status, ApiError> {
+    let db = db as &DatabaseConnection;
+
+    let ret = AppUser::find()
+        .filter(app_user::Column::Id.eq(user_id))
+        .filter(app_user::Column::Tenant.eq(admin.0.user.tenant))
+        .one(db)
+        .await?;
+
+    if ret.is_none() {
+        return Err(ApiError::not_found("user"));
+    }
+
+    revoke_sessions(db, we_chat_session::Column::UserId, user_id).await?;
+
+    Ok(Status::Ok)
+}
+
+#[derive(Serialize)]
+pub struct SessionResponse {
+    pub session_id: i32,
+    pub created_at: chrono::NaiveDateTime,
+    pub last_login: chrono::NaiveDateTime,
+    pub client_ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub current: bool,
+}
+
+#[get("/sessions")]
+pub async fn query_sessions(
+    db: &State<DatabaseConnection>,
+    auth: AuthUser,
+) -> Result<Json<Vec<SessionResponse>>, ApiError> {
+    let db = db as &DatabaseConnection;
+    let current = auth.session;
+    let now = chrono::Local::now().naive_local();
+
+    let sessions = WeChatSession::find()
+        .filter(we_chat_session::Column::UserId.eq(current.user_id))
+        .filter(we_chat_session::Column::Revoked.eq(false))
+        .filter(we_chat_session::Column::RefreshExpires.gt(now))
+        .order_by_desc(we_chat_session::Column::LastLogin)
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|x| SessionResponse {
+            session_id: x.id,
+            created_at: x.created_at,
+            last_login: x.last_login,
+            client_ip: x.client_ip,
+            user_agent: x.user_agent,
+            current: x.id == current.id,
+        })
+        .collect::<Vec<_>>();
+
+    Ok(Json(sessions))
+}
+
+#[delete("/sessions?<session_id>")]
+pub async fn revoke_session(
+    db: &State<DatabaseConnection>,
+    auth: AuthUser,
+    session_id: i32,
+) -> Result<Status, ApiError> {
+    let db = db as &DatabaseConnection;
+    let current = auth.session;
+
+    let revoked = WeChatSession::update_many()
+        .col_expr(we_chat_session::Column::Revoked, Expr::value(true))
+        .filter(we_chat_session::Column::Id.eq(session_id))
+        .filter(we_chat_session::Column::UserId.eq(current.user_id))
+        .filter(we_chat_session::Column::Revoked.eq(false))
+        .exec(db)
+        .await?;
+
+    if revoked.rows_affected == 0 {
+        return Err(ApiError::not_found("session"));
+    }
+
+    Ok(Status::Ok)
+}
+
+#[derive(Serialize)]
+pub struct LoginHistoryResponse {
+    pub login_at: chrono::NaiveDateTime,
+    pub client_ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub success: bool,
+}
+
+#[get("/login-history?<start>&<count>")]
+pub async fn query_login_history(
+    db: &State<DatabaseConnection>,
+    auth: AuthUser,
+    start: u64,
+    count: u64,
+) -> Result<Json<Vec<LoginHistoryResponse>>, ApiError> {
+    let db = db as &DatabaseConnection;
+    let current = auth.session;
+
+    let history = LoginHistory::find()
+        .filter(login_history::Column::UserId.eq(current.user_id))
+        .order_by_desc(login_history::Column::LoginAt)
+        .offset(start)
+        .limit(count)
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|x| LoginHistoryResponse {
+            login_at: x.login_at,
+            client_ip: x.client_ip,
+            user_agent: x.user_agent,
+            success: x.outcome == LoginOutcome::Success,
+        })
+        .collect::<Vec<_>>();
+
+    Ok(Json(history))
+}
+
+#[cfg(test)]
+mod tests {
+    use sea_orm::{DatabaseBackend, MockDatabase, MockExecResult};
+    use uuid::Uuid;
+
+    use super::revoke_reused_refresh_token;
+    use crate::orm::entities::retired_refresh_token;
+
+    #[rocket::async_test]
+    async fn reused_old_token_revokes_its_family() {
+        let family = Uuid::new_v4();
+        let retired = retired_refresh_token::Model {
+            refresh_token: Uuid::new_v4(),
+            refresh_family: family,
+            retired_at: chrono::Local::now().naive_local(),
+        };
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([vec![retired.clone()]])
+            .append_exec_results([MockExecResult {
+                last_insert_id: 0,
+                rows_affected: 1,
+            }])
+            .into_connection();
+
+        revoke_reused_refresh_token(&db, retired.refresh_token)
+            .await
+            .unwrap();
+
+        let log = format!("{:?}", db.into_transaction_log());
+        assert!(log.contains("refresh_family"));
+        assert!(log.contains(&family.to_string()));
+    }
+
+    #[rocket::async_test]
+    async fn unknown_token_revokes_nothing() {
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([Vec::<retired_refresh_token::Model>::new()])
+            .into_connection();
+
+        revoke_reused_refresh_token(&db, Uuid::new_v4())
+            .await
+            .unwrap();
+
+        assert_eq!(db.into_transaction_log().len(), 1);
+    }
+}
@@ -0,0 +1,101 @@
+use std::collections::BTreeMap;
+
+use rocket::{post, serde::json::Json, State};
+use sea_orm::{
+    sea_query::{Expr, OnConflict},
+    ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder, Set,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::config::WeChatConfig;
+use crate::error::ApiError;
+use crate::orm::entities::{prelude::*, subscribe_authorization};
+
+use super::AuthUser;
+
+#[derive(Deserialize)]
+pub struct SubscribeResultRequest {
+    /// The result of `wx.requestSubscribeMessage`, template id to `accept`, `reject`,
+    /// `ban` or `filter`.
+    pub results: BTreeMap<String, String>,
+}
+
+#[derive(Serialize)]
+pub struct SubscribeAuthorizationResponse {
+    pub template_id: String,
+    /// Messages that may still be sent with this template.
+    pub remaining: i32,
+}
+
+#[post("/subscribe-message/authorize", format = "json", data = "<info>")]
+pub async fn authorize_subscribe_message(
+    db: &State<DatabaseConnection>,
+    wechat_config: &State<WeChatConfig>,
+    auth: AuthUser,
+    info: Json<SubscribeResultRequest>,
+) -> Result<Json<Vec<SubscribeAuthorizationResponse>>, ApiError> {
+    let db = db as &DatabaseConnection;
+    let app = auth.mini_program(wechat_config)?;
+    let user = auth.user;
+
+    let templates = app
+        .review_template
+        .iter()
+        .map(|x| x.template_id.as_str())
+        .collect::<Vec<_>>();
+
+    for (template_id, result) in &info.results {
+        if !templates.contains(&template_id.as_str()) {
+            return Err(ApiError::validation("results", "unknown template id"));
+        }
+
+        match result.as_str() {
+            // Every accept allows one message; declining keeps earlier ones.
+            "accept" => {}
+            "reject" | "ban" | "filter" => continue,
+            _ => return Err(ApiError::validation("results", "unknown subscribe result")),
+        }
+
+        let now = chrono::Local::now().naive_local();
+        let authorization = subscribe_authorization::ActiveModel {
+            user_id: Set(user.id),
+            template_id: Set(template_id.clone()),
+            remaining: Set(1),
+            updated_at: Set(now),
+            ..Default::default()
+        };
+        SubscribeAuthorization::insert(authorization)
+            .on_conflict(
+                OnConflict::columns([
+                    subscribe_authorization::Column::UserId,
+                    subscribe_authorization::Column::TemplateId,
+                ])
+                .value(
+                    subscribe_authorization::Column::Remaining,
+                    Expr::col((
+                        SubscribeAuthorization,
+                        subscribe_authorization::Column::Remaining,
+                    ))
+                    .add(1),
+                )
+                .value(subscribe_authorization::Column::UpdatedAt, now)
+                .to_owned(),
+            )
+            .exec_without_returning(db)
+            .await?;
+    }
+
+    let authorizations = SubscribeAuthorization::find()
+        .filter(subscribe_authorization::Column::UserId.eq(user.id))
+        .order_by_asc(subscribe_authorization::Column::TemplateId)
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|x| SubscribeAuthorizationResponse {
+            template_id: x.template_id,
+            remaining: x.remaining,
+        })
+        .collect::<Vec<_>>();
+
+    Ok(Json(authorizations))
+}
@@ -0,0 +1,363 @@
+#![allow(clippy::blocks_in_conditions)]
+
+use chrono::NaiveDate;
+use rocket::{delete, get, http::Status, post, put, serde::json::Json, FromForm, State};
+use sea_orm::{
+    sea_query::Query, ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait,
+    IntoActiveModel, QueryFilter, QueryOrder, QuerySelect, Set,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::error::ApiError;
+use crate::orm::entities::prelude::{TravelRecord as TravelRecordDb, UserInfo as UserInfoDb};
+use crate::orm::entities::sea_orm_active_enums::TransportMode;
+use crate::orm::entities::{travel_record as travel_record_db, user_info as user_info_db};
+
+use super::{region::Scope, AuthUser, DateTimeParam, SubadminOrAdmin};
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Transport {
+    Bus,
+    Car,
+    Flight,
+    Ship,
+    Train,
+    Other,
+}
+
+impl From<Transport> for TransportMode {
+    fn from(x: Transport) -> Self {
+        match x {
+            Transport::Bus => TransportMode::Bus,
+            Transport::Car => TransportMode::Car,
+            Transport::Flight => TransportMode::Flight,
+            Transport::Ship => TransportMode::Ship,
+            Transport::Train => TransportMode::Train,
+            Transport::Other => TransportMode::Other,
+        }
+    }
+}
+
+impl From<TransportMode> for Transport {
+    fn from(x: TransportMode) -> Self {
+        match x {
+            TransportMode::Bus => Transport::Bus,
+            TransportMode::Car => Transport::Car,
+            TransportMode::Flight => Transport::Flight,
+            TransportMode::Ship => Transport::Ship,
+            TransportMode::Train => Transport::Train,
+            TransportMode::Other => Transport::Other,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TravelRecordResponse {
+    pub travel_record_id: Uuid,
+    pub user_info_id: Uuid,
+    pub creator: Uuid,
+    pub origin_region: String,
+    pub departure_date: NaiveDate,
+    pub arrival_date: NaiveDate,
+    pub transport: Transport,
+    pub vehicle_no: Option<String>,
+}
+
+impl From<travel_record_db::Model> for TravelRecordResponse {
+    fn from(x: travel_record_db::Model) -> Self {
+        TravelRecordResponse {
+            travel_record_id: x.id,
+            user_info_id: x.user_info_id,
+            creator: x.creator,
+            origin_region: x.origin_region,
+            departure_date: x.departure_date,
+            arrival_date: x.arrival_date,
+            transport: x.transport.into(),
+            vehicle_no: x.vehicle_no,
+        }
+    }
+}
+
+/// Checks the fields a trip is stored with, returning the normalised vehicle number.
+fn check_trip(
+    origin_region: &str,
+    departure_date: NaiveDate,
+    arrival_date: NaiveDate,
+    vehicle_no: Option<String>,
+) -> Result<Option<String>, ApiError> {
+    if origin_region.len() != 6 || !origin_region.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(ApiError::validation(
+            "origin_region",
+            "origin_region must be a 6-digit region code",
+        ));
+    }
+
+    if departure_date > arrival_date {
+        return Err(ApiError::validation(
+            "arrival_date",
+            "arrival_date must not be before departure_date",
+        ));
+    }
+
+    let vehicle_no = vehicle_no
+        .map(|x| x.trim().to_uppercase())
+        .filter(|x| !x.is_empty());
+    if vehicle_no.as_ref().is_some_and(|x| x.chars().count() > 32) {
+        return Err(ApiError::validation(
+            "vehicle_no",
+            "vehicle_no must be at most 32 characters",
+        ));
+    }
+
+    Ok(vehicle_no)
+}
+
+#[derive(FromForm)]
+pub struct TravelRecordRequest {
+    pub start: u64,
+    pub count: u64,
+    pub user_info_id: Option<Uuid>,
+}
+
+#[get("/travel-record/query?<query..>")]
+pub async fn query_travel_record(
+    db: &State<DatabaseConnection>,
+    auth: AuthUser,
+    query: TravelRecordRequest,
+) -> Result<Json<Vec<TravelRecordResponse>>, ApiError> {
+    let db = db as &DatabaseConnection;
+    let user = auth.user;
+
+    let mut select = TravelRecordDb::find().filter(travel_record_db::Column::Creator.eq(user.id));
+    if let Some(user_info_id) = query.user_info_id {
+        select = select.filter(travel_record_db::Column::UserInfoId.eq(user_info_id));
+    }
+
+    let travel_records = select
+        .order_by_desc(travel_record_db::Column::ArrivalDate)
+        .offset(query.start)
+        .limit(query.count)
+        .all(db)
+        .await?
+        .into_iter()
+        .map(TravelRecordResponse::from)
+        .collect::<Vec<_>>();
+
+    Ok(Json(travel_records))
+}
+
+#[derive(Deserialize)]
+pub struct AddingTravelRecord {
+    pub user_info_id: Uuid,
+    pub origin_region: String,
+    pub departure_date: NaiveDate,
+    pub arrival_date: NaiveDate,
+    pub transport: Transport,
+    pub vehicle_no: Option<String>,
+}
+
+#[post("/travel-record/add", data = "<travel_record>")]
+pub async fn add_travel_record(
+    db: &State<DatabaseConnection>,
+    auth: AuthUser,
+    travel_record: Json<AddingTravelRecord>,
+) -> Result<Json<TravelRecordResponse>, ApiError> {
+    let db = db as &DatabaseConnection;
+    let user = auth.user;
+
+    let AddingTravelRecord {
+        user_info_id,
+        origin_region,
+        departure_date,
+        arrival_date,
+        transport,
+        vehicle_no,
+    } = travel_record.into_inner();
+
+    let vehicle_no = check_trip(&origin_region, departure_date, arrival_date, vehicle_no)?;
+
+    let user_info = UserInfoDb::find()
+        .filter(user_info_db::Column::Id.eq(user_info_id))
+        .filter(user_info_db::Column::Creator.eq(user.id))
+        .one(db)
+        .await?;
+    if user_info.is_none() {
+        return Err(ApiError::validation("user_info_id", "user info not found"));
+    }
+
+    let travel_record = travel_record_db::ActiveModel {
+        user_info_id: Set(user_info_id),
+        creator: Set(user.id),
+        origin_region: Set(origin_region),
+        departure_date: Set(departure_date),
+        arrival_date: Set(arrival_date),
+        transport: Set(transport.into()),
+        vehicle_no: Set(vehicle_no),
+        tenant: Set(user.tenant),
+        ..Default::default()
+    };
+
+    let travel_record = travel_record.insert(db).await?;
+
+    Ok(Json(travel_record.into()))
+}
+
+#[delete("/travel-record/delete?<travel_record_id>")]
+pub async fn delete_travel_record(
+    db: &State<DatabaseConnection>,
+    auth: AuthUser,
+    travel_record_id: Uuid,
+) -> Result<Status, ApiError> {
+    let db = db as &DatabaseConnection;
+    let user = auth.user;
+
+    let result = TravelRecordDb::delete_many()
+        .filter(travel_record_db::Column::Id.eq(travel_record_id))
+        .filter(travel_record_db::Column::Creator.eq(user.id))
+        .exec(db)
+        .await?;
+    if result.rows_affected == 0 {
+        return Err(ApiError::not_found("travel record"));
+    }
+
+    Ok(Status::Ok)
+}
+
+#[derive(Deserialize)]
+pub struct ModifyingTravelRecord {
+    pub id: Uuid,
+    pub origin_region: Option<String>,
+    pub departure_date: Option<NaiveDate>,
+    pub arrival_date: Option<NaiveDate>,
+    pub transport: Option<Transport>,
+    pub vehicle_no: Option<String>,
+}
+
+#[put("/travel-record/set", data = "<travel_record>")]
+pub async fn set_travel_record(
+    db: &State<DatabaseConnection>,
+    auth: AuthUser,
+    travel_record: Json<ModifyingTravelRecord>,
+) -> Result<Json<TravelRecordResponse>, ApiError> {
+    let db = db as &DatabaseConnection;
+    let user = auth.user;
+
+    let ModifyingTravelRecord {
+        id,
+        origin_region,
+        departure_date,
+        arrival_date,
+        transport,
+        vehicle_no,
+    } = travel_record.into_inner();
+
+    let before = TravelRecordDb::find()
+        .filter(travel_record_db::Column::Id.eq(id))
+        .filter(travel_record_db::Column::Creator.eq(user.id))
+        .one(db)
+        .await?
+        .ok_or_else(|| ApiError::not_found("travel record"))?;
+
+    // The merged trip is checked as a whole, so a new departure is compared with the
+    // stored arrival and vice versa.
+    let origin_region = origin_region.unwrap_or_else(|| before.origin_region.clone());
+    let departure_date = departure_date.unwrap_or(before.departure_date);
+    let arrival_date = arrival_date.unwrap_or(before.arrival_date);
+    let vehicle_no = vehicle_no.or_else(|| before.vehicle_no.clone());
+    let vehicle_no = check_trip(&origin_region, departure_date, arrival_date, vehicle_no)?;
+
+    let mut travel_record = before.into_active_model();
+    travel_record.origin_region = Set(origin_region);
+    travel_record.departure_date = Set(departure_date);
+    travel_record.arrival_date = Set(arrival_date);
+    if let Some(transport) = transport {
+        travel_record.transport = Set(transport.into());
+    }
+    travel_record.vehicle_no = Set(vehicle_no);
+
+    let travel_record = travel_record.update(db).await?;
+
+    Ok(Json(travel_record.into()))
+}
+
+#[derive(FromForm)]
+pub struct ArrivingTravelRecordRequest {
+    pub start: u64,
+    pub count: u64,
+    pub arrive_from: Option<DateTimeParam>,
+    pub arrive_to: Option<DateTimeParam>,
+}
+
+/// Trips arriving in the reviewer's tenant, oldest arrival first; both bounds are inclusive.
+#[get("/review/travel?<query..>")]
+pub async fn query_arriving_travel_record(
+    db: &State<DatabaseConnection>,
+    reviewer: SubadminOrAdmin,
+    query: ArrivingTravelRecordRequest,
+) -> Result<Json<Vec<TravelRecordResponse>>, ApiError> {
+    let db = db as &DatabaseConnection;
+    let scope = Scope::of(db, &reviewer.0.user).await?;
+
+    let in_scope = Query::select()
+        .column(user_info_db::Column::Id)
+        .from(UserInfoDb)
+        .cond_where(scope.condition())
+        .to_owned();
+    let mut select = TravelRecordDb::find()
+        .filter(travel_record_db::Column::Tenant.eq(reviewer.0.user.tenant))
+        .filter(travel_record_db::Column::UserInfoId.in_subquery(in_scope));
+    if let Some(arrive_from) = query.arrive_from {
+        select = select.filter(travel_record_db::Column::ArrivalDate.gte(arrive_from.0.date()));
+    }
+    if let Some(arrive_to) = query.arrive_to {
+        select = select.filter(travel_record_db::Column::ArrivalDate.lte(arrive_to.0.date()));
+    }
+
+    let travel_records = select
+        .order_by_asc(travel_record_db::Column::ArrivalDate)
+        .offset(query.start)
+        .limit(query.count)
+        .all(db)
+        .await?
+        .into_iter()
+        .map(TravelRecordResponse::from)
+        .collect::<Vec<_>>();
+
+    Ok(Json(travel_records))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::check_trip;
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 2, day).unwrap()
+    }
+
+    #[test]
+    fn normalises_vehicle_no() {
+        let vehicle_no = check_trip("110105", date(1), date(2), Some(" g1234 ".to_owned()));
+        assert_eq!(vehicle_no.unwrap(), Some("G1234".to_owned()));
+
+        let vehicle_no = check_trip("110105", date(1), date(1), Some("  ".to_owned()));
+        assert_eq!(vehicle_no.unwrap(), None);
+    }
+
+    #[test]
+    fn rejects_arrival_before_departure() {
+        let err = check_trip("110105", date(2), date(1), None).unwrap_err();
+        assert_eq!(err.details.unwrap()["field"], "arrival_date");
+    }
+
+    #[test]
+    fn rejects_malformed_origin_region() {
+        for region in ["11010", "11010a", "1101050"] {
+            let err = check_trip(region, date(1), date(2), None).unwrap_err();
+            assert_eq!(err.details.unwrap()["field"], "origin_region");
+        }
+    }
+}
@@ -4,16 +4,71 @@ use rocket::{delete, post, put, FromForm};
 use rocket::{get, http::Status, serde::json::Json, State};
 use sea_orm::{
     ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, IntoActiveModel, QueryFilter,
-    QuerySelect, Set,
+    QuerySelect, Set, TransactionTrait,
 };
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+use crate::error::ApiError;
+use crate::id_card;
+use crate::observation::{self, remaining_days};
 use crate::orm::entities::prelude::UserInfo as UserInfoDb;
+use crate::orm::entities::sea_orm_active_enums::{
+    AuditAction, Gender, ObservationStatus, ObservationType, Validated,
+};
 use crate::orm::entities::user_info as user_info_db;
-use crate::orm::entities::{app_user, prelude::*};
 
-use super::{validate_token, BearerToken};
+use super::{
+    audit::{audited_delete, audited_insert, audited_update},
+    image::owns_image,
+    phone::is_verified_phone,
+    region::check_region_code,
+    security::{blocked_by_screening, ContentScreen},
+    AuthUser, RequestId,
+};
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum ObservationKind {
+    Home,
+    Centralized,
+}
+
+impl From<ObservationKind> for ObservationType {
+    fn from(x: ObservationKind) -> Self {
+        match x {
+            ObservationKind::Home => ObservationType::Home,
+            ObservationKind::Centralized => ObservationType::Centralized,
+        }
+    }
+}
+
+impl From<ObservationType> for ObservationKind {
+    fn from(x: ObservationType) -> Self {
+        match x {
+            ObservationType::Home => ObservationKind::Home,
+            ObservationType::Centralized => ObservationKind::Centralized,
+        }
+    }
+}
+
+pub fn observation_status_name(status: &ObservationStatus) -> String {
+    match status {
+        ObservationStatus::Observing => "observing",
+        ObservationStatus::Released => "released",
+        ObservationStatus::Cancelled => "cancelled",
+    }
+    .to_owned()
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ObservationResponse {
+    pub kind: ObservationKind,
+    pub start: chrono::NaiveDate,
+    pub end: chrono::NaiveDate,
+    pub status: String,
+    pub remaining_days: Option<i64>,
+}
 
 #[derive(Serialize, Deserialize)]
 pub struct UserInfoResponse {
@@ -24,6 +79,71 @@ pub struct UserInfoResponse {
     pub phone: String,
     pub address: String,
     pub image: Option<Uuid>,
+    pub validated: String,
+    pub review_reason: Option<String>,
+    pub birth_date: Option<chrono::NaiveDate>,
+    pub gender: Option<String>,
+    pub region_code: Option<String>,
+    pub address_region: Option<String>,
+    pub phone_verified: bool,
+    pub observation: Option<ObservationResponse>,
+    /// Goes up with every change; reviewers send back the one they looked at.
+    pub revision: i32,
+}
+
+pub fn validated_name(validated: &Validated) -> String {
+    match validated {
+        Validated::Pending => "pending",
+        Validated::Pass => "pass",
+        Validated::Blocked => "blocked",
+    }
+    .to_owned()
+}
+
+impl From<user_info_db::Model> for UserInfoResponse {
+    fn from(x: user_info_db::Model) -> Self {
+        let remaining_days = remaining_days(&x, chrono::Local::now().date_naive());
+        let observation = match (
+            x.observation_type,
+            x.observation_start,
+            x.observation_end,
+            x.observation_status,
+        ) {
+            (Some(kind), Some(start), Some(end), Some(status)) => Some(ObservationResponse {
+                kind: kind.into(),
+                start,
+                end,
+                status: observation_status_name(&status),
+                remaining_days,
+            }),
+            _ => None,
+        };
+
+        UserInfoResponse {
+            user_info_id: x.id,
+            creator: x.creator,
+            id_no: x.id_no,
+            name: x.name,
+            phone: x.phone,
+            address: x.address,
+            image: x.image,
+            validated: validated_name(&x.validated),
+            review_reason: x.review_reason,
+            birth_date: x.birth_date,
+            gender: x.gender.map(|gender| {
+                match gender {
+                    Gender::Male => "male",
+                    Gender::Female => "female",
+                }
+                .to_owned()
+            }),
+            region_code: x.region_code,
+            address_region: x.address_region,
+            phone_verified: x.phone_verified,
+            observation,
+            revision: x.revision,
+        }
+    }
 }
 
 #[derive(FromForm)]
@@ -35,55 +155,21 @@ pub struct UserInfoRequest {
 #[get("/user-info/query?<query..>")]
 pub async fn query_user_info(
     db: &State<DatabaseConnection>,
-    token: BearerToken,
+    auth: AuthUser,
     query: UserInfoRequest,
-) -> Result<Json<Vec<UserInfoResponse>>, Status> {
+) -> Result<Json<Vec<UserInfoResponse>>, ApiError> {
     let db = db as &DatabaseConnection;
-    let token = token.token;
-
-    let ret = validate_token(db, &token).await?;
-
-    let ret = match AppUser::find()
-        .filter(app_user::Column::Id.eq(ret.user_id))
-        .one(db)
-        .await
-    {
-        Ok(op) => op,
-        Err(e) => {
-            eprintln!("Error: {}", e);
-            return Err(Status::InternalServerError);
-        }
-    };
-
-    let user = match ret {
-        Some(user) => user,
-        None => return Err(Status::Unauthorized),
-    };
+    let user = auth.user;
 
-    let user_infos = match UserInfoDb::find()
+    let user_infos = UserInfoDb::find()
         .filter(user_info_db::Column::Creator.eq(user.id))
         .offset(query.start)
         .limit(query.count)
         .all(db)
-        .await
-    {
-        Ok(op) => op
-            .into_iter()
-            .map(|x| UserInfoResponse {
-                user_info_id: x.id,
-                creator: x.creator,
-                id_no: x.id_no,
-                name: x.name,
-                phone: x.phone,
-                address: x.address,
-                image: x.image,
-            })
-            .collect::<Vec<_>>(),
-        Err(e) => {
-            eprintln!("Error: {}", e);
-            return Err(Status::InternalServerError);
-        }
-    };
+        .await?
+        .into_iter()
+        .map(UserInfoResponse::from)
+        .collect::<Vec<_>>();
 
     Ok(Json(user_infos))
 }
@@ -94,120 +180,109 @@ pub struct AddingUserInfo {
     pub name: String,
     pub phone: String,
     pub address: String,
+    /// Region code of `address`, down to the street if known; decides which subadmins
+    /// review the record.
+    pub address_region: String,
     pub image: Option<Uuid>,
 }
 
 #[post("/user-info/add", data = "<user_info>")]
 pub async fn add_user_info(
     db: &State<DatabaseConnection>,
-    token: BearerToken,
+    auth: AuthUser,
+    screen: ContentScreen<'_>,
+    request_id: RequestId,
     user_info: Json<AddingUserInfo>,
-) -> Result<Json<UserInfoResponse>, Status> {
+) -> Result<Json<UserInfoResponse>, ApiError> {
     let db = db as &DatabaseConnection;
-    let token = token.token;
-
-    let ret = validate_token(db, &token).await?;
-
-    let ret = match AppUser::find()
-        .filter(app_user::Column::Id.eq(ret.user_id))
-        .one(db)
-        .await
-    {
-        Ok(op) => op,
-        Err(e) => {
-            eprintln!("Error: {}", e);
-            return Err(Status::InternalServerError);
-        }
-    };
-
-    let user = match ret {
-        Some(user) => user,
-        None => return Err(Status::Unauthorized),
-    };
+    let user = &auth.user;
 
     let AddingUserInfo {
         id_no,
         name,
         phone,
         address,
+        address_region,
         image,
     } = user_info.into_inner();
 
+    let phone = phone.trim().to_owned();
+    let resident_id = id_card::parse(&id_no, chrono::Local::now().date_naive())?;
+    check_region_code("address_region", &address_region)?;
+
+    if let Some(image) = image {
+        if !owns_image(db, user.id, image).await? {
+            return Err(ApiError::validation("image", "image not found"));
+        }
+    }
+
+    screen
+        .text(db, &auth, &[("name", &name), ("address", &address)])
+        .await?;
+    if let Some(image) = image {
+        screen.image(db, &auth, image).await?;
+    }
+
     let user_info = user_info_db::ActiveModel {
         creator: Set(user.id),
-        id_no: Set(id_no),
+        id_no: Set(resident_id.id_no),
         name: Set(name),
+        phone_verified: Set(is_verified_phone(user, &phone)),
         phone: Set(phone),
         address: Set(address),
+        address_region: Set(Some(address_region)),
         image: Set(image),
+        birth_date: Set(Some(resident_id.birth_date)),
+        gender: Set(Some(match resident_id.gender {
+            id_card::Gender::Male => Gender::Male,
+            id_card::Gender::Female => Gender::Female,
+        })),
+        region_code: Set(Some(resident_id.region_code)),
+        tenant: Set(user.tenant.clone()),
         ..Default::default()
     };
 
-    let user_info = match user_info.insert(db).await {
-        Ok(op) => op,
-        Err(e) => {
-            eprintln!("Error: {}", e);
-            return Err(Status::InternalServerError);
-        }
-    };
+    let user_info = audited_insert(db, user_info, user.id, request_id).await?;
 
-    Ok(Json(UserInfoResponse {
-        user_info_id: user_info.id,
-        creator: user_info.creator,
-        id_no: user_info.id_no,
-        name: user_info.name,
-        phone: user_info.phone,
-        address: user_info.address,
-        image: user_info.image,
-    }))
+    Ok(Json(user_info.into()))
 }
 
 #[delete("/user-info/delete?<user_info_id>")]
 pub async fn delete_user_info(
     db: &State<DatabaseConnection>,
-    token: BearerToken,
+    auth: AuthUser,
+    request_id: RequestId,
     user_info_id: Uuid,
-) -> Result<Status, Status> {
+) -> Result<Status, ApiError> {
     let db = db as &DatabaseConnection;
-    let token = token.token;
-
-    let ret = validate_token(db, &token).await?;
+    let user = auth.user;
 
-    let ret = match AppUser::find()
-        .filter(app_user::Column::Id.eq(ret.user_id))
+    let user_info = UserInfoDb::find()
+        .filter(user_info_db::Column::Id.eq(user_info_id))
+        .filter(user_info_db::Column::Creator.eq(user.id))
         .one(db)
-        .await
-    {
-        Ok(op) => op,
-        Err(e) => {
-            eprintln!("Error: {}", e);
-            return Err(Status::InternalServerError);
-        }
-    };
+        .await?
+        .ok_or_else(|| ApiError::not_found("user info"))?;
 
-    let user = match ret {
-        Some(user) => user,
-        None => return Err(Status::Unauthorized),
-    };
+    audited_delete(db, user_info, user.id, request_id).await?;
 
-    let user_info = match UserInfoDb::delete_many()
-        .filter(user_info_db::Column::Id.eq(user_info_id))
-        .filter(user_info_db::Column::Creator.eq(user.id))
-        .exec(db)
-        .await
-    {
-        Ok(op) => op,
-        Err(e) => {
-            eprintln!("Error: {}", e);
-            return Err(Status::InternalServerError);
-        }
-    };
+    Ok(Status::Ok)
+}
 
-    if user_info.rows_affected == 0 {
-        return Err(Status::NotFound);
+/// Invalidates the review decision on an edited record.
+///
+/// A block for a risky image stays until the image is replaced, since other edits leave
+/// the image showing; the replacement is screened like any other upload. A running
+/// observation carries on while the record waits to be reviewed again.
+fn reopen_review(before: &user_info_db::Model, user_info: &mut user_info_db::ActiveModel) {
+    if blocked_by_screening(before) && !user_info.image.is_set() {
+        return;
     }
 
-    Ok(Status::Ok)
+    user_info.validated = Set(Validated::Pending);
+    user_info.review_reason = Set(None);
+    user_info.reviewer = Set(None);
+    user_info.reviewed_at = Set(None);
 }
 
 #[derive(Serialize, Deserialize)]
@@ -215,79 +290,188 @@ pub struct ModifyingUserInfo {
     pub id: Uuid,
     pub phone: Option<String>,
     pub address: Option<String>,
+    pub address_region: Option<String>,
     pub image: Option<Uuid>,
 }
 
 #[put("/user-info/set", data = "<user_info>")]
 pub async fn set_user_info(
     db: &State<DatabaseConnection>,
-    token: BearerToken,
+    auth: AuthUser,
+    screen: ContentScreen<'_>,
+    request_id: RequestId,
     user_info: Json<ModifyingUserInfo>,
-) -> Result<Status, Status> {
+) -> Result<Status, ApiError> {
     let db = db as &DatabaseConnection;
-    let token = token.token;
-
-    let ret = validate_token(db, &token).await?;
-
-    let ret = match AppUser::find()
-        .filter(app_user::Column::Id.eq(ret.user_id))
-        .one(db)
-        .await
-    {
-        Ok(op) => op,
-        Err(e) => {
-            eprintln!("Error: {}", e);
-            return Err(Status::InternalServerError);
-        }
-    };
-
-    let user = match ret {
-        Some(user) => user,
-        None => return Err(Status::Unauthorized),
-    };
+    let user = &auth.user;
 
     let ModifyingUserInfo {
         id,
         phone,
         address,
+        address_region,
         image,
     } = user_info.into_inner();
 
-    let user_info = match UserInfoDb::find()
+    if let Some(address_region) = &address_region {
+        check_region_code("address_region", address_region)?;
+    }
+
+    if let Some(image) = image {
+        if !owns_image(db, user.id, image).await? {
+            return Err(ApiError::validation("image", "image not found"));
+        }
+    }
+
+    // Screening costs WeChat quota, so only records of the caller get that far.
+    let before = UserInfoDb::find()
         .filter(user_info_db::Column::Id.eq(id))
         .filter(user_info_db::Column::Creator.eq(user.id))
         .one(db)
-        .await
-    {
-        Ok(op) => op,
-        Err(e) => {
-            eprintln!("Error: {}", e);
-            return Err(Status::InternalServerError);
-        }
-    };
+        .await?
+        .ok_or_else(|| ApiError::not_found("user info"))?;
 
-    let mut user_info = match user_info {
-        Some(user_info) => user_info.into_active_model(),
-        None => return Err(Status::NotFound),
-    };
+    if let Some(address) = &address {
+        screen.text(db, &auth, &[("address", address)]).await?;
+    }
+    if let Some(image) = image {
+        screen.image(db, &auth, image).await?;
+    }
+
+    let mut user_info = before.clone().into_active_model();
 
-    if let Some(phone) = phone {
+    // Fields re-sent with their current value are left alone so they don't count as changes.
+    if let Some(phone) = phone
+        .map(|x| x.trim().to_owned())
+        .filter(|x| *x != before.phone)
+    {
+        user_info.phone_verified = Set(is_verified_phone(user, &phone));
         user_info.phone = Set(phone);
     }
 
-    if let Some(address) = address {
+    if let Some(address) = address.filter(|x| *x != before.address) {
         user_info.address = Set(address);
     }
 
-    if let Some(image) = image {
+    // Moving the record to another region takes it out of its reviewer's scope; the reset
+    // below puts it back to pending for the subadmins of the new region.
+    if let Some(address_region) =
+        address_region.filter(|x| before.address_region.as_ref() != Some(x))
+    {
+        user_info.address_region = Set(Some(address_region));
+    }
+
+    if let Some(image) = image.filter(|x| before.image != Some(*x)) {
         user_info.image = Set(Some(image));
     }
 
-    match user_info.update(db).await {
-        Ok(_) => Ok(Status::Ok),
-        Err(e) => {
-            eprintln!("Error: {}", e);
-            Err(Status::InternalServerError)
+    if user_info.is_changed() {
+        reopen_review(&before, &mut user_info);
+    }
+
+    let txn = db.begin().await?;
+
+    let after = audited_update(
+        &txn,
+        &before,
+        user_info,
+        user.id,
+        AuditAction::Update,
+        request_id,
+    )
+    .await?;
+    observation::record_change(&txn, &before, &after, user.id).await?;
+
+    txn.commit().await?;
+
+    Ok(Status::Ok)
+}
+
+#[cfg(test)]
+mod tests {
+    use sea_orm::{IntoActiveModel, Set};
+    use uuid::Uuid;
+
+    use super::reopen_review;
+    use crate::orm::entities::{
+        sea_orm_active_enums::{ObservationStatus, ObservationType, Validated},
+        user_info,
+    };
+
+    fn blocked(reviewer: Option<Uuid>) -> user_info::Model {
+        let now = chrono::Local::now().naive_local();
+        user_info::Model {
+            id: Uuid::new_v4(),
+            creator: Uuid::new_v4(),
+            id_no: "11010519491231002X".to_owned(),
+            name: "name".to_owned(),
+            phone: "13800000000".to_owned(),
+            address: "address".to_owned(),
+            image: Some(Uuid::new_v4()),
+            validated: Validated::Blocked,
+            created_at: now,
+            review_reason: Some("blocked".to_owned()),
+            reviewer,
+            reviewed_at: Some(now),
+            revision: 0,
+            birth_date: None,
+            gender: None,
+            region_code: None,
+            phone_verified: false,
+            tenant: "default".to_owned(),
+            observation_type: None,
+            observation_start: None,
+            observation_end: None,
+            observation_status: None,
+            address_region: None,
         }
     }
+
+    #[test]
+    fn keeps_image_blocks_until_the_image_is_replaced() {
+        let before = blocked(None);
+
+        let mut edited = before.clone().into_active_model();
+        edited.address = Set("elsewhere".to_owned());
+        reopen_review(&before, &mut edited);
+        assert_eq!(edited.validated.as_ref(), &Validated::Blocked);
+
+        let mut replaced = before.clone().into_active_model();
+        replaced.image = Set(Some(Uuid::new_v4()));
+        reopen_review(&before, &mut replaced);
+        assert_eq!(replaced.validated.as_ref(), &Validated::Pending);
+    }
+
+    #[test]
+    fn reopens_reviewer_blocks_on_any_change() {
+        let before = blocked(Some(Uuid::new_v4()));
+
+        let mut edited = before.clone().into_active_model();
+        edited.address = Set("elsewhere".to_owned());
+        reopen_review(&before, &mut edited);
+        assert_eq!(edited.validated.as_ref(), &Validated::Pending);
+        assert_eq!(edited.reviewer.as_ref(), &None);
+    }
+
+    #[test]
+    fn keeps_observing_through_owner_edits() {
+        let today = chrono::Local::now().date_naive();
+        let before = user_info::Model {
+            validated: Validated::Pass,
+            observation_type: Some(ObservationType::Home),
+            observation_start: Some(today),
+            observation_end: Some(today + chrono::Duration::days(13)),
+            observation_status: Some(ObservationStatus::Observing),
+            ..blocked(Some(Uuid::new_v4()))
+        };
+
+        let mut edited = before.clone().into_active_model();
+        edited.phone = Set("13900000000".to_owned());
+        reopen_review(&before, &mut edited);
+        assert_eq!(edited.validated.as_ref(), &Validated::Pending);
+        assert_eq!(
+            edited.observation_status.as_ref(),
+            &Some(ObservationStatus::Observing)
+        );
+    }
 }
@@ -0,0 +1,109 @@
+use rocket::{http::Status, post, serde::json::Json, State};
+use sea_orm::DatabaseConnection;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::config::WeChatConfig;
+use crate::error::ApiError;
+use crate::wechat::crypto::{check_watermark, decrypt, verify_signature};
+
+use super::AuthUser;
+
+/// Decrypts `encryptedData` with the caller's session_key and checks its watermark.
+fn open_data(
+    wechat: &WeChatConfig,
+    auth: &AuthUser,
+    encrypted_data: &str,
+    iv: &str,
+) -> Result<Value, ApiError> {
+    let app = auth.mini_program(wechat)?;
+    let data = decrypt(encrypted_data, iv, &auth.session.last_session)?;
+    check_watermark(
+        &data,
+        &app.appid,
+        chrono::Utc::now().timestamp(),
+        wechat.wechat_watermark_max_age,
+    )?;
+
+    Ok(data)
+}
+
+fn string_field(data: &Value, field: &str) -> Option<String> {
+    data.get(field).and_then(Value::as_str).map(str::to_owned)
+}
+
+#[derive(Deserialize)]
+pub struct SignedProfileRequest {
+    pub raw_data: String,
+    pub signature: String,
+    pub encrypted_data: String,
+    pub iv: String,
+}
+
+#[derive(Serialize)]
+pub struct ProfileResponse {
+    pub nick_name: Option<String>,
+    pub avatar_url: Option<String>,
+    pub unionid: Option<String>,
+}
+
+#[post("/wechat-data/profile", format = "json", data = "<info>")]
+pub async fn accept_profile(
+    db: &State<DatabaseConnection>,
+    wechat: &State<WeChatConfig>,
+    auth: AuthUser,
+    info: Json<SignedProfileRequest>,
+) -> Result<Json<ProfileResponse>, ApiError> {
+    let db = db as &DatabaseConnection;
+    let session_key = &auth.session.last_session;
+
+    verify_signature(&info.raw_data, session_key, &info.signature)?;
+    let data = open_data(wechat, &auth, &info.encrypted_data, &info.iv)?;
+
+    // Data encrypted with this session_key but for another user would be a replay.
+    let app = auth.mini_program(wechat)?;
+    let openid = auth.openid_in(db, &app.appid).await?;
+    if string_field(&data, "openId").as_ref() != Some(&openid) {
+        return Err(ApiError::new(
+            Status::Forbidden,
+            "wechat_openid_mismatch",
+            "encrypted data belongs to another user",
+        ));
+    }
+
+    Ok(Json(ProfileResponse {
+        nick_name: string_field(&data, "nickName"),
+        avatar_url: string_field(&data, "avatarUrl"),
+        unionid: string_field(&data, "unionId"),
+    }))
+}
+
+#[derive(Deserialize)]
+pub struct ShareTicketRequest {
+    pub encrypted_data: String,
+    pub iv: String,
+}
+
+#[derive(Serialize)]
+pub struct ShareTicketResponse {
+    pub open_gid: String,
+}
+
+#[post("/wechat-data/share-ticket", format = "json", data = "<info>")]
+pub async fn accept_share_ticket(
+    wechat: &State<WeChatConfig>,
+    auth: AuthUser,
+    info: Json<ShareTicketRequest>,
+) -> Result<Json<ShareTicketResponse>, ApiError> {
+    let data = open_data(wechat, &auth, &info.encrypted_data, &info.iv)?;
+
+    let open_gid = string_field(&data, "openGId").ok_or_else(|| {
+        ApiError::new(
+            Status::BadRequest,
+            "wechat_decrypt_failed",
+            "decrypted data has no openGId",
+        )
+    })?;
+
+    Ok(Json(ShareTicketResponse { open_gid }))
+}
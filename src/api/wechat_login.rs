@@ -1,141 +1,429 @@
-use rocket::{http::Status, post, serde::json::Json, State};
-use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, Set};
+use std::sync::Arc;
+
+use rocket::{post, serde::json::Json, State};
+use sea_orm::{ActiveModelTrait, DatabaseConnection, Set};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::orm::entities::{app_user, prelude::*, sea_orm_active_enums::UserRole, we_chat_session};
+use crate::{
+    config::{SessionConfig, WeChatApp, WeChatConfig},
+    error::ApiError,
+    orm::entities::{login_history, sea_orm_active_enums::LoginOutcome, we_chat_session},
+    wechat::{Code2Session, WeChatClient, WeChatError},
+};
 
-const WECHAT_API: &str = "https://api.weixin.qq.com/sns/jscode2session";
+use super::{account::resolve_user, role_name, ClientInfo, Tenant};
 
 #[derive(Deserialize)]
 pub struct WeChatLoginRequest {
     pub wechat_code: String,
-}
-
-#[derive(Deserialize)]
-pub struct WeChatLoginAPIResponse {
-    pub session_key: Option<String>,
-    pub unionid: Option<String>,
-    pub errmsg: Option<String>,
-    pub openid: Option<String>,
-    pub errcode: Option<i32>,
+    /// The app the code was issued for; defaults to the tenant's mini program.
+    pub appid: Option<String>,
 }
 
 #[derive(Serialize)]
 pub struct WeChatLoginResponse {
     pub token: Uuid,
+    pub refresh_token: Uuid,
     pub role: String,
 }
 
+async fn record_login(
+    db: &DatabaseConnection,
+    user_id: Option<Uuid>,
+    session_id: Option<i32>,
+    client: &ClientInfo,
+    outcome: LoginOutcome,
+    errcode: Option<i32>,
+) -> anyhow::Result<()> {
+    let record = login_history::ActiveModel {
+        user_id: Set(user_id),
+        session_id: Set(session_id),
+        client_ip: Set(client.ip.clone()),
+        user_agent: Set(client.user_agent.clone()),
+        outcome: Set(outcome),
+        errcode: Set(errcode),
+        ..Default::default()
+    };
+    record.insert(db).await?;
+
+    Ok(())
+}
+
 async fn get_token_and_role(
     db: &State<DatabaseConnection>,
-    resp: &WeChatLoginAPIResponse,
-) -> anyhow::Result<(Uuid, String)> {
+    config: &SessionConfig,
+    client: &ClientInfo,
+    tenant: &str,
+    app: &WeChatApp,
+    resp: &Code2Session,
+) -> anyhow::Result<(we_chat_session::Model, String)> {
     let db = db as &DatabaseConnection;
 
-    let openid = resp.openid.clone().unwrap();
-
-    let user = match AppUser::find()
-        .filter(app_user::Column::WechatId.eq(openid.clone()))
-        .one(db)
-        .await?
-    {
-        Some(user) => user,
-        None => {
-            let user = app_user::ActiveModel {
-                wechat_id: Set(openid),
-                user_role: Set(UserRole::Normal),
-                ..Default::default()
-            };
-            user.insert(db).await?
-        }
-    };
+    let user = resolve_user(db, tenant, &app.appid, resp).await?;
 
-    let user_role = match user.user_role {
-        UserRole::Admin => "admin",
-        UserRole::Subadmin => "subadmin",
-        UserRole::Normal => "normal",
-    }
-    .to_owned();
-
-    let session_key = resp.session_key.clone().unwrap();
-
-    let token = match WeChatSession::find()
-        .filter(we_chat_session::Column::UserId.eq(user.id))
-        .one(db)
-        .await?
-    {
-        Some(token) => {
-            let token = we_chat_session::ActiveModel {
-                last_session: Set(session_key),
-                ..token.into()
-            };
-            token.update(db).await?
-        }
-        None => {
-            let token = we_chat_session::ActiveModel {
-                user_id: Set(user.id),
-                last_session: Set(session_key),
-                ..Default::default()
-            };
-            token.insert(db).await?
-        }
+    let user_role = role_name(&user.user_role);
+
+    // Official account logins carry no session_key; decryption endpoints then fail closed.
+    let session_key = resp.session_key.clone().unwrap_or_default();
+    let now = chrono::Local::now().naive_local();
+
+    // Every login opens a new device session; other devices keep theirs.
+    let token = we_chat_session::ActiveModel {
+        user_id: Set(user.id),
+        last_login: Set(now),
+        last_session: Set(session_key),
+        last_token: Set(Uuid::new_v4()),
+        refresh_token: Set(Uuid::new_v4()),
+        refresh_family: Set(Uuid::new_v4()),
+        refresh_expires: Set(now + config.refresh_ttl()),
+        created_at: Set(now),
+        client_ip: Set(client.ip.clone()),
+        user_agent: Set(client.user_agent.clone()),
+        appid: Set(Some(app.appid.clone())),
+        tenant: Set(user.tenant.clone()),
+        ..Default::default()
     };
+    let token = token.insert(db).await?;
+
+    record_login(
+        db,
+        Some(user.id),
+        Some(token.id),
+        client,
+        LoginOutcome::Success,
+        None,
+    )
+    .await?;
 
-    Ok((token.last_token, user_role))
+    Ok((token, user_role))
 }
 
 #[post("/wechat-login", format = "json", data = "<info>")]
 pub async fn wechat_login_service(
     db: &State<DatabaseConnection>,
+    config: &State<SessionConfig>,
+    wechat_config: &State<WeChatConfig>,
+    wechat: &State<Arc<dyn WeChatClient>>,
+    client_info: ClientInfo,
+    tenant: Tenant,
     info: Json<WeChatLoginRequest>,
-) -> Result<Json<WeChatLoginResponse>, Status> {
-    let client = reqwest::Client::new();
-    let response = client
-        .get(WECHAT_API)
-        .query(&[
-            ("appid", crate::APPID),
-            ("secret", crate::SECRET),
-            ("js_code", &info.wechat_code),
-            ("grant_type", "authorization_code"),
-        ])
-        .send()
-        .await;
-
-    match response {
-        Ok(res) if res.status().is_success() => {
-            let text_res = match res.text().await {
-                Ok(text) => text,
-                Err(e) => {
-                    eprintln!("{}", e);
-                    return Err(Status::BadGateway);
-                }
-            };
-            let json_res = serde_json::from_str::<WeChatLoginAPIResponse>(&text_res);
-
-            match json_res {
-                Ok(json_value) if json_value.errcode.is_none() => {
-                    match get_token_and_role(db, &json_value).await {
-                        Ok((token, role)) => Ok(Json(WeChatLoginResponse { token, role })),
-                        Err(e) => {
-                            eprintln!("{}", e);
-                            Err(Status::BadGateway)
-                        }
-                    }
-                }
-                Ok(json_value) => match json_value.errcode {
-                    Some(-1) => Err(Status::ServiceUnavailable),
-                    Some(40029) => Err(Status::BadRequest),
-                    Some(40226) => Err(Status::Forbidden),
-                    Some(45011) => Err(Status::TooManyRequests),
-                    _ => Err(Status::NotImplemented),
-                },
-                Err(e) => {
+) -> Result<Json<WeChatLoginResponse>, ApiError> {
+    let app = wechat_config
+        .app(&tenant.0, info.appid.as_deref())
+        .ok_or_else(|| ApiError::validation("appid", "unknown appid"))?;
+
+    let resp = match wechat.code2session(&app, &info.wechat_code).await {
+        Ok(resp) => resp,
+        Err(e) => {
+            eprintln!("{}", e);
+            if let WeChatError::Api { errcode, .. } = e {
+                if let Err(e) = record_login(
+                    db,
+                    None,
+                    None,
+                    &client_info,
+                    LoginOutcome::Failure,
+                    Some(errcode),
+                )
+                .await
+                {
                     eprintln!("{}", e);
-                    Err(Status::BadGateway)
                 }
             }
+            return Err(e.into());
+        }
+    };
+
+    let (session, role) = get_token_and_role(db, config, &client_info, &tenant.0, &app, &resp)
+        .await
+        .map_err(ApiError::internal)?;
+
+    Ok(Json(WeChatLoginResponse {
+        token: session.last_token,
+        refresh_token: session.refresh_token,
+        role,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use rocket::http::{ContentType, Header, Status};
+    use rocket::local::asynchronous::Client;
+    use rocket::{catchers, routes};
+    use sea_orm::{DatabaseBackend, MockDatabase};
+    use serde_json::{json, Value};
+    use uuid::Uuid;
+
+    use super::wechat_login_service;
+    use crate::config::{SessionConfig, WeChatApp, WeChatAppKind, WeChatConfig};
+    use crate::error::default_catcher;
+    use crate::orm::entities::{
+        app_user, login_history,
+        sea_orm_active_enums::{LoginOutcome, UserRole},
+        we_chat_identity, we_chat_session,
+    };
+    use crate::wechat::{fake::FakeWeChatClient, stub, WeChatClient};
+
+    fn failed_login(errcode: i32) -> login_history::Model {
+        login_history::Model {
+            id: 1,
+            user_id: None,
+            session_id: None,
+            login_at: chrono::Local::now().naive_local(),
+            client_ip: None,
+            user_agent: None,
+            outcome: LoginOutcome::Failure,
+            errcode: Some(errcode),
+        }
+    }
+
+    async fn client(db: MockDatabase, wechat: Arc<dyn WeChatClient>) -> Client {
+        let rocket = rocket::build()
+            .manage(db.into_connection())
+            .manage(SessionConfig {
+                session_ttl: 60,
+                refresh_ttl: 3600,
+            })
+            .manage(WeChatConfig {
+                wechat_appid: "appid".to_owned(),
+                wechat_secret: "secret".to_owned(),
+                wechat_api: String::new(),
+                wechat_token_margin: 300,
+                wechat_watermark_max_age: 600,
+                wechat_tenant: "default".to_owned(),
+                wechat_apps: vec![
+                    WeChatApp {
+                        appid: "oa-appid".to_owned(),
+                        secret: "oa-secret".to_owned(),
+                        kind: WeChatAppKind::OfficialAccount,
+                        tenant: None,
+                        review_template: None,
+                    },
+                    WeChatApp {
+                        appid: "north-appid".to_owned(),
+                        secret: "north-secret".to_owned(),
+                        kind: WeChatAppKind::MiniProgram,
+                        tenant: Some("north".to_owned()),
+                        review_template: None,
+                    },
+                ],
+                wechat_review_template: None,
+                wechat_push_token: None,
+                wechat_media_base_url: None,
+            })
+            .manage(wechat)
+            .mount("/", routes![wechat_login_service])
+            .register("/", catchers![default_catcher]);
+
+        Client::untracked(rocket).await.unwrap()
+    }
+
+    async fn login(client: &Client, code: &str) -> (Status, Value) {
+        login_to(client, code, None).await
+    }
+
+    async fn login_to(client: &Client, code: &str, appid: Option<&str>) -> (Status, Value) {
+        login_to_tenant(client, code, appid, None).await
+    }
+
+    async fn login_to_tenant(
+        client: &Client,
+        code: &str,
+        appid: Option<&str>,
+        tenant: Option<&str>,
+    ) -> (Status, Value) {
+        let mut request = client
+            .post("/wechat-login")
+            .header(ContentType::JSON)
+            .body(json!({ "wechat_code": code, "appid": appid }).to_string());
+        if let Some(tenant) = tenant {
+            request = request.header(Header::new("X-Tenant", tenant.to_owned()));
         }
-        _ => Err(Status::InternalServerError),
+        let response = request.dispatch().await;
+        let status = response.status();
+
+        (status, response.into_json().await.unwrap())
+    }
+
+    fn user(wechat_id: &str, role: UserRole, unionid: Option<&str>) -> app_user::Model {
+        tenant_user("default", wechat_id, role, unionid)
+    }
+
+    fn tenant_user(
+        tenant: &str,
+        wechat_id: &str,
+        role: UserRole,
+        unionid: Option<&str>,
+    ) -> app_user::Model {
+        app_user::Model {
+            id: Uuid::new_v4(),
+            wechat_id: wechat_id.to_owned(),
+            user_role: role,
+            phone: None,
+            phone_verified_at: None,
+            unionid: unionid.map(str::to_owned),
+            merged_into: None,
+            tenant: tenant.to_owned(),
+        }
+    }
+
+    fn identity(user: &app_user::Model, appid: &str, openid: &str) -> we_chat_identity::Model {
+        we_chat_identity::Model {
+            id: 1,
+            user_id: user.id,
+            appid: appid.to_owned(),
+            openid: openid.to_owned(),
+            created_at: chrono::Local::now().naive_local(),
+        }
+    }
+
+    fn session(user: &app_user::Model) -> we_chat_session::Model {
+        let now = chrono::Local::now().naive_local();
+        we_chat_session::Model {
+            id: 7,
+            user_id: user.id,
+            last_login: now,
+            last_session: "session-key-openid-1".to_owned(),
+            last_token: Uuid::new_v4(),
+            refresh_token: Uuid::new_v4(),
+            refresh_family: Uuid::new_v4(),
+            refresh_expires: now,
+            revoked: false,
+            created_at: now,
+            client_ip: None,
+            user_agent: None,
+            appid: None,
+            tenant: user.tenant.clone(),
+        }
+    }
+
+    fn succeeded_login(session: &we_chat_session::Model) -> login_history::Model {
+        login_history::Model {
+            user_id: Some(session.user_id),
+            session_id: Some(session.id),
+            outcome: LoginOutcome::Success,
+            errcode: None,
+            ..failed_login(0)
+        }
+    }
+
+    #[rocket::async_test]
+    async fn logs_in_known_user() {
+        let user = user("openid-1", UserRole::Subadmin, None);
+        let session = session(&user);
+
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([vec![identity(&user, "appid", "openid-1")]])
+            .append_query_results([vec![user]])
+            .append_query_results([vec![session.clone()]])
+            .append_query_results([vec![succeeded_login(&session)]])
+            .append_query_results([vec![failed_login(40226)]]);
+        let wechat = FakeWeChatClient::default()
+            .with_session("code-1", "openid-1")
+            .with_errcode("code-2", 40226);
+        let client = client(db, Arc::new(wechat)).await;
+
+        let (status, body) = login(&client, "code-1").await;
+        assert_eq!(status, Status::Ok);
+        assert_eq!(body["token"], json!(session.last_token));
+        assert_eq!(body["refresh_token"], json!(session.refresh_token));
+        assert_eq!(body["role"], "subadmin");
+
+        let (status, body) = login(&client, "code-1").await;
+        assert_eq!(status, Status::BadRequest);
+        assert_eq!(body["code"], "wechat_code_used");
+
+        let (status, body) = login(&client, "code-2").await;
+        assert_eq!(status, Status::Forbidden);
+        assert_eq!(body["code"], "wechat_user_blocked");
+    }
+
+    #[rocket::async_test]
+    async fn matches_other_app_logins_by_unionid() {
+        let user = user("openid-1", UserRole::Normal, Some("unionid-1"));
+        let session = session(&user);
+
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            // No identity for the official account and no legacy wechat_id match.
+            .append_query_results([Vec::<we_chat_identity::Model>::new()])
+            .append_query_results([Vec::<app_user::Model>::new()])
+            .append_query_results([vec![user.clone()]])
+            .append_query_results([vec![identity(&user, "oa-appid", "oa-openid-1")]])
+            .append_query_results([vec![session.clone()]])
+            .append_query_results([vec![succeeded_login(&session)]]);
+        let wechat = FakeWeChatClient::default().with_union_session(
+            "code-1",
+            "oa-openid-1",
+            Some("unionid-1"),
+        );
+        let client = client(db, Arc::new(wechat)).await;
+
+        let (status, body) = login_to(&client, "code-1", Some("oa-appid")).await;
+        assert_eq!(status, Status::Ok);
+        assert_eq!(body["token"], json!(session.last_token));
+
+        let (status, body) = login_to(&client, "code-2", Some("unknown")).await;
+        assert_eq!(status, Status::UnprocessableEntity);
+        assert_eq!(body["code"], "validation_failed");
+    }
+
+    #[rocket::async_test]
+    async fn logs_in_within_requested_tenant() {
+        let user = tenant_user("north", "north-openid-1", UserRole::Normal, None);
+        let session = session(&user);
+
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([vec![identity(&user, "north-appid", "north-openid-1")]])
+            .append_query_results([vec![user]])
+            .append_query_results([vec![session.clone()]])
+            .append_query_results([vec![succeeded_login(&session)]]);
+        let wechat = FakeWeChatClient::default().with_session("code-1", "north-openid-1");
+        let client = client(db, Arc::new(wechat)).await;
+
+        let (status, body) = login_to_tenant(&client, "code-1", None, Some("north")).await;
+        assert_eq!(status, Status::Ok);
+        assert_eq!(body["token"], json!(session.last_token));
+
+        // Another tenant's app cannot be used to log in here.
+        let (status, body) =
+            login_to_tenant(&client, "code-2", Some("oa-appid"), Some("north")).await;
+        assert_eq!(status, Status::UnprocessableEntity);
+        assert_eq!(body["details"]["field"], "appid");
+
+        let (status, body) = login_to_tenant(&client, "code-2", None, Some("south")).await;
+        assert_eq!(status, Status::BadRequest);
+        assert_eq!(body["code"], "tenant_unknown");
+    }
+
+    #[rocket::async_test]
+    async fn maps_wechat_errcodes_end_to_end() {
+        let wechat = stub::client(&stub::spawn().await);
+        let db = stub::ERRCODES.iter().fold(
+            MockDatabase::new(DatabaseBackend::Postgres),
+            |db, &(errcode, _)| db.append_query_results([vec![failed_login(errcode)]]),
+        );
+        let client = client(db, Arc::new(wechat)).await;
+
+        for &(errcode, errmsg) in stub::ERRCODES {
+            let (status, body) = login(&client, &errcode.to_string()).await;
+            let expected = match errcode {
+                -1 => (Status::ServiceUnavailable, "wechat_busy"),
+                40029 => (Status::BadRequest, "wechat_code_invalid"),
+                40163 => (Status::BadRequest, "wechat_code_used"),
+                40226 => (Status::Forbidden, "wechat_user_blocked"),
+                45011 => (Status::TooManyRequests, "wechat_rate_limited"),
+                _ => unreachable!(),
+            };
+            assert_eq!((status, body["code"].as_str().unwrap()), expected);
+            assert_eq!(body["details"]["errcode"], errcode);
+            assert_eq!(body["details"]["errmsg"], errmsg);
+        }
+
+        let (status, body) = login(&client, stub::MALFORMED).await;
+        assert_eq!(status, Status::BadGateway);
+        assert_eq!(body["code"], "wechat_unavailable");
     }
 }
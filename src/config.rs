@@ -0,0 +1,443 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use rocket::data::{ByteUnit, ToByteUnit};
+use serde::Deserialize;
+
+const DEFAULT_SESSION_TTL: i64 = 6 * 60 * 60;
+const DEFAULT_REFRESH_TTL: i64 = 30 * 24 * 60 * 60;
+
+#[derive(Deserialize)]
+pub struct SessionConfig {
+    /// Lifetime of an access token in seconds, counted from the login that minted it.
+    #[serde(default = "default_session_ttl")]
+    pub session_ttl: i64,
+    /// Lifetime of a refresh token in seconds, counted from the `/wechat-login` that issued it.
+    #[serde(default = "default_refresh_ttl")]
+    pub refresh_ttl: i64,
+}
+
+fn default_session_ttl() -> i64 {
+    DEFAULT_SESSION_TTL
+}
+
+fn default_refresh_ttl() -> i64 {
+    DEFAULT_REFRESH_TTL
+}
+
+impl SessionConfig {
+    pub fn ttl(&self) -> chrono::Duration {
+        chrono::Duration::seconds(self.session_ttl)
+    }
+
+    pub fn refresh_ttl(&self) -> chrono::Duration {
+        chrono::Duration::seconds(self.refresh_ttl)
+    }
+}
+
+const DEFAULT_IMAGE_DIR: &str = "images";
+const DEFAULT_IMAGE_MAX_SIZE: u64 = 5 * 1024 * 1024;
+
+#[derive(Deserialize)]
+pub struct ImageConfig {
+    /// Directory used by the local filesystem image backend.
+    #[serde(default = "default_image_dir")]
+    pub image_dir: String,
+    /// Largest accepted upload in bytes; also raises Rocket's `file` and `data-form` limits.
+    #[serde(default = "default_image_max_size")]
+    pub image_max_size: u64,
+}
+
+fn default_image_dir() -> String {
+    DEFAULT_IMAGE_DIR.to_owned()
+}
+
+fn default_image_max_size() -> u64 {
+    DEFAULT_IMAGE_MAX_SIZE
+}
+
+impl ImageConfig {
+    pub fn file_limit(&self) -> ByteUnit {
+        self.image_max_size.bytes()
+    }
+
+    /// Leaves room for the multipart framing around the file itself.
+    pub fn form_limit(&self) -> ByteUnit {
+        (self.image_max_size + 64 * 1024).bytes()
+    }
+}
+
+const DEFAULT_WECHAT_API: &str = "https://api.weixin.qq.com";
+const DEFAULT_WECHAT_TOKEN_MARGIN: i64 = 5 * 60;
+const DEFAULT_WECHAT_WATERMARK_MAX_AGE: i64 = 10 * 60;
+const DEFAULT_WECHAT_TENANT: &str = "default";
+
+#[derive(Clone, Deserialize)]
+pub struct WeChatConfig {
+    /// Mini-program `appid`, e.g. from `ROCKET_WECHAT_APPID`.
+    pub wechat_appid: String,
+    /// Mini-program `secret`, e.g. from `ROCKET_WECHAT_SECRET`.
+    pub wechat_secret: String,
+    /// Base URL of the WeChat server API; point it at a stub in tests.
+    #[serde(default = "default_wechat_api")]
+    pub wechat_api: String,
+    /// Seconds before expiry at which the server access_token is refreshed.
+    #[serde(default = "default_wechat_token_margin")]
+    pub wechat_token_margin: i64,
+    /// Oldest accepted `watermark.timestamp` on decrypted user data, in seconds.
+    #[serde(default = "default_wechat_watermark_max_age")]
+    pub wechat_watermark_max_age: i64,
+    /// Tenant served by the primary app; rows created before tenants existed belong to it.
+    #[serde(default = "default_wechat_tenant")]
+    pub wechat_tenant: String,
+    /// Further apps, each serving one tenant; apps of a tenant share users by unionid.
+    #[serde(default)]
+    pub wechat_apps: Vec<WeChatApp>,
+    /// Subscribe message sent by the primary app when a record is reviewed.
+    #[serde(default)]
+    pub wechat_review_template: Option<SubscribeTemplate>,
+    /// Token entered with the message push URL in the WeChat console; pushes are refused
+    /// while it is unset.
+    #[serde(default)]
+    pub wechat_push_token: Option<String>,
+    /// Public base URL of this server, from which WeChat fetches images to screen; images
+    /// are not screened while it is unset.
+    #[serde(default)]
+    pub wechat_media_base_url: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WeChatAppKind {
+    /// Logs in with `wx.login` codes through jscode2session.
+    #[default]
+    MiniProgram,
+    /// Logs in with web OAuth codes through `sns/oauth2/access_token`.
+    OfficialAccount,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct WeChatApp {
+    pub appid: String,
+    pub secret: String,
+    #[serde(default)]
+    pub kind: WeChatAppKind,
+    /// The district this app serves; defaults to `wechat_tenant`.
+    #[serde(default)]
+    pub tenant: Option<String>,
+    /// Subscribe message this app sends when a record is reviewed.
+    #[serde(default)]
+    pub review_template: Option<SubscribeTemplate>,
+}
+
+/// What a template keyword is filled with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReviewField {
+    Name,
+    Decision,
+    Reason,
+    ReviewedAt,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct SubscribeTemplate {
+    pub template_id: String,
+    /// Mini-program page opened from the message.
+    #[serde(default)]
+    pub page: Option<String>,
+    /// Template keyword, e.g. `thing1`, to the field it shows.
+    pub data: BTreeMap<String, ReviewField>,
+    /// Text shown for each decision, keyed `pass` or `blocked`; defaults to the key itself.
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+}
+
+fn default_wechat_api() -> String {
+    DEFAULT_WECHAT_API.to_owned()
+}
+
+fn default_wechat_token_margin() -> i64 {
+    DEFAULT_WECHAT_TOKEN_MARGIN
+}
+
+fn default_wechat_watermark_max_age() -> i64 {
+    DEFAULT_WECHAT_WATERMARK_MAX_AGE
+}
+
+fn default_wechat_tenant() -> String {
+    DEFAULT_WECHAT_TENANT.to_owned()
+}
+
+impl WeChatConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.wechat_appid.trim().is_empty() {
+            return Err("wechat_appid must not be empty".to_owned());
+        }
+        if self.wechat_secret.trim().is_empty() {
+            return Err("wechat_secret must not be empty".to_owned());
+        }
+        if self.wechat_tenant.trim().is_empty() {
+            return Err("wechat_tenant must not be empty".to_owned());
+        }
+        for (i, app) in self.wechat_apps.iter().enumerate() {
+            if app.appid.trim().is_empty() || app.secret.trim().is_empty() {
+                return Err(format!("wechat_apps[{}] needs an appid and a secret", i));
+            }
+            if app.appid == self.wechat_appid
+                || self.wechat_apps[..i].iter().any(|x| x.appid == app.appid)
+            {
+                return Err(format!("wechat_apps has a duplicate appid {:?}", app.appid));
+            }
+            if app.tenant.as_deref().is_some_and(|x| x.trim().is_empty()) {
+                return Err(format!("wechat_apps[{}] has an empty tenant", i));
+            }
+        }
+        match reqwest::Url::parse(&self.wechat_api) {
+            Ok(url) if matches!(url.scheme(), "http" | "https") => Ok(()),
+            _ => Err(format!(
+                "wechat_api must be an http(s) URL, got {:?}",
+                self.wechat_api
+            )),
+        }
+    }
+
+    /// Every configured app, the primary one first, each with its tenant filled in.
+    fn apps(&self) -> impl Iterator<Item = WeChatApp> + '_ {
+        let primary = WeChatApp {
+            appid: self.wechat_appid.clone(),
+            secret: self.wechat_secret.clone(),
+            kind: WeChatAppKind::MiniProgram,
+            tenant: None,
+            review_template: self.wechat_review_template.clone(),
+        };
+        std::iter::once(primary)
+            .chain(self.wechat_apps.iter().cloned())
+            .map(|mut app| {
+                app.tenant.get_or_insert_with(|| self.wechat_tenant.clone());
+                app
+            })
+    }
+
+    pub fn app_by_id(&self, appid: &str) -> Option<WeChatApp> {
+        self.apps().find(|x| x.appid == appid)
+    }
+
+    /// The apps of `tenant`, the primary one first.
+    pub fn tenant_apps<'a>(&'a self, tenant: &'a str) -> impl Iterator<Item = WeChatApp> + 'a {
+        self.apps()
+            .filter(move |x| x.tenant.as_deref() == Some(tenant))
+    }
+
+    pub fn has_tenant(&self, tenant: &str) -> bool {
+        self.apps().any(|x| x.tenant.as_deref() == Some(tenant))
+    }
+
+    /// The app of `tenant` with `appid`, or the tenant's first mini-program when `appid`
+    /// is `None`.
+    pub fn app(&self, tenant: &str, appid: Option<&str>) -> Option<WeChatApp> {
+        self.tenant_apps(tenant).find(|x| match appid {
+            Some(appid) => x.appid == appid,
+            None => x.kind == WeChatAppKind::MiniProgram,
+        })
+    }
+
+    pub fn token_margin(&self) -> chrono::Duration {
+        chrono::Duration::seconds(self.wechat_token_margin)
+    }
+
+    pub fn endpoint(&self, path: &str) -> String {
+        format!("{}{}", self.wechat_api.trim_end_matches('/'), path)
+    }
+}
+
+const DEFAULT_WECHAT_CONNECT_TIMEOUT_MS: u64 = 2_000;
+const DEFAULT_WECHAT_TIMEOUT_MS: u64 = 5_000;
+const DEFAULT_WECHAT_RETRIES: u32 = 2;
+const DEFAULT_WECHAT_BACKOFF_MS: u64 = 200;
+const DEFAULT_WECHAT_BREAKER_THRESHOLD: u32 = 5;
+const DEFAULT_WECHAT_BREAKER_COOLDOWN: u64 = 30;
+
+#[derive(Clone, Deserialize)]
+pub struct WeChatHttpConfig {
+    /// Time allowed to open a TCP/TLS connection to WeChat, in milliseconds.
+    #[serde(default = "default_wechat_connect_timeout_ms")]
+    pub wechat_connect_timeout_ms: u64,
+    /// Time allowed for a whole WeChat call, in milliseconds.
+    #[serde(default = "default_wechat_timeout_ms")]
+    pub wechat_timeout_ms: u64,
+    /// Extra attempts after a retryable errcode such as `-1`.
+    #[serde(default = "default_wechat_retries")]
+    pub wechat_retries: u32,
+    /// Delay before the first retry in milliseconds; doubled on every further attempt.
+    #[serde(default = "default_wechat_backoff_ms")]
+    pub wechat_backoff_ms: u64,
+    /// Consecutive failed calls after which the circuit opens.
+    #[serde(default = "default_wechat_breaker_threshold")]
+    pub wechat_breaker_threshold: u32,
+    /// Seconds the circuit stays open before a trial call is let through.
+    #[serde(default = "default_wechat_breaker_cooldown")]
+    pub wechat_breaker_cooldown: u64,
+}
+
+fn default_wechat_connect_timeout_ms() -> u64 {
+    DEFAULT_WECHAT_CONNECT_TIMEOUT_MS
+}
+
+fn default_wechat_timeout_ms() -> u64 {
+    DEFAULT_WECHAT_TIMEOUT_MS
+}
+
+fn default_wechat_retries() -> u32 {
+    DEFAULT_WECHAT_RETRIES
+}
+
+fn default_wechat_backoff_ms() -> u64 {
+    DEFAULT_WECHAT_BACKOFF_MS
+}
+
+fn default_wechat_breaker_threshold() -> u32 {
+    DEFAULT_WECHAT_BREAKER_THRESHOLD
+}
+
+fn default_wechat_breaker_cooldown() -> u64 {
+    DEFAULT_WECHAT_BREAKER_COOLDOWN
+}
+
+impl WeChatHttpConfig {
+    pub fn connect_timeout(&self) -> Duration {
+        Duration::from_millis(self.wechat_connect_timeout_ms)
+    }
+
+    pub fn timeout(&self) -> Duration {
+        Duration::from_millis(self.wechat_timeout_ms)
+    }
+
+    /// Delay before retry number `attempt`, counting from 1.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        Duration::from_millis(
+            self.wechat_backoff_ms
+                .saturating_mul(1 << (attempt - 1).min(16)),
+        )
+    }
+
+    pub fn breaker_cooldown(&self) -> Duration {
+        Duration::from_secs(self.wechat_breaker_cooldown)
+    }
+}
+
+const DEFAULT_NOTIFY_INTERVAL: u64 = 30;
+const DEFAULT_NOTIFY_BATCH: u64 = 20;
+const DEFAULT_NOTIFY_MAX_ATTEMPTS: i32 = 6;
+const DEFAULT_NOTIFY_BACKOFF: i64 = 60;
+
+#[derive(Clone, Deserialize)]
+pub struct NotifyConfig {
+    /// Seconds between scans of the notification outbox.
+    #[serde(default = "default_notify_interval")]
+    pub notify_interval: u64,
+    /// Messages sent per scan at most.
+    #[serde(default = "default_notify_batch")]
+    pub notify_batch: u64,
+    /// Sends after which a message that keeps failing is given up.
+    #[serde(default = "default_notify_max_attempts")]
+    pub notify_max_attempts: i32,
+    /// Seconds before the first retry; doubled on every further attempt.
+    #[serde(default = "default_notify_backoff")]
+    pub notify_backoff: i64,
+}
+
+fn default_notify_interval() -> u64 {
+    DEFAULT_NOTIFY_INTERVAL
+}
+
+fn default_notify_batch() -> u64 {
+    DEFAULT_NOTIFY_BATCH
+}
+
+fn default_notify_max_attempts() -> i32 {
+    DEFAULT_NOTIFY_MAX_ATTEMPTS
+}
+
+fn default_notify_backoff() -> i64 {
+    DEFAULT_NOTIFY_BACKOFF
+}
+
+impl NotifyConfig {
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.notify_interval)
+    }
+
+    /// Delay before the send following attempt number `attempt`, counting from 1.
+    pub fn backoff(&self, attempt: i32) -> chrono::Duration {
+        chrono::Duration::seconds(
+            self.notify_backoff
+                .saturating_mul(1 << (attempt - 1).clamp(0, 16)),
+        )
+    }
+}
+
+const DEFAULT_OBSERVATION_DAYS: i64 = 14;
+const DEFAULT_OBSERVATION_INTERVAL: u64 = 5 * 60;
+
+#[derive(Clone, Deserialize)]
+pub struct ObservationConfig {
+    /// Length of the observation period given to approvals that do not set one, counting
+    /// the day of review.
+    #[serde(default = "default_observation_days")]
+    pub observation_days: i64,
+    /// Seconds between scans for observation periods that have ended.
+    #[serde(default = "default_observation_interval")]
+    pub observation_interval: u64,
+}
+
+fn default_observation_days() -> i64 {
+    DEFAULT_OBSERVATION_DAYS
+}
+
+fn default_observation_interval() -> u64 {
+    DEFAULT_OBSERVATION_INTERVAL
+}
+
+impl ObservationConfig {
+    pub fn period(&self) -> chrono::Duration {
+        chrono::Duration::days(self.observation_days)
+    }
+
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.observation_interval)
+    }
+}
+
+const DEFAULT_PASS_TTL: i64 = 5 * 60;
+/// HMAC-SHA256 keys shorter than its output weaken the signature.
+const PASS_KEY_MIN_LEN: usize = 32;
+
+#[derive(Deserialize)]
+pub struct PassConfig {
+    /// Secret passes are signed with; passes cannot be issued while it is unset.
+    pub pass_key: Option<String>,
+    /// Seconds a pass stays valid after it was issued.
+    #[serde(default = "default_pass_ttl")]
+    pub pass_ttl: i64,
+}
+
+fn default_pass_ttl() -> i64 {
+    DEFAULT_PASS_TTL
+}
+
+impl PassConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        match &self.pass_key {
+            Some(key) if key.len() < PASS_KEY_MIN_LEN => Err(format!(
+                "pass_key must be at least {} bytes",
+                PASS_KEY_MIN_LEN
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn ttl(&self) -> chrono::Duration {
+        chrono::Duration::seconds(self.pass_ttl)
+    }
+}
@@ -0,0 +1,313 @@
+use std::io::Cursor;
+
+use rocket::{
+    catch,
+    http::{ContentType, Status},
+    response::{self, Responder},
+    Request, Response,
+};
+use sea_orm::DbErr;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::id_card::IdNoError;
+use crate::pass::PassError;
+use crate::wechat::{crypto::CryptoError, WeChatError};
+
+/// Error body returned by every route: `{code, message, details}`.
+///
+/// `code` is a stable machine-readable identifier the mini-program can branch on;
+/// `message` is for humans and may change.
+#[derive(Debug, Clone, Serialize)]
+pub struct ApiError {
+    #[serde(skip)]
+    pub status: Status,
+    pub code: &'static str,
+    pub message: String,
+    pub details: Option<Value>,
+}
+
+impl ApiError {
+    pub fn new(status: Status, code: &'static str, message: impl Into<String>) -> Self {
+        ApiError {
+            status,
+            code,
+            message: message.into(),
+            details: None,
+        }
+    }
+
+    pub fn with_details(mut self, details: Value) -> Self {
+        self.details = Some(details);
+        self
+    }
+
+    pub fn unauthorized() -> Self {
+        ApiError::new(
+            Status::Unauthorized,
+            "unauthorized",
+            "missing or malformed bearer token",
+        )
+    }
+
+    pub fn token_invalid() -> Self {
+        ApiError::new(
+            Status::Unauthorized,
+            "token_invalid",
+            "token is not recognised",
+        )
+    }
+
+    pub fn token_expired() -> Self {
+        ApiError::new(Status::Unauthorized, "token_expired", "token has expired")
+    }
+
+    pub fn session_revoked() -> Self {
+        ApiError::new(
+            Status::Unauthorized,
+            "session_revoked",
+            "session has been revoked",
+        )
+    }
+
+    pub fn forbidden() -> Self {
+        ApiError::new(
+            Status::Forbidden,
+            "forbidden",
+            "insufficient role for this operation",
+        )
+    }
+
+    pub fn out_of_scope() -> Self {
+        ApiError::new(
+            Status::Forbidden,
+            "region_out_of_scope",
+            "record is outside the regions assigned to you",
+        )
+    }
+
+    pub fn tenant_unknown() -> Self {
+        ApiError::new(
+            Status::BadRequest,
+            "tenant_unknown",
+            "X-Tenant names no configured tenant",
+        )
+    }
+
+    pub fn tenant_mismatch() -> Self {
+        ApiError::new(
+            Status::Forbidden,
+            "tenant_mismatch",
+            "token was issued for another tenant",
+        )
+    }
+
+    pub fn record_changed() -> Self {
+        ApiError::new(
+            Status::Conflict,
+            "record_changed",
+            "record was changed by someone else, reload it and try again",
+        )
+    }
+
+    pub fn not_found(what: &str) -> Self {
+        ApiError::new(Status::NotFound, "not_found", format!("{} not found", what))
+    }
+
+    pub fn validation(field: &str, reason: &str) -> Self {
+        ApiError::new(Status::UnprocessableEntity, "validation_failed", reason)
+            .with_details(json!({ "field": field }))
+    }
+
+    pub fn internal(e: impl std::fmt::Display) -> Self {
+        eprintln!("Error: {}", e);
+        ApiError::new(
+            Status::InternalServerError,
+            "internal_error",
+            "internal server error",
+        )
+    }
+
+    /// Maps the documented `errcode` values of the WeChat server APIs.
+    pub fn wechat(errcode: i32, errmsg: Option<&str>) -> Self {
+        let (status, code, message) = match errcode {
+            -1 => (
+                Status::ServiceUnavailable,
+                "wechat_busy",
+                "WeChat is busy, retry later",
+            ),
+            40029 => (
+                Status::BadRequest,
+                "wechat_code_invalid",
+                "login code is invalid",
+            ),
+            40163 => (
+                Status::BadRequest,
+                "wechat_code_used",
+                "login code has already been used",
+            ),
+            40226 => (
+                Status::Forbidden,
+                "wechat_user_blocked",
+                "WeChat flagged this user as high risk",
+            ),
+            45011 => (
+                Status::TooManyRequests,
+                "wechat_rate_limited",
+                "too many WeChat logins, retry later",
+            ),
+            _ => (
+                Status::NotImplemented,
+                "wechat_error",
+                "unexpected WeChat error",
+            ),
+        };
+
+        ApiError::new(status, code, message)
+            .with_details(json!({ "errcode": errcode, "errmsg": errmsg }))
+    }
+
+    pub fn wechat_unavailable(e: impl std::fmt::Display) -> Self {
+        eprintln!("{}", e);
+        ApiError::new(
+            Status::BadGateway,
+            "wechat_unavailable",
+            "WeChat did not return a usable response",
+        )
+    }
+
+    /// Fallback for statuses raised outside of a handler, e.g. by Rocket itself.
+    pub fn from_status(status: Status) -> Self {
+        let code = match status.code {
+            400 => "bad_request",
+            401 => "unauthorized",
+            403 => "forbidden",
+            404 => "not_found",
+            413 => "payload_too_large",
+            415 => "unsupported_media_type",
+            422 => "validation_failed",
+            500 => "internal_error",
+            _ => "error",
+        };
+
+        ApiError::new(status, code, status.reason_lossy())
+    }
+}
+
+impl From<DbErr> for ApiError {
+    fn from(e: DbErr) -> Self {
+        match e {
+            DbErr::RecordNotFound(what) => ApiError::not_found(&what),
+            DbErr::RecordNotUpdated => ApiError::record_changed(),
+            e => {
+                eprintln!("Error: {}", e);
+                ApiError::new(
+                    Status::InternalServerError,
+                    "database_error",
+                    "database error",
+                )
+            }
+        }
+    }
+}
+
+impl From<IdNoError> for ApiError {
+    fn from(e: IdNoError) -> Self {
+        ApiError::validation("id_no", e.reason())
+    }
+}
+
+impl From<CryptoError> for ApiError {
+    fn from(e: CryptoError) -> Self {
+        let code = match e {
+            CryptoError::Signature => "wechat_signature_invalid",
+            CryptoError::Watermark => "wechat_watermark_invalid",
+            CryptoError::Encoding | CryptoError::Decrypt | CryptoError::Payload => {
+                "wechat_decrypt_failed"
+            }
+        };
+
+        ApiError::new(Status::BadRequest, code, e.reason())
+    }
+}
+
+impl From<PassError> for ApiError {
+    fn from(e: PassError) -> Self {
+        let code = match e {
+            PassError::Malformed | PassError::Signature => "pass_invalid",
+            PassError::Expired => "pass_expired",
+        };
+
+        ApiError::new(Status::UnprocessableEntity, code, e.reason())
+    }
+}
+
+impl From<WeChatError> for ApiError {
+    fn from(e: WeChatError) -> Self {
+        match e {
+            WeChatError::Api { errcode, errmsg } => ApiError::wechat(errcode, errmsg.as_deref()),
+            WeChatError::Transport(e) => ApiError::wechat_unavailable(e),
+            WeChatError::CircuitOpen => ApiError::new(
+                Status::ServiceUnavailable,
+                "wechat_circuit_open",
+                "WeChat is unavailable, retry later",
+            ),
+        }
+    }
+}
+
+impl<'r> Responder<'r, 'static> for ApiError {
+    fn respond_to(self, _: &'r Request<'_>) -> response::Result<'static> {
+        let body = serde_json::to_string(&self).map_err(|_| Status::InternalServerError)?;
+
+        Response::build()
+            .status(self.status)
+            .header(ContentType::JSON)
+            .sized_body(body.len(), Cursor::new(body))
+            .ok()
+    }
+}
+
+/// Request guards stash their error here so the catcher can render it.
+pub struct GuardError(pub Option<ApiError>);
+
+pub fn stash_guard_error(request: &Request<'_>, error: &ApiError) {
+    request.local_cache(|| GuardError(Some(error.clone())));
+}
+
+fn guard_error_or(request: &Request<'_>, status: Status) -> ApiError {
+    match &request.local_cache(|| GuardError(None)).0 {
+        Some(error) if error.status == status => error.clone(),
+        _ => ApiError::from_status(status),
+    }
+}
+
+#[catch(401)]
+pub fn unauthorized(request: &Request) -> ApiError {
+    guard_error_or(request, Status::Unauthorized)
+}
+
+#[catch(403)]
+pub fn forbidden(request: &Request) -> ApiError {
+    guard_error_or(request, Status::Forbidden)
+}
+
+#[catch(404)]
+pub fn not_found(request: &Request) -> ApiError {
+    ApiError::not_found(request.uri().path().as_str())
+}
+
+#[catch(422)]
+pub fn unprocessable_entity(request: &Request) -> ApiError {
+    guard_error_or(request, Status::UnprocessableEntity)
+}
+
+#[catch(500)]
+pub fn internal_error(request: &Request) -> ApiError {
+    guard_error_or(request, Status::InternalServerError)
+}
+
+#[catch(default)]
+pub fn default_catcher(status: Status, request: &Request) -> ApiError {
+    guard_error_or(request, status)
+}
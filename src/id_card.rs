@@ -0,0 +1,171 @@
+//! Resident identity card numbers as specified by GB 11643-1999.
+
+use chrono::{Datelike, NaiveDate};
+
+const WEIGHTS: [u32; 17] = [7, 9, 10, 5, 8, 4, 2, 1, 6, 3, 7, 9, 10, 5, 8, 4, 2];
+const CHECK_DIGITS: [char; 11] = ['1', '0', 'X', '9', '8', '7', '6', '5', '4', '3', '2'];
+
+/// First two digits of every province-level division, including HK/MO/TW.
+const PROVINCES: [&str; 34] = [
+    "11", "12", "13", "14", "15", "21", "22", "23", "31", "32", "33", "34", "35", "36", "37", "41",
+    "42", "43", "44", "45", "46", "50", "51", "52", "53", "54", "61", "62", "63", "64", "65", "71",
+    "81", "82",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Gender {
+    Male,
+    Female,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResidentId {
+    /// Always the 18-digit form; legacy 15-digit numbers are upgraded.
+    pub id_no: String,
+    pub region_code: String,
+    pub birth_date: NaiveDate,
+    pub gender: Gender,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdNoError {
+    Length,
+    Format,
+    Region,
+    BirthDate,
+    CheckDigit,
+}
+
+impl IdNoError {
+    pub fn reason(&self) -> &'static str {
+        match self {
+            IdNoError::Length => "id_no must have 15 or 18 characters",
+            IdNoError::Format => "id_no must be digits, with an optional trailing X",
+            IdNoError::Region => "id_no has an unknown region code",
+            IdNoError::BirthDate => "id_no has an invalid birth date",
+            IdNoError::CheckDigit => "id_no check digit does not match",
+        }
+    }
+}
+
+/// ISO 7064 MOD 11-2 check digit over the first 17 digits.
+pub fn check_digit(body: &str) -> Option<char> {
+    if body.len() != 17 || !body.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    let sum: u32 = body
+        .bytes()
+        .zip(WEIGHTS)
+        .map(|(b, w)| (b - b'0') as u32 * w)
+        .sum();
+
+    Some(CHECK_DIGITS[(sum % 11) as usize])
+}
+
+pub fn parse(id_no: &str, today: NaiveDate) -> Result<ResidentId, IdNoError> {
+    let id_no = id_no.trim().to_ascii_uppercase();
+    if !id_no.is_ascii() {
+        return Err(IdNoError::Format);
+    }
+
+    let id_no = match id_no.len() {
+        18 => {
+            let (body, check) = id_no.split_at(17);
+            if !body.bytes().all(|b| b.is_ascii_digit())
+                || !check.bytes().all(|b| b.is_ascii_digit() || b == b'X')
+            {
+                return Err(IdNoError::Format);
+            }
+            if check_digit(body) != check.chars().next() {
+                return Err(IdNoError::CheckDigit);
+            }
+            id_no
+        }
+        15 => {
+            if !id_no.bytes().all(|b| b.is_ascii_digit()) {
+                return Err(IdNoError::Format);
+            }
+            // Legacy numbers carry a two-digit year from the 1900s and no check digit.
+            let body = format!("{}19{}", &id_no[..6], &id_no[6..]);
+            let check = check_digit(&body).ok_or(IdNoError::Format)?;
+            format!("{}{}", body, check)
+        }
+        _ => return Err(IdNoError::Length),
+    };
+
+    let region_code = id_no[..6].to_owned();
+    if !PROVINCES.contains(&&region_code[..2]) {
+        return Err(IdNoError::Region);
+    }
+
+    let birth_date =
+        NaiveDate::parse_from_str(&id_no[6..14], "%Y%m%d").map_err(|_| IdNoError::BirthDate)?;
+    if birth_date.year() < 1900 || birth_date > today {
+        return Err(IdNoError::BirthDate);
+    }
+
+    let gender = if (id_no.as_bytes()[16] - b'0') % 2 == 1 {
+        Gender::Male
+    } else {
+        Gender::Female
+    };
+
+    Ok(ResidentId {
+        id_no,
+        region_code,
+        birth_date,
+        gender,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::{check_digit, parse, Gender, IdNoError};
+
+    fn today() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 3, 1).unwrap()
+    }
+
+    #[test]
+    fn computes_check_digit() {
+        assert_eq!(check_digit("11010519491231002"), Some('X'));
+        assert_eq!(check_digit("44030819900307001"), Some('3'));
+    }
+
+    #[test]
+    fn parses_valid_id() {
+        let id = parse("11010519491231002x", today()).unwrap();
+        assert_eq!(id.id_no, "11010519491231002X");
+        assert_eq!(id.region_code, "110105");
+        assert_eq!(
+            id.birth_date,
+            NaiveDate::from_ymd_opt(1949, 12, 31).unwrap()
+        );
+        assert_eq!(id.gender, Gender::Female);
+    }
+
+    #[test]
+    fn upgrades_legacy_id() {
+        let id = parse("440308900307001", today()).unwrap();
+        assert_eq!(id.id_no, "440308199003070013");
+        assert_eq!(id.gender, Gender::Male);
+    }
+
+    #[test]
+    fn rejects_invalid_ids() {
+        assert_eq!(
+            parse("110105194912310021", today()),
+            Err(IdNoError::CheckDigit)
+        );
+        assert_eq!(parse("1101051949123100", today()), Err(IdNoError::Length));
+        assert_eq!(parse("11010519491231002Y", today()), Err(IdNoError::Format));
+        assert_eq!(parse("990105194912310023", today()), Err(IdNoError::Region));
+        assert_eq!(
+            parse("110105194902300020", today()),
+            Err(IdNoError::BirthDate)
+        );
+    }
+}
@@ -1,24 +1,135 @@
 mod api;
+mod config;
+mod error;
+mod id_card;
+mod notify;
+mod observation;
 mod orm;
+mod pass;
+mod storage;
+mod wechat;
 
+use std::sync::Arc;
+
+use anyhow::Context;
 use api::{
+    account::{merge_duplicate_account, query_duplicate_accounts},
+    audit::query_audit_log,
+    check_in::{add_check_in, query_check_in, query_missed_check_in},
+    image::{download_image, upload_image},
+    pass::{issue_pass, verify_pass},
+    phone::bind_phone,
+    region::{assign_subadmin_region, query_subadmin_regions, revoke_subadmin_region},
+    review::{query_observation_transitions, query_pending_user_info, review_user_info},
+    security::{accept_push, media_for_check, verify_push_url},
+    session::{
+        logout, query_login_history, query_sessions, refresh_token, revoke_session,
+        revoke_user_sessions,
+    },
+    subscribe::authorize_subscribe_message,
+    travel::{
+        add_travel_record, delete_travel_record, query_arriving_travel_record, query_travel_record,
+        set_travel_record,
+    },
     user_info::{add_user_info, delete_user_info, query_user_info, set_user_info},
+    wechat_data::{accept_profile, accept_share_ticket},
     wechat_login::wechat_login_service,
+    RequestId,
+};
+use config::{
+    ImageConfig, NotifyConfig, ObservationConfig, PassConfig, SessionConfig, WeChatConfig,
+    WeChatHttpConfig,
+};
+use error::{
+    default_catcher, forbidden, internal_error, not_found, unauthorized, unprocessable_entity,
+};
+use notify::Outbox;
+use rocket::{catchers, fairing::AdHoc, routes};
+use storage::{ImageStorage, LocalStorage};
+use wechat::{
+    http_client, AccessTokenProvider, DbAccessTokenManager, HttpWeChatClient, WeChatClient,
 };
-use rocket::routes;
-
-const APPID: &str = "your_appid";
-const SECRET: &str = "your_secret";
 
 #[rocket::main]
 async fn main() -> anyhow::Result<()> {
-    let mut app = rocket::build();
+    let figment = rocket::Config::figment();
+    let image_config = figment.extract::<ImageConfig>()?;
+    let wechat_config = figment.extract::<WeChatConfig>().context(
+        "WeChat credentials are not configured; set wechat_appid and wechat_secret \
+         in Rocket.toml or ROCKET_WECHAT_APPID / ROCKET_WECHAT_SECRET",
+    )?;
+    wechat_config
+        .validate()
+        .map_err(|e| anyhow::anyhow!("invalid WeChat configuration: {}", e))?;
+    let wechat_http_config = figment.extract::<WeChatHttpConfig>()?;
+    let notify_config = figment.extract::<NotifyConfig>()?;
+    let observation_config = figment.extract::<ObservationConfig>()?;
+    let pass_config = figment.extract::<PassConfig>()?;
+    pass_config
+        .validate()
+        .map_err(|e| anyhow::anyhow!("invalid pass configuration: {}", e))?;
+    let figment = figment
+        .merge(("limits.file", image_config.file_limit()))
+        .merge(("limits.data-form", image_config.form_limit()));
+
+    let mut app = rocket::custom(figment);
 
     let db = orm::establish_connection().await?;
 
     app = app.manage(db);
+    app = app.attach(AdHoc::config::<SessionConfig>());
+    app = app.attach(RequestId::fairing());
+
+    let storage: Box<dyn ImageStorage> = Box::new(LocalStorage::new(&image_config.image_dir));
+    app = app.manage(storage);
+    app = app.manage(image_config);
+    let client = http_client(&wechat_http_config)?;
+    let wechat: Arc<dyn WeChatClient> = Arc::new(HttpWeChatClient::new(
+        client.clone(),
+        wechat_config.clone(),
+        wechat_http_config,
+    ));
+    app = app.manage(client);
+    let access_token: Arc<dyn AccessTokenProvider> = Arc::new(DbAccessTokenManager::new(
+        wechat.clone(),
+        wechat_config.token_margin(),
+    ));
+
+    // The outbox runs on its own pool so it never competes with requests for connections.
+    let outbox = Outbox {
+        wechat: wechat.clone(),
+        access_token: access_token.clone(),
+        wechat_config: wechat_config.clone(),
+        config: notify_config,
+    };
+    rocket::tokio::spawn(notify::run_outbox(
+        orm::establish_connection().await?,
+        outbox,
+    ));
+
+    rocket::tokio::spawn(observation::run_scheduler(
+        orm::establish_connection().await?,
+        observation_config.clone(),
+    ));
+    app = app.manage(observation_config);
+    app = app.manage(pass_config);
+
+    app = app.manage(wechat);
+    app = app.manage(access_token);
+    app = app.manage(wechat_config);
 
-    app = app.mount("/", routes![wechat_login_service]);
+    app = app.mount(
+        "/",
+        routes![
+            wechat_login_service,
+            refresh_token,
+            logout,
+            revoke_user_sessions,
+            query_sessions,
+            revoke_session,
+            query_login_history
+        ],
+    );
     app = app.mount(
         "/",
         routes![
@@ -28,6 +139,56 @@ async fn main() -> anyhow::Result<()> {
             set_user_info
         ],
     );
+    app = app.mount(
+        "/",
+        routes![
+            query_travel_record,
+            add_travel_record,
+            delete_travel_record,
+            set_travel_record
+        ],
+    );
+    app = app.mount(
+        "/",
+        routes![
+            query_pending_user_info,
+            review_user_info,
+            query_observation_transitions
+        ],
+    );
+    app = app.mount("/", routes![query_arriving_travel_record]);
+    app = app.mount("/", routes![query_check_in, add_check_in]);
+    app = app.mount("/", routes![query_missed_check_in]);
+    app = app.mount("/", routes![query_audit_log]);
+    app = app.mount("/", routes![upload_image, download_image]);
+    app = app.mount("/", routes![bind_phone]);
+    app = app.mount("/", routes![issue_pass, verify_pass]);
+    app = app.mount("/", routes![authorize_subscribe_message]);
+    app = app.mount("/", routes![media_for_check, verify_push_url, accept_push]);
+    app = app.mount("/", routes![accept_profile, accept_share_ticket]);
+    app = app.mount(
+        "/",
+        routes![query_duplicate_accounts, merge_duplicate_account],
+    );
+    app = app.mount(
+        "/",
+        routes![
+            query_subadmin_regions,
+            assign_subadmin_region,
+            revoke_subadmin_region
+        ],
+    );
+    app = app.register(
+        "/",
+        catchers![
+            unauthorized,
+            forbidden,
+            not_found,
+            unprocessable_entity,
+            internal_error,
+            default_catcher
+        ],
+    );
 
     app.launch().await?;
 
@@ -0,0 +1,428 @@
+//! Subscribe messages telling reporters how their records were reviewed.
+//!
+//! Messages are written to `notification_outbox` in the transaction that changes the
+//! record, then sent by [`run_outbox`], which retries sends that failed because WeChat
+//! was unreachable or busy.
+
+use std::sync::Arc;
+
+use sea_orm::{
+    sea_query::Expr, ColumnTrait, ConnectionTrait, DatabaseConnection, DbErr, EntityTrait,
+    QueryFilter, QueryOrder, QuerySelect, Set,
+};
+use serde_json::{json, Map, Value};
+
+use crate::api::user_info::validated_name;
+use crate::config::{NotifyConfig, ReviewField, SubscribeTemplate, WeChatConfig};
+use crate::orm::entities::{
+    notification_outbox, prelude::*, sea_orm_active_enums::Validated, subscribe_authorization,
+    user_info, we_chat_identity,
+};
+use crate::wechat::{with_access_token, AccessTokenProvider, SubscribeMessage, WeChatClient};
+
+/// WeChat rejects `thing` keywords longer than this.
+const THING_MAX_CHARS: usize = 20;
+const LAST_ERROR_MAX_CHARS: usize = 256;
+
+fn field_value(template: &SubscribeTemplate, field: ReviewField, x: &user_info::Model) -> String {
+    match field {
+        ReviewField::Name => x.name.clone(),
+        ReviewField::Decision => {
+            let decision = validated_name(&x.validated);
+            template.labels.get(&decision).cloned().unwrap_or(decision)
+        }
+        ReviewField::Reason => x.review_reason.clone().unwrap_or_default(),
+        ReviewField::ReviewedAt => x
+            .reviewed_at
+            .map(|at| at.format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_default(),
+    }
+}
+
+/// The template `data` announcing the review of `x`.
+pub fn review_data(template: &SubscribeTemplate, x: &user_info::Model) -> Value {
+    let data = template
+        .data
+        .iter()
+        .map(|(keyword, &field)| {
+            let mut value = field_value(template, field, x);
+            if keyword.starts_with("thing") {
+                value = value.chars().take(THING_MAX_CHARS).collect();
+            }
+            (keyword.clone(), json!({ "value": value }))
+        })
+        .collect::<Map<_, _>>();
+
+    Value::Object(data)
+}
+
+/// Queues the message announcing that `after` was reviewed, through whichever app of the
+/// tenant the creator still has an authorization for its review template left in.
+pub async fn enqueue_review<C: ConnectionTrait>(
+    db: &C,
+    config: &WeChatConfig,
+    after: &user_info::Model,
+) -> Result<(), DbErr> {
+    if !matches!(after.validated, Validated::Pass | Validated::Blocked) {
+        return Ok(());
+    }
+
+    for app in config.tenant_apps(&after.tenant) {
+        let template = match &app.review_template {
+            Some(template) => template,
+            None => continue,
+        };
+
+        let identity = WeChatIdentity::find()
+            .filter(we_chat_identity::Column::UserId.eq(after.creator))
+            .filter(we_chat_identity::Column::Appid.eq(app.appid.clone()))
+            .one(db)
+            .await?;
+        let openid = match identity {
+            Some(identity) => identity.openid,
+            None => continue,
+        };
+
+        // Each accepted request allows exactly one message.
+        let authorized = SubscribeAuthorization::update_many()
+            .col_expr(
+                subscribe_authorization::Column::Remaining,
+                Expr::col(subscribe_authorization::Column::Remaining).sub(1),
+            )
+            .filter(subscribe_authorization::Column::UserId.eq(after.creator))
+            .filter(subscribe_authorization::Column::TemplateId.eq(template.template_id.clone()))
+            .filter(subscribe_authorization::Column::Remaining.gt(0))
+            .exec(db)
+            .await?;
+        if authorized.rows_affected == 0 {
+            continue;
+        }
+
+        let message = notification_outbox::ActiveModel {
+            user_id: Set(after.creator),
+            appid: Set(app.appid.clone()),
+            openid: Set(openid),
+            template_id: Set(template.template_id.clone()),
+            page: Set(template.page.clone()),
+            data: Set(review_data(template, after)),
+            ..Default::default()
+        };
+        NotificationOutbox::insert(message)
+            .exec_without_returning(db)
+            .await?;
+
+        return Ok(());
+    }
+
+    Ok(())
+}
+
+/// Everything the outbox needs to send messages.
+pub struct Outbox {
+    pub wechat: Arc<dyn WeChatClient>,
+    pub access_token: Arc<dyn AccessTokenProvider>,
+    pub wechat_config: WeChatConfig,
+    pub config: NotifyConfig,
+}
+
+impl Outbox {
+    /// Sends one message, or gives it up once WeChat rejected it or the attempts ran out.
+    async fn deliver(
+        &self,
+        db: &DatabaseConnection,
+        message: notification_outbox::Model,
+    ) -> Result<bool, DbErr> {
+        let now = chrono::Local::now().naive_local();
+        let attempt = message.attempts + 1;
+
+        // Claimed rows move out of reach of other instances until the backoff passes.
+        let claimed = NotificationOutbox::update_many()
+            .col_expr(notification_outbox::Column::Attempts, Expr::value(attempt))
+            .col_expr(
+                notification_outbox::Column::NextAttemptAt,
+                Expr::value(now + self.config.backoff(attempt)),
+            )
+            .filter(notification_outbox::Column::Id.eq(message.id))
+            .filter(notification_outbox::Column::Attempts.eq(message.attempts))
+            .exec(db)
+            .await?;
+        if claimed.rows_affected == 0 {
+            return Ok(false);
+        }
+
+        let app = match self.wechat_config.app_by_id(&message.appid) {
+            Some(app) => app,
+            None => {
+                self.finish(db, message.id, None, Some("app is no longer configured"))
+                    .await?;
+                return Ok(false);
+            }
+        };
+
+        let body = SubscribeMessage {
+            touser: message.openid,
+            template_id: message.template_id,
+            page: message.page,
+            data: message.data,
+        };
+        let wechat = self.wechat.as_ref();
+        let sent = with_access_token(self.access_token.as_ref(), db, &app, |token| {
+            let body = &body;
+            async move {
+                // Requests WeChat rejects outright are not retried.
+                match wechat.send_subscribe_message(&token, body).await {
+                    Err(e) if !e.is_stale_token() && !e.is_outage() => Ok(Err(e)),
+                    result => result.map(Ok),
+                }
+            }
+        })
+        .await;
+
+        match sent {
+            Ok(Ok(())) => {
+                self.finish(db, message.id, Some(now), None).await?;
+                Ok(true)
+            }
+            Ok(Err(e)) => {
+                self.finish(db, message.id, None, Some(&e.to_string()))
+                    .await?;
+                Ok(false)
+            }
+            Err(e) if attempt >= self.config.notify_max_attempts => {
+                self.finish(db, message.id, None, Some(&e.message)).await?;
+                Ok(false)
+            }
+            Err(e) => {
+                NotificationOutbox::update_many()
+                    .col_expr(
+                        notification_outbox::Column::LastError,
+                        Expr::value(truncate_error(&e.message)),
+                    )
+                    .filter(notification_outbox::Column::Id.eq(message.id))
+                    .exec(db)
+                    .await?;
+                Ok(false)
+            }
+        }
+    }
+
+    /// Takes the message out of the queue, as sent or given up.
+    async fn finish(
+        &self,
+        db: &DatabaseConnection,
+        id: i64,
+        sent_at: Option<chrono::NaiveDateTime>,
+        error: Option<&str>,
+    ) -> Result<(), DbErr> {
+        NotificationOutbox::update_many()
+            .col_expr(
+                notification_outbox::Column::NextAttemptAt,
+                Expr::value(Option::<chrono::NaiveDateTime>::None),
+            )
+            .col_expr(notification_outbox::Column::SentAt, Expr::value(sent_at))
+            .col_expr(
+                notification_outbox::Column::LastError,
+                Expr::value(error.map(truncate_error)),
+            )
+            .filter(notification_outbox::Column::Id.eq(id))
+            .exec(db)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Sends the messages that are due and returns how many went out.
+    pub async fn deliver_due(&self, db: &DatabaseConnection) -> Result<usize, DbErr> {
+        let now = chrono::Local::now().naive_local();
+        let due = NotificationOutbox::find()
+            .filter(notification_outbox::Column::NextAttemptAt.lte(now))
+            .order_by_asc(notification_outbox::Column::NextAttemptAt)
+            .limit(self.config.notify_batch)
+            .all(db)
+            .await?;
+
+        let mut sent = 0;
+        for message in due {
+            if self.deliver(db, message).await? {
+                sent += 1;
+            }
+        }
+
+        Ok(sent)
+    }
+}
+
+fn truncate_error(error: &str) -> String {
+    error.chars().take(LAST_ERROR_MAX_CHARS).collect()
+}
+
+/// Drains the outbox every `notify_interval` for as long as the server runs.
+pub async fn run_outbox(db: DatabaseConnection, outbox: Outbox) {
+    let mut interval = rocket::tokio::time::interval(outbox.config.interval());
+    loop {
+        interval.tick().await;
+        if let Err(e) = outbox.deliver_due(&db).await {
+            eprintln!("Notification outbox: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+
+    use sea_orm::{DatabaseBackend, MockDatabase, MockExecResult};
+    use serde_json::json;
+    use uuid::Uuid;
+
+    use super::{review_data, Outbox};
+    use crate::config::{NotifyConfig, ReviewField, SubscribeTemplate, WeChatConfig};
+    use crate::orm::entities::{
+        notification_outbox, sea_orm_active_enums::Validated, user_info, we_chat_access_token,
+    };
+    use crate::wechat::{fake::FakeWeChatClient, DbAccessTokenManager};
+
+    fn template() -> SubscribeTemplate {
+        SubscribeTemplate {
+            template_id: "review-template".to_owned(),
+            page: Some("pages/records/index".to_owned()),
+            data: BTreeMap::from([
+                ("thing1".to_owned(), ReviewField::Name),
+                ("phrase2".to_owned(), ReviewField::Decision),
+                ("thing3".to_owned(), ReviewField::Reason),
+                ("time4".to_owned(), ReviewField::ReviewedAt),
+            ]),
+            labels: BTreeMap::from([("blocked".to_owned(), "未通过".to_owned())]),
+        }
+    }
+
+    #[test]
+    fn renders_review_keywords() {
+        let reviewed_at = chrono::NaiveDate::from_ymd_opt(2024, 4, 15)
+            .unwrap()
+            .and_hms_opt(9, 30, 0)
+            .unwrap();
+        let record = user_info::Model {
+            id: Uuid::new_v4(),
+            creator: Uuid::new_v4(),
+            id_no: "11010519491231002X".to_owned(),
+            name: "张三".to_owned(),
+            phone: "13800138000".to_owned(),
+            address: "北京市".to_owned(),
+            image: None,
+            validated: Validated::Blocked,
+            created_at: reviewed_at,
+            review_reason: Some("地址信息不完整，请补充到门牌号并重新提交审核".to_owned()),
+            reviewer: None,
+            reviewed_at: Some(reviewed_at),
+            revision: 0,
+            birth_date: None,
+            gender: None,
+            region_code: None,
+            phone_verified: false,
+            tenant: "default".to_owned(),
+            observation_type: None,
+            observation_start: None,
+            observation_end: None,
+            observation_status: None,
+            address_region: None,
+        };
+
+        assert_eq!(
+            review_data(&template(), &record),
+            json!({
+                "thing1": { "value": "张三" },
+                "phrase2": { "value": "未通过" },
+                "thing3": { "value": "地址信息不完整，请补充到门牌号并重新提交" },
+                "time4": { "value": "2024-04-15 09:30" },
+            })
+        );
+    }
+
+    fn queued(id: i64) -> notification_outbox::Model {
+        let now = chrono::Local::now().naive_local();
+        notification_outbox::Model {
+            id,
+            user_id: Uuid::new_v4(),
+            appid: "appid".to_owned(),
+            openid: format!("openid-{}", id),
+            template_id: "review-template".to_owned(),
+            page: None,
+            data: json!({}),
+            attempts: 0,
+            next_attempt_at: Some(now),
+            sent_at: None,
+            last_error: None,
+            created_at: now,
+        }
+    }
+
+    fn executed() -> MockExecResult {
+        MockExecResult {
+            last_insert_id: 0,
+            rows_affected: 1,
+        }
+    }
+
+    #[rocket::async_test]
+    async fn retries_busy_sends_and_drops_rejected_ones() {
+        let now = chrono::Local::now().naive_local();
+        let token = we_chat_access_token::Model {
+            appid: "appid".to_owned(),
+            access_token: "token".to_owned(),
+            expires_at: now + chrono::Duration::hours(1),
+            updated_at: now,
+        };
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([vec![queued(1), queued(2), queued(3)]])
+            .append_query_results([vec![token]])
+            // Claim and outcome of each message in turn.
+            .append_exec_results([executed(), executed()])
+            .append_exec_results([executed(), executed()])
+            .append_exec_results([executed(), executed()])
+            .into_connection();
+        let wechat = Arc::new(
+            FakeWeChatClient::default()
+                .with_send_errcode(-1)
+                .with_send_errcode(43101),
+        );
+        let wechat_config = WeChatConfig {
+            wechat_appid: "appid".to_owned(),
+            wechat_secret: "secret".to_owned(),
+            wechat_api: String::new(),
+            wechat_token_margin: 300,
+            wechat_watermark_max_age: 600,
+            wechat_tenant: "default".to_owned(),
+            wechat_apps: Vec::new(),
+            wechat_review_template: Some(template()),
+            wechat_push_token: None,
+            wechat_media_base_url: None,
+        };
+        let outbox = Outbox {
+            wechat: wechat.clone(),
+            access_token: Arc::new(DbAccessTokenManager::new(
+                wechat.clone(),
+                chrono::Duration::seconds(300),
+            )),
+            wechat_config,
+            config: NotifyConfig {
+                notify_interval: 30,
+                notify_batch: 20,
+                notify_max_attempts: 6,
+                notify_backoff: 60,
+            },
+        };
+
+        assert_eq!(outbox.deliver_due(&db).await.unwrap(), 1);
+
+        let sent = wechat.sent_messages();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].touser, "openid-3");
+
+        let log = db.into_transaction_log();
+        // The busy send keeps its retry time; the rejected one leaves the queue.
+        assert!(!format!("{:?}", log[3]).contains("next_attempt_at"));
+        assert!(format!("{:?}", log[5]).contains("next_attempt_at"));
+    }
+}
@@ -0,0 +1,311 @@
+//! Observation periods reviewers assign when approving a record.
+//!
+//! A record is `observing` from approval until the last day of its period, after which
+//! [`run_scheduler`] releases it. A record that is blocked before then has its observation
+//! `cancelled`; edits by the owner leave it running while the record waits to be reviewed
+//! again. Every status change, and every new period, is written to
+//! `observation_transition` together with whoever made it.
+
+use chrono::NaiveDate;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, DatabaseConnection, DbErr, EntityTrait,
+    QueryFilter, Set, TransactionTrait,
+};
+use uuid::Uuid;
+
+use crate::api::security::SYSTEM_ACTOR;
+use crate::config::ObservationConfig;
+use crate::orm::entities::{
+    observation_transition, prelude::*, sea_orm_active_enums::ObservationStatus, user_info,
+};
+
+/// Whether `x` has to check in on `today`.
+pub fn under_observation(x: &user_info::Model, today: NaiveDate) -> bool {
+    match (
+        &x.observation_status,
+        x.observation_start,
+        x.observation_end,
+    ) {
+        (Some(ObservationStatus::Observing), Some(start), Some(end)) => {
+            start <= today && today <= end
+        }
+        _ => false,
+    }
+}
+
+/// Days of observation left from `today` on, counting both `today` and the last day.
+pub fn remaining_days(x: &user_info::Model, today: NaiveDate) -> Option<i64> {
+    match (
+        &x.observation_status,
+        x.observation_start,
+        x.observation_end,
+    ) {
+        (Some(ObservationStatus::Observing), Some(start), Some(end)) => {
+            Some(((end - today.max(start)).num_days() + 1).max(0))
+        }
+        (Some(ObservationStatus::Released | ObservationStatus::Cancelled), _, _) => Some(0),
+        _ => None,
+    }
+}
+
+/// Cancels the observation of `before`, if it is running, as the record stops being approved.
+pub fn cancel(before: &user_info::Model, user_info: &mut user_info::ActiveModel) {
+    if before.observation_status == Some(ObservationStatus::Observing) {
+        user_info.observation_status = Set(Some(ObservationStatus::Cancelled));
+    }
+}
+
+/// Records the observation change from `before` to `after`: a new status or a new period.
+pub async fn record_change<C: ConnectionTrait>(
+    db: &C,
+    before: &user_info::Model,
+    after: &user_info::Model,
+    actor: Uuid,
+) -> Result<(), DbErr> {
+    let period = |x: &user_info::Model| {
+        (
+            x.observation_type.clone(),
+            x.observation_start,
+            x.observation_end,
+        )
+    };
+    if after.observation_status == before.observation_status && period(after) == period(before) {
+        return Ok(());
+    }
+
+    match after.observation_status.clone() {
+        Some(status) => {
+            record_transition(
+                db,
+                after.id,
+                before.observation_status.clone(),
+                status,
+                actor,
+            )
+            .await
+        }
+        None => Ok(()),
+    }
+}
+
+pub async fn record_transition<C: ConnectionTrait>(
+    db: &C,
+    user_info_id: Uuid,
+    from_status: Option<ObservationStatus>,
+    to_status: ObservationStatus,
+    actor: Uuid,
+) -> Result<(), DbErr> {
+    let transition = observation_transition::ActiveModel {
+        user_info_id: Set(user_info_id),
+        from_status: Set(from_status),
+        to_status: Set(to_status),
+        actor: Set(actor),
+        ..Default::default()
+    };
+    transition.insert(db).await?;
+
+    Ok(())
+}
+
+/// Releases every record whose period ended before `today`, returning how many were.
+pub async fn release_due(db: &DatabaseConnection, today: NaiveDate) -> Result<usize, DbErr> {
+    let due = UserInfo::find()
+        .filter(user_info::Column::ObservationStatus.eq(ObservationStatus::Observing))
+        .filter(user_info::Column::ObservationEnd.lt(today))
+        .all(db)
+        .await?;
+
+    let mut released = 0;
+    for record in due {
+        let txn = db.begin().await?;
+
+        // Guarded by the status, so a reviewer's concurrent change or another instance wins.
+        let result = UserInfo::update_many()
+            .set(user_info::ActiveModel {
+                observation_status: Set(Some(ObservationStatus::Released)),
+                ..Default::default()
+            })
+            .filter(user_info::Column::Id.eq(record.id))
+            .filter(user_info::Column::ObservationStatus.eq(ObservationStatus::Observing))
+            .filter(user_info::Column::ObservationEnd.lt(today))
+            .exec(&txn)
+            .await?;
+        if result.rows_affected == 0 {
+            continue;
+        }
+
+        record_transition(
+            &txn,
+            record.id,
+            Some(ObservationStatus::Observing),
+            ObservationStatus::Released,
+            SYSTEM_ACTOR,
+        )
+        .await?;
+
+        txn.commit().await?;
+        released += 1;
+    }
+
+    Ok(released)
+}
+
+pub async fn run_scheduler(db: DatabaseConnection, config: ObservationConfig) {
+    let mut interval = rocket::tokio::time::interval(config.interval());
+    loop {
+        interval.tick().await;
+        if let Err(e) = release_due(&db, chrono::Local::now().date_naive()).await {
+            eprintln!("Observation scheduler: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use sea_orm::{DatabaseBackend, IntoActiveModel, MockDatabase, MockExecResult};
+    use uuid::Uuid;
+
+    use super::{
+        cancel, record_change, release_due, remaining_days, under_observation, SYSTEM_ACTOR,
+    };
+    use crate::orm::entities::{
+        observation_transition,
+        sea_orm_active_enums::{ObservationStatus, ObservationType, Validated},
+        user_info,
+    };
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 5, day).unwrap()
+    }
+
+    fn observed(start: u32, end: u32) -> user_info::Model {
+        let now = chrono::Local::now().naive_local();
+        user_info::Model {
+            id: Uuid::new_v4(),
+            creator: Uuid::new_v4(),
+            id_no: "11010519491231002X".to_owned(),
+            name: "name".to_owned(),
+            phone: "13800000000".to_owned(),
+            address: "address".to_owned(),
+            image: None,
+            validated: Validated::Pass,
+            created_at: now,
+            review_reason: None,
+            reviewer: None,
+            reviewed_at: Some(now),
+            revision: 0,
+            birth_date: None,
+            gender: None,
+            region_code: None,
+            phone_verified: false,
+            tenant: "default".to_owned(),
+            observation_type: Some(ObservationType::Home),
+            observation_start: Some(date(start)),
+            observation_end: Some(date(end)),
+            observation_status: Some(ObservationStatus::Observing),
+            address_region: None,
+        }
+    }
+
+    #[test]
+    fn observes_from_start_to_end_day() {
+        let record = observed(3, 16);
+
+        assert!(!under_observation(&record, date(2)));
+        assert!(under_observation(&record, date(3)));
+        assert!(under_observation(&record, date(16)));
+        assert!(!under_observation(&record, date(17)));
+    }
+
+    #[test]
+    fn counts_remaining_days_inclusively() {
+        let record = observed(3, 16);
+
+        assert_eq!(remaining_days(&record, date(1)), Some(14));
+        assert_eq!(remaining_days(&record, date(3)), Some(14));
+        assert_eq!(remaining_days(&record, date(16)), Some(1));
+        assert_eq!(remaining_days(&record, date(17)), Some(0));
+    }
+
+    #[rocket::async_test]
+    async fn cancels_and_records_observation_changes() {
+        let before = observed(3, 16);
+        let mut record = before.clone().into_active_model();
+        cancel(&before, &mut record);
+        let cancelled = user_info::Model {
+            observation_status: Some(ObservationStatus::Cancelled),
+            ..before.clone()
+        };
+        assert_eq!(
+            record.observation_status.as_ref(),
+            &cancelled.observation_status
+        );
+        assert!(!under_observation(&cancelled, date(4)));
+        assert_eq!(remaining_days(&cancelled, date(4)), Some(0));
+
+        let extended = user_info::Model {
+            observation_end: Some(date(20)),
+            ..before.clone()
+        };
+        let transition = observation_transition::Model {
+            id: 1,
+            user_info_id: before.id,
+            from_status: Some(ObservationStatus::Observing),
+            to_status: ObservationStatus::Cancelled,
+            actor: SYSTEM_ACTOR,
+            created_at: chrono::Local::now().naive_local(),
+        };
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([vec![transition.clone()], vec![transition]])
+            .into_connection();
+
+        record_change(&db, &before, &before, SYSTEM_ACTOR)
+            .await
+            .unwrap();
+        record_change(&db, &before, &cancelled, SYSTEM_ACTOR)
+            .await
+            .unwrap();
+        record_change(&db, &before, &extended, SYSTEM_ACTOR)
+            .await
+            .unwrap();
+
+        let log = db.into_transaction_log();
+        assert_eq!(log.len(), 2);
+        assert!(format!("{:?}", log[0]).contains("cancelled"));
+    }
+
+    #[rocket::async_test]
+    async fn releases_ended_periods_once() {
+        let first = observed(1, 14);
+        let second = observed(1, 14);
+        let transition = observation_transition::Model {
+            id: 1,
+            user_info_id: first.id,
+            from_status: Some(ObservationStatus::Observing),
+            to_status: ObservationStatus::Released,
+            actor: SYSTEM_ACTOR,
+            created_at: chrono::Local::now().naive_local(),
+        };
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([vec![first, second]])
+            .append_exec_results([
+                MockExecResult {
+                    last_insert_id: 0,
+                    rows_affected: 1,
+                },
+                // The second record was changed in the meantime.
+                MockExecResult {
+                    last_insert_id: 0,
+                    rows_affected: 0,
+                },
+            ])
+            .append_query_results([vec![transition]])
+            .into_connection();
+
+        assert_eq!(release_due(&db, date(15)).await.unwrap(), 1);
+
+        let log = db.into_transaction_log();
+        assert!(format!("{:?}", log[1]).contains("released"));
+    }
+}
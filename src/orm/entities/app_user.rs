@@ -11,22 +11,91 @@ pub struct Model {
     #[sea_orm(unique)]
     pub wechat_id: String,
     pub user_role: UserRole,
+    pub phone: Option<String>,
+    pub phone_verified_at: Option<DateTime>,
+    pub unionid: Option<String>,
+    pub merged_into: Option<Uuid>,
+    pub tenant: String,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
+    #[sea_orm(
+        belongs_to = "Entity",
+        from = "Column::MergedInto",
+        to = "Column::Id",
+        on_update = "NoAction",
+        on_delete = "NoAction"
+    )]
+    SelfRef,
+    #[sea_orm(has_many = "super::image::Entity")]
+    Image,
+    #[sea_orm(has_many = "super::login_history::Entity")]
+    LoginHistory,
+    #[sea_orm(has_many = "super::notification_outbox::Entity")]
+    NotificationOutbox,
+    #[sea_orm(has_many = "super::subadmin_region::Entity")]
+    SubadminRegion,
+    #[sea_orm(has_many = "super::subscribe_authorization::Entity")]
+    SubscribeAuthorization,
+    #[sea_orm(has_many = "super::travel_record::Entity")]
+    TravelRecord,
     #[sea_orm(has_many = "super::user_info::Entity")]
     UserInfo,
-    #[sea_orm(has_one = "super::we_chat_session::Entity")]
+    #[sea_orm(has_many = "super::we_chat_identity::Entity")]
+    WeChatIdentity,
+    #[sea_orm(has_many = "super::we_chat_session::Entity")]
     WeChatSession,
 }
 
+impl Related<super::image::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Image.def()
+    }
+}
+
+impl Related<super::login_history::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::LoginHistory.def()
+    }
+}
+
+impl Related<super::notification_outbox::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::NotificationOutbox.def()
+    }
+}
+
+impl Related<super::subadmin_region::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::SubadminRegion.def()
+    }
+}
+
+impl Related<super::subscribe_authorization::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::SubscribeAuthorization.def()
+    }
+}
+
+impl Related<super::travel_record::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::TravelRecord.def()
+    }
+}
+
 impl Related<super::user_info::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::UserInfo.def()
     }
 }
 
+impl Related<super::we_chat_identity::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::WeChatIdentity.def()
+    }
+}
+
 impl Related<super::we_chat_session::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::WeChatSession.def()
@@ -3,6 +3,20 @@
 pub mod prelude;
 
 pub mod app_user;
+pub mod audit_log;
+pub mod duplicate_account;
+pub mod health_check_in;
+pub mod image;
+pub mod login_history;
+pub mod media_check;
+pub mod notification_outbox;
+pub mod observation_transition;
+pub mod retired_refresh_token;
 pub mod sea_orm_active_enums;
+pub mod subadmin_region;
+pub mod subscribe_authorization;
+pub mod travel_record;
 pub mod user_info;
+pub mod we_chat_access_token;
+pub mod we_chat_identity;
 pub mod we_chat_session;
@@ -1,5 +1,19 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.12.10
 
 pub use super::app_user::Entity as AppUser;
+pub use super::audit_log::Entity as AuditLog;
+pub use super::duplicate_account::Entity as DuplicateAccount;
+pub use super::health_check_in::Entity as HealthCheckIn;
+pub use super::image::Entity as Image;
+pub use super::login_history::Entity as LoginHistory;
+pub use super::media_check::Entity as MediaCheck;
+pub use super::notification_outbox::Entity as NotificationOutbox;
+pub use super::observation_transition::Entity as ObservationTransition;
+pub use super::retired_refresh_token::Entity as RetiredRefreshToken;
+pub use super::subadmin_region::Entity as SubadminRegion;
+pub use super::subscribe_authorization::Entity as SubscribeAuthorization;
+pub use super::travel_record::Entity as TravelRecord;
 pub use super::user_info::Entity as UserInfo;
+pub use super::we_chat_access_token::Entity as WeChatAccessToken;
+pub use super::we_chat_identity::Entity as WeChatIdentity;
 pub use super::we_chat_session::Entity as WeChatSession;
@@ -2,11 +2,85 @@
 
 use sea_orm::entity::prelude::*;
 
+#[derive(Debug, Clone, PartialEq, Eq, EnumIter, DeriveActiveEnum)]
+#[sea_orm(rs_type = "String", db_type = "Enum", enum_name = "audit_action")]
+pub enum AuditAction {
+    #[sea_orm(string_value = "delete")]
+    Delete,
+    #[sea_orm(string_value = "insert")]
+    Insert,
+    #[sea_orm(string_value = "review")]
+    Review,
+    #[sea_orm(string_value = "update")]
+    Update,
+}
+#[derive(Debug, Clone, PartialEq, Eq, EnumIter, DeriveActiveEnum)]
+#[sea_orm(rs_type = "String", db_type = "Enum", enum_name = "gender")]
+pub enum Gender {
+    #[sea_orm(string_value = "female")]
+    Female,
+    #[sea_orm(string_value = "male")]
+    Male,
+}
+#[derive(Debug, Clone, PartialEq, Eq, EnumIter, DeriveActiveEnum)]
+#[sea_orm(rs_type = "String", db_type = "Enum", enum_name = "login_outcome")]
+pub enum LoginOutcome {
+    #[sea_orm(string_value = "failure")]
+    Failure,
+    #[sea_orm(string_value = "success")]
+    Success,
+}
+#[derive(Debug, Clone, PartialEq, Eq, EnumIter, DeriveActiveEnum)]
+#[sea_orm(rs_type = "String", db_type = "Enum", enum_name = "observation_status")]
+pub enum ObservationStatus {
+    #[sea_orm(string_value = "observing")]
+    Observing,
+    #[sea_orm(string_value = "released")]
+    Released,
+    #[sea_orm(string_value = "cancelled")]
+    Cancelled,
+}
+#[derive(Debug, Clone, PartialEq, Eq, EnumIter, DeriveActiveEnum)]
+#[sea_orm(rs_type = "String", db_type = "Enum", enum_name = "observation_type")]
+pub enum ObservationType {
+    #[sea_orm(string_value = "centralized")]
+    Centralized,
+    #[sea_orm(string_value = "home")]
+    Home,
+}
+#[derive(Debug, Clone, PartialEq, Eq, EnumIter, DeriveActiveEnum)]
+#[sea_orm(rs_type = "String", db_type = "Enum", enum_name = "test_result")]
+pub enum TestResult {
+    #[sea_orm(string_value = "negative")]
+    Negative,
+    #[sea_orm(string_value = "not_tested")]
+    NotTested,
+    #[sea_orm(string_value = "positive")]
+    Positive,
+}
+#[derive(Debug, Clone, PartialEq, Eq, EnumIter, DeriveActiveEnum)]
+#[sea_orm(rs_type = "String", db_type = "Enum", enum_name = "transport_mode")]
+pub enum TransportMode {
+    #[sea_orm(string_value = "bus")]
+    Bus,
+    #[sea_orm(string_value = "car")]
+    Car,
+    #[sea_orm(string_value = "flight")]
+    Flight,
+    #[sea_orm(string_value = "other")]
+    Other,
+    #[sea_orm(string_value = "ship")]
+    Ship,
+    #[sea_orm(string_value = "train")]
+    Train,
+}
 #[derive(Debug, Clone, PartialEq, Eq, EnumIter, DeriveActiveEnum)]
 #[sea_orm(rs_type = "String", db_type = "Enum", enum_name = "user_role")]
 pub enum UserRole {
     #[sea_orm(string_value = "admin")]
     Admin,
+    #[sea_orm(string_value = "inspector")]
+    Inspector,
     #[sea_orm(string_value = "normal")]
     Normal,
     #[sea_orm(string_value = "subadmin")]
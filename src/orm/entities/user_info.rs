@@ -1,5 +1,8 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.12.10
 
+use super::sea_orm_active_enums::Gender;
+use super::sea_orm_active_enums::ObservationStatus;
+use super::sea_orm_active_enums::ObservationType;
 use super::sea_orm_active_enums::Validated;
 use sea_orm::entity::prelude::*;
 
@@ -15,6 +18,21 @@ pub struct Model {
     pub address: String,
     pub image: Option<Uuid>,
     pub validated: Validated,
+    pub created_at: DateTime,
+    pub review_reason: Option<String>,
+    pub reviewer: Option<Uuid>,
+    pub reviewed_at: Option<DateTime>,
+    pub revision: i32,
+    pub birth_date: Option<Date>,
+    pub gender: Option<Gender>,
+    pub region_code: Option<String>,
+    pub phone_verified: bool,
+    pub tenant: String,
+    pub observation_type: Option<ObservationType>,
+    pub observation_start: Option<Date>,
+    pub observation_end: Option<Date>,
+    pub observation_status: Option<ObservationStatus>,
+    pub address_region: Option<String>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -27,6 +45,12 @@ pub enum Relation {
         on_delete = "NoAction"
     )]
     AppUser,
+    #[sea_orm(has_many = "super::health_check_in::Entity")]
+    HealthCheckIn,
+    #[sea_orm(has_many = "super::observation_transition::Entity")]
+    ObservationTransition,
+    #[sea_orm(has_many = "super::travel_record::Entity")]
+    TravelRecord,
 }
 
 impl Related<super::app_user::Entity> for Entity {
@@ -35,4 +59,22 @@ impl Related<super::app_user::Entity> for Entity {
     }
 }
 
+impl Related<super::health_check_in::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::HealthCheckIn.def()
+    }
+}
+
+impl Related<super::observation_transition::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ObservationTransition.def()
+    }
+}
+
+impl Related<super::travel_record::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::TravelRecord.def()
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
@@ -7,13 +7,21 @@ use sea_orm::entity::prelude::*;
 pub struct Model {
     #[sea_orm(primary_key)]
     pub id: i32,
-    #[sea_orm(unique)]
     pub user_id: Uuid,
     pub last_login: DateTime,
-    #[sea_orm(unique)]
     pub last_session: String,
     #[sea_orm(unique)]
     pub last_token: Uuid,
+    #[sea_orm(unique)]
+    pub refresh_token: Uuid,
+    pub refresh_family: Uuid,
+    pub refresh_expires: DateTime,
+    pub revoked: bool,
+    pub created_at: DateTime,
+    pub client_ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub appid: Option<String>,
+    pub tenant: String,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
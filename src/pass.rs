@@ -0,0 +1,156 @@
+//! Signed passes gatekeepers scan instead of calling the office.
+//!
+//! A pass is `base64url(claims) "." base64url(HMAC-SHA256(key, base64url(claims)))`, where
+//! the claims are JSON naming the record, its review status when issued and the expiry.
+
+use std::io::Cursor;
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use image::{DynamicImage, ImageFormat, Luma};
+use qrcode::{render::svg, QrCode};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use uuid::Uuid;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Side length of the rendered code in pixels, at least.
+const QR_MIN_SIZE: u32 = 256;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PassClaims {
+    pub user_info_id: Uuid,
+    pub status: String,
+    /// Unix seconds.
+    pub expires_at: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PassError {
+    Malformed,
+    Signature,
+    Expired,
+}
+
+impl PassError {
+    pub fn reason(&self) -> &'static str {
+        match self {
+            PassError::Malformed => "pass is not a signed pass",
+            PassError::Signature => "pass signature does not match",
+            PassError::Expired => "pass has expired",
+        }
+    }
+}
+
+fn mac(key: &[u8]) -> HmacSha256 {
+    HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any length")
+}
+
+pub fn sign(key: &[u8], claims: &PassClaims) -> String {
+    let payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(claims).expect("claims serialize"));
+
+    let mut mac = mac(key);
+    mac.update(payload.as_bytes());
+    let signature = URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes());
+
+    format!("{}.{}", payload, signature)
+}
+
+/// Checks the signature of `pass` and that it has not expired at `now` (Unix seconds).
+pub fn verify(key: &[u8], pass: &str, now: i64) -> Result<PassClaims, PassError> {
+    let (payload, signature) = pass.split_once('.').ok_or(PassError::Malformed)?;
+    let signature = URL_SAFE_NO_PAD
+        .decode(signature)
+        .map_err(|_| PassError::Malformed)?;
+
+    let mut mac = mac(key);
+    mac.update(payload.as_bytes());
+    mac.verify_slice(&signature)
+        .map_err(|_| PassError::Signature)?;
+
+    let claims = URL_SAFE_NO_PAD
+        .decode(payload)
+        .map_err(|_| PassError::Malformed)?;
+    let claims: PassClaims = serde_json::from_slice(&claims).map_err(|_| PassError::Malformed)?;
+
+    if claims.expires_at <= now {
+        return Err(PassError::Expired);
+    }
+
+    Ok(claims)
+}
+
+pub fn render_png(pass: &str) -> Result<Vec<u8>, String> {
+    let code = QrCode::new(pass.as_bytes()).map_err(|e| e.to_string())?;
+    let image = code
+        .render::<Luma<u8>>()
+        .min_dimensions(QR_MIN_SIZE, QR_MIN_SIZE)
+        .build();
+
+    let mut png = Vec::new();
+    DynamicImage::ImageLuma8(image)
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .map_err(|e| e.to_string())?;
+
+    Ok(png)
+}
+
+pub fn render_svg(pass: &str) -> Result<String, String> {
+    let code = QrCode::new(pass.as_bytes()).map_err(|e| e.to_string())?;
+
+    Ok(code
+        .render::<svg::Color>()
+        .min_dimensions(QR_MIN_SIZE, QR_MIN_SIZE)
+        .build())
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::{render_png, render_svg, sign, verify, PassClaims, PassError};
+
+    const KEY: &[u8] = b"0123456789abcdef0123456789abcdef";
+
+    fn claims() -> PassClaims {
+        PassClaims {
+            user_info_id: Uuid::new_v4(),
+            status: "pass".to_owned(),
+            expires_at: 1_700_000_300,
+        }
+    }
+
+    #[test]
+    fn verifies_own_signature_until_expiry() {
+        let claims = claims();
+        let pass = sign(KEY, &claims);
+
+        assert_eq!(verify(KEY, &pass, 1_700_000_299), Ok(claims));
+        assert_eq!(verify(KEY, &pass, 1_700_000_300), Err(PassError::Expired));
+    }
+
+    #[test]
+    fn rejects_tampered_or_foreign_passes() {
+        let pass = sign(KEY, &claims());
+        let (_, signature) = pass.split_once('.').unwrap();
+        let other = sign(KEY, &claims());
+        let (payload, _) = other.split_once('.').unwrap();
+
+        let forged = format!("{}.{}", payload, signature);
+        assert_eq!(verify(KEY, &forged, 0), Err(PassError::Signature));
+        assert_eq!(
+            verify(b"another key of thirty-two bytes!", &pass, 0),
+            Err(PassError::Signature)
+        );
+        assert_eq!(verify(KEY, "not a pass", 0), Err(PassError::Malformed));
+    }
+
+    #[test]
+    fn renders_pass_as_png_and_svg() {
+        let pass = sign(KEY, &claims());
+
+        assert!(render_png(&pass).unwrap().starts_with(b"\x89PNG"));
+        assert!(render_svg(&pass).unwrap().contains("<svg"));
+    }
+}
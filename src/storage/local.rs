@@ -0,0 +1,39 @@
+use std::{io, path::PathBuf};
+
+use rocket::tokio::fs;
+use uuid::Uuid;
+
+use super::ImageStorage;
+
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        LocalStorage { root: root.into() }
+    }
+
+    fn path(&self, id: Uuid) -> PathBuf {
+        self.root.join(id.to_string())
+    }
+}
+
+#[rocket::async_trait]
+impl ImageStorage for LocalStorage {
+    async fn store(&self, id: Uuid, bytes: &[u8]) -> io::Result<()> {
+        fs::create_dir_all(&self.root).await?;
+        fs::write(self.path(id), bytes).await
+    }
+
+    async fn load(&self, id: Uuid) -> io::Result<Vec<u8>> {
+        fs::read(self.path(id)).await
+    }
+
+    async fn remove(&self, id: Uuid) -> io::Result<()> {
+        match fs::remove_file(self.path(id)).await {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            ret => ret,
+        }
+    }
+}
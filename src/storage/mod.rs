@@ -0,0 +1,17 @@
+mod local;
+
+use std::io;
+
+use uuid::Uuid;
+
+pub use local::LocalStorage;
+
+/// Where uploaded image bytes live; metadata stays in the `image` table.
+#[rocket::async_trait]
+pub trait ImageStorage: Send + Sync {
+    async fn store(&self, id: Uuid, bytes: &[u8]) -> io::Result<()>;
+
+    async fn load(&self, id: Uuid) -> io::Result<Vec<u8>>;
+
+    async fn remove(&self, id: Uuid) -> io::Result<()>;
+}
@@ -0,0 +1,183 @@
+//! Verification and decryption of user data handed to the mini-program by `wx.getUserInfo`,
+//! `wx.getShareInfo` and friends, keyed by the session_key from jscode2session.
+
+use aes::cipher::{block_padding::Pkcs7, BlockDecryptMut, KeyIvInit};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::Deserialize;
+use serde_json::Value;
+use sha1::{Digest, Sha1};
+
+type Aes128CbcDec = cbc::Decryptor<aes::Aes128>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CryptoError {
+    Signature,
+    Encoding,
+    Decrypt,
+    Payload,
+    Watermark,
+}
+
+impl CryptoError {
+    pub fn reason(&self) -> &'static str {
+        match self {
+            CryptoError::Signature => "signature does not match rawData",
+            CryptoError::Encoding => "session_key, iv or encryptedData is not valid base64",
+            CryptoError::Decrypt => "encryptedData could not be decrypted",
+            CryptoError::Payload => "decrypted data is not valid JSON",
+            CryptoError::Watermark => "watermark does not match this app or is too old",
+        }
+    }
+}
+
+/// Checks `signature == sha1(rawData + session_key)`.
+pub fn verify_signature(
+    raw_data: &str,
+    session_key: &str,
+    signature: &str,
+) -> Result<(), CryptoError> {
+    let mut hasher = Sha1::new();
+    hasher.update(raw_data.as_bytes());
+    hasher.update(session_key.as_bytes());
+    let expected = hex::encode(hasher.finalize());
+
+    let signature = signature.to_ascii_lowercase();
+    // Compare without short-circuiting, so timing does not leak the matching prefix.
+    let diff = expected
+        .bytes()
+        .zip(signature.bytes())
+        .fold(expected.len() ^ signature.len(), |acc, (a, b)| {
+            acc | (a ^ b) as usize
+        });
+
+    if diff == 0 {
+        Ok(())
+    } else {
+        Err(CryptoError::Signature)
+    }
+}
+
+/// AES-128-CBC with PKCS#7 padding; key, iv and data are all base64.
+pub fn decrypt(encrypted_data: &str, iv: &str, session_key: &str) -> Result<Value, CryptoError> {
+    let key = STANDARD
+        .decode(session_key)
+        .map_err(|_| CryptoError::Encoding)?;
+    let iv = STANDARD.decode(iv).map_err(|_| CryptoError::Encoding)?;
+    let data = STANDARD
+        .decode(encrypted_data)
+        .map_err(|_| CryptoError::Encoding)?;
+
+    let plain = Aes128CbcDec::new_from_slices(&key, &iv)
+        .map_err(|_| CryptoError::Encoding)?
+        .decrypt_padded_vec_mut::<Pkcs7>(&data)
+        .map_err(|_| CryptoError::Decrypt)?;
+
+    serde_json::from_slice(&plain).map_err(|_| CryptoError::Payload)
+}
+
+#[derive(Deserialize)]
+struct Watermark {
+    appid: String,
+    timestamp: i64,
+}
+
+/// Rejects data minted for another appid, or more than `max_age` seconds before `now`.
+pub fn check_watermark(
+    data: &Value,
+    appid: &str,
+    now: i64,
+    max_age: i64,
+) -> Result<(), CryptoError> {
+    let watermark = data
+        .get("watermark")
+        .cloned()
+        .and_then(|x| serde_json::from_value::<Watermark>(x).ok())
+        .ok_or(CryptoError::Watermark)?;
+
+    if watermark.appid != appid || now - watermark.timestamp > max_age {
+        return Err(CryptoError::Watermark);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_watermark, decrypt, verify_signature, CryptoError};
+
+    // Vectors from the WeChat open data documentation and its official demo.
+    const RAW_DATA: &str = r#"{"nickName":"Band","gender":1,"language":"zh_CN","city":"Guangzhou","province":"Guangdong","country":"CN","avatarUrl":"http://wx.qlogo.cn/mmopen/vi_32/1vZvI39NWFQ9XM4LtQpFrQJ1xlgZxx3w7bQxKARol6503Iuswjjn6nIGBiaycAjAtpujxyzYsrztuuICqIM5ibXQ/0"}"#;
+    const RAW_SESSION_KEY: &str = "HyVFkGl5F5OQWJZZaNzBBg==";
+    const SIGNATURE: &str = "75e81ceda165f4ffa64f4068af58c64b8f54b88c";
+
+    const APPID: &str = "wx4f4bc4dec97d474b";
+    const SESSION_KEY: &str = "tiihtNczf5v6AKRyjwEUhQ==";
+    const IV: &str = "r7BXXKkLb8qrSNn05n0qiA==";
+    const ENCRYPTED_DATA: &str = "CiyLU1Aw2KjvrjMdj8YKliAjtP4gsMZMQmRzooG2xrDcvSnxIMXFufNstNGTyaGS9uT5geRa0W4oTOb1WT7fJlAC+oNPdbB+3hVbJSRgv+4lGOETKUQz6OYStslQ142dNCuabNPGBzlooOmB231qMM85d2/fV6ChevvXvQP8Hkue1poOFtnEtpyxVLW1zAo6/1Xx1COxFvrc2d7UL/lmHInNlxuacJXwu0fjpXfz/YqYzBIBzD6WUfTIF9GRHpOn/Hz7saL8xz+W//FRAUid1OksQaQx4CMs8LOddcQhULW4ucetDf96JcR3g0gfRK4PC7E/r7Z6xNrXd2UIeorGj5Ef7b1pJAYB6Y5anaHqZ9J6nKEBvB4DnNLIVWSgARns/8wR2SiRS7MNACwTyrGvt9ts8p12PKFdlqYTopNHR1Vf7XjfhQlVsAJdNiKdYmYVoKlaRv85IfVunYzO0IKXsyl7JCUjCpoG20f0a04COwfneQAGGwd5oa+T8yO5hzuyDb/XcxxmK01EpqOyuxINew==";
+    const WATERMARK_TIMESTAMP: i64 = 1477314187;
+
+    #[test]
+    fn verifies_raw_data_signature() {
+        assert_eq!(
+            verify_signature(RAW_DATA, RAW_SESSION_KEY, SIGNATURE),
+            Ok(())
+        );
+        assert_eq!(
+            verify_signature(RAW_DATA, RAW_SESSION_KEY, &SIGNATURE.to_uppercase()),
+            Ok(())
+        );
+        assert_eq!(
+            verify_signature(
+                &RAW_DATA.replace("Band", "Bond"),
+                RAW_SESSION_KEY,
+                SIGNATURE
+            ),
+            Err(CryptoError::Signature)
+        );
+        assert_eq!(
+            verify_signature(RAW_DATA, SESSION_KEY, SIGNATURE),
+            Err(CryptoError::Signature)
+        );
+    }
+
+    #[test]
+    fn decrypts_user_info() {
+        let data = decrypt(ENCRYPTED_DATA, IV, SESSION_KEY).unwrap();
+        assert_eq!(data["openId"], "oGZUI0egBJY1zhBYw2KhdUfwVJJE");
+        assert_eq!(data["unionId"], "ocMvos6NjeKLIBqg5Mr9QjxrP1FA");
+        assert_eq!(data["nickName"], "Band");
+        assert_eq!(data["watermark"]["appid"], APPID);
+    }
+
+    #[test]
+    fn rejects_wrong_key_or_bad_input() {
+        assert!(decrypt(ENCRYPTED_DATA, IV, RAW_SESSION_KEY).is_err());
+        assert_eq!(
+            decrypt(ENCRYPTED_DATA, "not base64!", SESSION_KEY),
+            Err(CryptoError::Encoding)
+        );
+        assert_eq!(
+            decrypt(ENCRYPTED_DATA, "cjdCWFhLa0xiOHFy", SESSION_KEY).unwrap_err(),
+            CryptoError::Encoding
+        );
+    }
+
+    #[test]
+    fn checks_watermark() {
+        let data = decrypt(ENCRYPTED_DATA, IV, SESSION_KEY).unwrap();
+        let now = WATERMARK_TIMESTAMP + 60;
+        assert_eq!(check_watermark(&data, APPID, now, 300), Ok(()));
+        assert_eq!(
+            check_watermark(&data, "wx0000000000000000", now, 300),
+            Err(CryptoError::Watermark)
+        );
+        assert_eq!(
+            check_watermark(&data, APPID, now + 600, 300),
+            Err(CryptoError::Watermark)
+        );
+        assert_eq!(
+            check_watermark(&serde_json::json!({}), APPID, now, 300),
+            Err(CryptoError::Watermark)
+        );
+    }
+}
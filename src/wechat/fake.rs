@@ -0,0 +1,222 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use super::{
+    AccessToken, Code2Session, PhoneNumber, SecCheckResult, SecSuggest, SubscribeMessage,
+    WeChatClient, WeChatError,
+};
+use crate::config::WeChatApp;
+
+/// In-memory client: codes must be registered up front, anything else is `40029`.
+#[derive(Default)]
+pub struct FakeWeChatClient {
+    answers: Mutex<HashMap<String, Result<Code2Session, WeChatError>>>,
+    used: Mutex<HashSet<String>>,
+    access_tokens: Mutex<VecDeque<AccessToken>>,
+    access_token_fetches: AtomicUsize,
+    phone_numbers: Mutex<HashMap<String, String>>,
+    current_token: Mutex<Option<String>>,
+    send_errors: Mutex<VecDeque<WeChatError>>,
+    sent: Mutex<Vec<SubscribeMessage>>,
+    risky_words: Mutex<Vec<String>>,
+    media_checks: Mutex<Vec<String>>,
+}
+
+impl FakeWeChatClient {
+    pub fn with_session(self, code: &str, openid: &str) -> Self {
+        self.with_union_session(code, openid, None)
+    }
+
+    pub fn with_union_session(self, code: &str, openid: &str, unionid: Option<&str>) -> Self {
+        let session = Code2Session {
+            openid: openid.to_owned(),
+            session_key: Some(format!("session-key-{}", openid)),
+            unionid: unionid.map(str::to_owned),
+        };
+        self.answers
+            .lock()
+            .unwrap()
+            .insert(code.to_owned(), Ok(session));
+        self
+    }
+
+    pub fn with_errcode(self, code: &str, errcode: i32) -> Self {
+        let error = WeChatError::Api {
+            errcode,
+            errmsg: None,
+        };
+        self.answers
+            .lock()
+            .unwrap()
+            .insert(code.to_owned(), Err(error));
+        self
+    }
+
+    /// Queues the answer for the next `fetch_access_token`.
+    pub fn with_access_token(self, access_token: &str, expires_in: i64) -> Self {
+        self.access_tokens.lock().unwrap().push_back(AccessToken {
+            access_token: access_token.to_owned(),
+            expires_in,
+        });
+        self
+    }
+
+    /// `code` answers `phone_number`, but only to the most recently fetched access_token.
+    pub fn with_phone_number(self, code: &str, phone_number: &str) -> Self {
+        self.phone_numbers
+            .lock()
+            .unwrap()
+            .insert(code.to_owned(), phone_number.to_owned());
+        self
+    }
+
+    /// The next subscribe message send fails with `errcode`; later ones succeed.
+    pub fn with_send_errcode(self, errcode: i32) -> Self {
+        self.send_errors
+            .lock()
+            .unwrap()
+            .push_back(WeChatError::Api {
+                errcode,
+                errmsg: None,
+            });
+        self
+    }
+
+    /// Text containing `word` is judged risky with label `20001`.
+    pub fn with_risky_word(self, word: &str) -> Self {
+        self.risky_words.lock().unwrap().push(word.to_owned());
+        self
+    }
+
+    /// URLs submitted to `media_check_async`; the n-th got trace_id `trace-<n>`.
+    pub fn media_checks(&self) -> Vec<String> {
+        self.media_checks.lock().unwrap().clone()
+    }
+
+    pub fn sent_messages(&self) -> Vec<SubscribeMessage> {
+        self.sent.lock().unwrap().clone()
+    }
+
+    pub fn access_token_fetches(&self) -> usize {
+        self.access_token_fetches.load(Ordering::SeqCst)
+    }
+}
+
+#[rocket::async_trait]
+impl WeChatClient for FakeWeChatClient {
+    async fn code2session(
+        &self,
+        _app: &WeChatApp,
+        code: &str,
+    ) -> Result<Code2Session, WeChatError> {
+        // A code is single use, like on the real API.
+        if let Some(answer) = self.answers.lock().unwrap().remove(code) {
+            self.used.lock().unwrap().insert(code.to_owned());
+            return answer;
+        }
+
+        let (errcode, errmsg) = if self.used.lock().unwrap().contains(code) {
+            (40163, "code been used")
+        } else {
+            (40029, "invalid code")
+        };
+        Err(WeChatError::Api {
+            errcode,
+            errmsg: Some(errmsg.to_owned()),
+        })
+    }
+
+    async fn fetch_access_token(&self, _app: &WeChatApp) -> Result<AccessToken, WeChatError> {
+        self.access_token_fetches.fetch_add(1, Ordering::SeqCst);
+        // Let concurrent callers pile up, as they would behind a real network call.
+        rocket::tokio::task::yield_now().await;
+
+        let token = self
+            .access_tokens
+            .lock()
+            .unwrap()
+            .pop_front()
+            .ok_or_else(|| WeChatError::Api {
+                errcode: 40013,
+                errmsg: Some("invalid appid".to_owned()),
+            })?;
+        *self.current_token.lock().unwrap() = Some(token.access_token.clone());
+
+        Ok(token)
+    }
+
+    async fn get_phone_number(
+        &self,
+        access_token: &str,
+        code: &str,
+    ) -> Result<PhoneNumber, WeChatError> {
+        if self.current_token.lock().unwrap().as_deref() != Some(access_token) {
+            return Err(WeChatError::Api {
+                errcode: 40001,
+                errmsg: Some("invalid credential".to_owned()),
+            });
+        }
+
+        match self.phone_numbers.lock().unwrap().remove(code) {
+            Some(phone_number) => Ok(PhoneNumber {
+                pure_phone_number: phone_number.clone(),
+                phone_number,
+            }),
+            None => Err(WeChatError::Api {
+                errcode: 40029,
+                errmsg: Some("invalid code".to_owned()),
+            }),
+        }
+    }
+
+    async fn msg_sec_check(
+        &self,
+        _access_token: &str,
+        _openid: &str,
+        content: &str,
+    ) -> Result<SecCheckResult, WeChatError> {
+        let risky = self
+            .risky_words
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|word| content.contains(word.as_str()));
+
+        Ok(match risky {
+            true => SecCheckResult {
+                suggest: SecSuggest::Risky,
+                label: 20001,
+            },
+            false => SecCheckResult {
+                suggest: SecSuggest::Pass,
+                label: 100,
+            },
+        })
+    }
+
+    async fn media_check_async(
+        &self,
+        _access_token: &str,
+        _openid: &str,
+        media_url: &str,
+    ) -> Result<String, WeChatError> {
+        let mut checks = self.media_checks.lock().unwrap();
+        checks.push(media_url.to_owned());
+
+        Ok(format!("trace-{}", checks.len()))
+    }
+
+    async fn send_subscribe_message(
+        &self,
+        _access_token: &str,
+        message: &SubscribeMessage,
+    ) -> Result<(), WeChatError> {
+        if let Some(error) = self.send_errors.lock().unwrap().pop_front() {
+            return Err(error);
+        }
+
+        self.sent.lock().unwrap().push(message.clone());
+        Ok(())
+    }
+}
@@ -0,0 +1,379 @@
+use std::future::Future;
+
+use crate::config::{WeChatApp, WeChatAppKind, WeChatConfig, WeChatHttpConfig};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{
+    breaker::CircuitBreaker, AccessToken, AccessTokenAPIResponse, Code2Session,
+    MediaCheckAPIResponse, MsgSecCheckAPIResponse, PhoneNumber, PhoneNumberAPIResponse,
+    SecCheckResult, StatusAPIResponse, SubscribeMessage, WeChatClient, WeChatError,
+    WeChatLoginAPIResponse,
+};
+
+/// Content security `scene` for profile data.
+const SEC_CHECK_SCENE: i32 = 1;
+const MEDIA_TYPE_IMAGE: i32 = 2;
+
+/// Builds the one `reqwest::Client` shared by every outgoing WeChat call.
+pub fn http_client(config: &WeChatHttpConfig) -> reqwest::Result<reqwest::Client> {
+    reqwest::Client::builder()
+        .connect_timeout(config.connect_timeout())
+        .timeout(config.timeout())
+        .build()
+}
+
+/// Talks to the real WeChat API, or to whatever `wechat_api` points at.
+pub struct HttpWeChatClient {
+    client: reqwest::Client,
+    config: WeChatConfig,
+    http: WeChatHttpConfig,
+    breaker: CircuitBreaker,
+}
+
+impl HttpWeChatClient {
+    pub fn new(client: reqwest::Client, config: WeChatConfig, http: WeChatHttpConfig) -> Self {
+        let breaker = CircuitBreaker::new(http.wechat_breaker_threshold, http.breaker_cooldown());
+        HttpWeChatClient {
+            client,
+            config,
+            http,
+            breaker,
+        }
+    }
+
+    async fn get<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        query: &[(&str, &str)],
+    ) -> Result<T, WeChatError> {
+        self.send(self.client.get(self.config.endpoint(path)).query(query))
+            .await
+    }
+
+    async fn post<T: DeserializeOwned, B: Serialize + ?Sized>(
+        &self,
+        path: &str,
+        query: &[(&str, &str)],
+        body: &B,
+    ) -> Result<T, WeChatError> {
+        self.send(
+            self.client
+                .post(self.config.endpoint(path))
+                .query(query)
+                .json(body),
+        )
+        .await
+    }
+
+    /// Decodes the body by hand; WeChat often answers with `text/plain`.
+    async fn send<T: DeserializeOwned>(
+        &self,
+        request: reqwest::RequestBuilder,
+    ) -> Result<T, WeChatError> {
+        let response = request
+            .send()
+            .await
+            .map_err(|e| WeChatError::Transport(e.to_string()))?;
+
+        if !response.status().is_success() {
+            return Err(WeChatError::Transport(format!(
+                "HTTP {}",
+                response.status()
+            )));
+        }
+
+        let text = response
+            .text()
+            .await
+            .map_err(|e| WeChatError::Transport(e.to_string()))?;
+
+        serde_json::from_str(&text).map_err(|e| WeChatError::Transport(e.to_string()))
+    }
+
+    /// Runs `call` behind the circuit breaker, retrying retryable errcodes with backoff.
+    async fn call<T, F, Fut>(&self, mut call: F) -> Result<T, WeChatError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, WeChatError>>,
+    {
+        if !self.breaker.allow() {
+            return Err(WeChatError::CircuitOpen);
+        }
+
+        let mut attempt = 0;
+        loop {
+            let result = call().await;
+            match &result {
+                Err(e) if e.is_retryable() && attempt < self.http.wechat_retries => {
+                    attempt += 1;
+                    rocket::tokio::time::sleep(self.http.backoff(attempt)).await;
+                }
+                Err(e) if e.is_outage() => {
+                    self.breaker.record_failure();
+                    return result;
+                }
+                _ => {
+                    self.breaker.record_success();
+                    return result;
+                }
+            }
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl WeChatClient for HttpWeChatClient {
+    async fn code2session(&self, app: &WeChatApp, code: &str) -> Result<Code2Session, WeChatError> {
+        let (path, code_param) = match app.kind {
+            WeChatAppKind::MiniProgram => ("/sns/jscode2session", "js_code"),
+            WeChatAppKind::OfficialAccount => ("/sns/oauth2/access_token", "code"),
+        };
+
+        self.call(|| async {
+            self.get::<WeChatLoginAPIResponse>(
+                path,
+                &[
+                    ("appid", &app.appid),
+                    ("secret", &app.secret),
+                    (code_param, code),
+                    ("grant_type", "authorization_code"),
+                ],
+            )
+            .await?
+            .into_result()
+        })
+        .await
+    }
+
+    async fn fetch_access_token(&self, app: &WeChatApp) -> Result<AccessToken, WeChatError> {
+        self.call(|| async {
+            self.get::<AccessTokenAPIResponse>(
+                "/cgi-bin/token",
+                &[
+                    ("grant_type", "client_credential"),
+                    ("appid", &app.appid),
+                    ("secret", &app.secret),
+                ],
+            )
+            .await?
+            .into_result()
+        })
+        .await
+    }
+
+    async fn get_phone_number(
+        &self,
+        access_token: &str,
+        code: &str,
+    ) -> Result<PhoneNumber, WeChatError> {
+        self.call(|| async {
+            self.post::<PhoneNumberAPIResponse, _>(
+                "/wxa/business/getuserphonenumber",
+                &[("access_token", access_token)],
+                &serde_json::json!({ "code": code }),
+            )
+            .await?
+            .into_result()
+        })
+        .await
+    }
+
+    async fn msg_sec_check(
+        &self,
+        access_token: &str,
+        openid: &str,
+        content: &str,
+    ) -> Result<SecCheckResult, WeChatError> {
+        self.call(|| async {
+            self.post::<MsgSecCheckAPIResponse, _>(
+                "/wxa/msg_sec_check",
+                &[("access_token", access_token)],
+                &serde_json::json!({
+                    "content": content,
+                    "version": 2,
+                    "scene": SEC_CHECK_SCENE,
+                    "openid": openid,
+                }),
+            )
+            .await?
+            .into_result()
+        })
+        .await
+    }
+
+    async fn media_check_async(
+        &self,
+        access_token: &str,
+        openid: &str,
+        media_url: &str,
+    ) -> Result<String, WeChatError> {
+        self.call(|| async {
+            self.post::<MediaCheckAPIResponse, _>(
+                "/wxa/media_check_async",
+                &[("access_token", access_token)],
+                &serde_json::json!({
+                    "media_url": media_url,
+                    "media_type": MEDIA_TYPE_IMAGE,
+                    "version": 2,
+                    "scene": SEC_CHECK_SCENE,
+                    "openid": openid,
+                }),
+            )
+            .await?
+            .into_result()
+        })
+        .await
+    }
+
+    async fn send_subscribe_message(
+        &self,
+        access_token: &str,
+        message: &SubscribeMessage,
+    ) -> Result<(), WeChatError> {
+        self.call(|| async {
+            self.post::<StatusAPIResponse, _>(
+                "/cgi-bin/message/subscribe/send",
+                &[("access_token", access_token)],
+                message,
+            )
+            .await?
+            .into_result()
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HttpWeChatClient;
+    use crate::config::{WeChatApp, WeChatAppKind};
+    use crate::wechat::{stub, WeChatClient, WeChatError};
+
+    async fn client() -> HttpWeChatClient {
+        stub::client(&stub::spawn().await)
+    }
+
+    #[rocket::async_test]
+    async fn exchanges_code() {
+        let session = client()
+            .await
+            .code2session(&stub::app(), "user-1")
+            .await
+            .unwrap();
+        assert_eq!(session.openid, "openid-user-1");
+        assert_eq!(session.session_key.as_deref(), Some(stub::SESSION_KEY));
+    }
+
+    #[rocket::async_test]
+    async fn exchanges_official_account_code() {
+        let app = WeChatApp {
+            kind: WeChatAppKind::OfficialAccount,
+            ..stub::app()
+        };
+        let session = client().await.code2session(&app, "user-1").await.unwrap();
+        assert_eq!(session.openid, "oa-openid-user-1");
+        assert_eq!(session.session_key, None);
+        assert_eq!(session.unionid.as_deref(), Some("unionid-user-1"));
+    }
+
+    #[rocket::async_test]
+    async fn reports_every_errcode() {
+        let client = client().await;
+        for &(errcode, errmsg) in stub::ERRCODES {
+            let error = client
+                .code2session(&stub::app(), &errcode.to_string())
+                .await
+                .unwrap_err();
+            assert_eq!(
+                error,
+                WeChatError::Api {
+                    errcode,
+                    errmsg: Some(errmsg.to_owned())
+                }
+            );
+        }
+    }
+
+    #[rocket::async_test]
+    async fn reports_malformed_body() {
+        let error = client()
+            .await
+            .code2session(&stub::app(), stub::MALFORMED)
+            .await
+            .unwrap_err();
+        assert!(matches!(error, WeChatError::Transport(_)));
+    }
+
+    #[rocket::async_test]
+    async fn fetches_access_token() {
+        let token = client()
+            .await
+            .fetch_access_token(&stub::app())
+            .await
+            .unwrap();
+        assert_eq!(token.access_token, stub::ACCESS_TOKEN);
+        assert_eq!(token.expires_in, 7200);
+    }
+
+    #[rocket::async_test]
+    async fn exchanges_phone_code() {
+        let client = client().await;
+        let phone = client
+            .get_phone_number(stub::ACCESS_TOKEN, "phone-1")
+            .await
+            .unwrap();
+        assert_eq!(phone.phone_number, stub::PHONE_NUMBER);
+
+        let error = client
+            .get_phone_number("expired", "phone-1")
+            .await
+            .unwrap_err();
+        assert!(error.is_stale_token());
+    }
+
+    #[rocket::async_test]
+    async fn retries_system_busy() {
+        let client = client().await;
+        let code = stub::busy_code(stub::RETRIES);
+        let session = client.code2session(&stub::app(), &code).await.unwrap();
+        assert_eq!(session.openid, format!("openid-{}", code));
+
+        let error = client
+            .code2session(&stub::app(), &stub::busy_code(stub::RETRIES + 1))
+            .await
+            .unwrap_err();
+        assert!(matches!(error, WeChatError::Api { errcode: -1, .. }));
+    }
+
+    #[rocket::async_test]
+    async fn opens_circuit_when_wechat_is_down() {
+        // Nothing listens on the discard port, so every call fails to connect.
+        let client = stub::client("http://127.0.0.1:9");
+        for _ in 0..stub::BREAKER_THRESHOLD {
+            let error = client
+                .code2session(&stub::app(), "user-1")
+                .await
+                .unwrap_err();
+            assert!(matches!(error, WeChatError::Transport(_)));
+        }
+
+        let error = client
+            .code2session(&stub::app(), "user-1")
+            .await
+            .unwrap_err();
+        assert_eq!(error, WeChatError::CircuitOpen);
+    }
+
+    #[rocket::async_test]
+    async fn rejected_codes_keep_circuit_closed() {
+        let client = client().await;
+        for _ in 0..stub::BREAKER_THRESHOLD + 1 {
+            let error = client
+                .code2session(&stub::app(), "40029")
+                .await
+                .unwrap_err();
+            assert!(matches!(error, WeChatError::Api { errcode: 40029, .. }));
+        }
+    }
+}
@@ -0,0 +1,382 @@
+//! Server-side calls into the WeChat API, behind a trait so handlers can be tested offline.
+
+mod breaker;
+pub mod crypto;
+#[cfg(test)]
+pub mod fake;
+mod http;
+#[cfg(test)]
+pub mod stub;
+mod token;
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::WeChatApp;
+
+pub use http::{http_client, HttpWeChatClient};
+pub use token::{with_access_token, AccessTokenProvider, DbAccessTokenManager};
+
+/// Result of exchanging a `wx.login` code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Code2Session {
+    pub openid: String,
+    /// Absent for official account logins, which have no session_key.
+    pub session_key: Option<String>,
+    pub unionid: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WeChatError {
+    /// WeChat answered with a non-zero `errcode`.
+    Api {
+        errcode: i32,
+        errmsg: Option<String>,
+    },
+    /// WeChat could not be reached or did not return a usable body.
+    Transport(String),
+    /// Too many recent calls failed; WeChat was not contacted.
+    CircuitOpen,
+}
+
+impl WeChatError {
+    /// The access_token was rejected as invalid or expired.
+    pub fn is_stale_token(&self) -> bool {
+        matches!(
+            self,
+            WeChatError::Api {
+                errcode: 40001 | 40014 | 42001,
+                ..
+            }
+        )
+    }
+
+    /// `-1` means WeChat is busy and the same request may succeed if sent again.
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, WeChatError::Api { errcode: -1, .. })
+    }
+
+    /// Failures that say WeChat itself is unhealthy, as opposed to a rejected request.
+    pub fn is_outage(&self) -> bool {
+        matches!(self, WeChatError::Transport(_)) || self.is_retryable()
+    }
+}
+
+impl fmt::Display for WeChatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WeChatError::Api { errcode, errmsg } => write!(
+                f,
+                "WeChat errcode {}: {}",
+                errcode,
+                errmsg.as_deref().unwrap_or("")
+            ),
+            WeChatError::Transport(e) => write!(f, "WeChat transport error: {}", e),
+            WeChatError::CircuitOpen => write!(f, "WeChat circuit breaker is open"),
+        }
+    }
+}
+
+/// A freshly issued `client_credential` token.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccessToken {
+    pub access_token: String,
+    /// Lifetime in seconds, counted from when WeChat answered.
+    pub expires_in: i64,
+}
+
+#[rocket::async_trait]
+pub trait WeChatClient: Send + Sync {
+    /// Exchanges a login code issued to `app` for the user's openid and session key.
+    async fn code2session(&self, app: &WeChatApp, code: &str) -> Result<Code2Session, WeChatError>;
+
+    /// Asks WeChat for a new server access_token of `app`. Use [`AccessTokenProvider`]
+    /// instead of calling this directly: every call invalidates the previous token.
+    async fn fetch_access_token(&self, app: &WeChatApp) -> Result<AccessToken, WeChatError>;
+
+    /// Exchanges a `getPhoneNumber` button code for the user's verified number.
+    async fn get_phone_number(
+        &self,
+        access_token: &str,
+        code: &str,
+    ) -> Result<PhoneNumber, WeChatError>;
+
+    /// Screens user text with `msgSecCheck`; `openid` must have used the app recently.
+    async fn msg_sec_check(
+        &self,
+        access_token: &str,
+        openid: &str,
+        content: &str,
+    ) -> Result<SecCheckResult, WeChatError>;
+
+    /// Submits an image at `media_url` to `mediaCheckAsync` and returns its `trace_id`.
+    /// WeChat downloads the image later and pushes the verdict to the message push URL.
+    async fn media_check_async(
+        &self,
+        access_token: &str,
+        openid: &str,
+        media_url: &str,
+    ) -> Result<String, WeChatError>;
+
+    /// Sends a subscribe message; each one uses up an authorization the user granted.
+    async fn send_subscribe_message(
+        &self,
+        access_token: &str,
+        message: &SubscribeMessage,
+    ) -> Result<(), WeChatError>;
+}
+
+/// Body of `/cgi-bin/message/subscribe/send`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SubscribeMessage {
+    pub touser: String,
+    pub template_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page: Option<String>,
+    /// Keyword to `{"value": ...}`, as the template expects.
+    pub data: serde_json::Value,
+}
+
+/// Raw body of `/sns/jscode2session` and `/sns/oauth2/access_token`.
+#[derive(Deserialize)]
+pub struct WeChatLoginAPIResponse {
+    pub session_key: Option<String>,
+    pub unionid: Option<String>,
+    pub errmsg: Option<String>,
+    pub openid: Option<String>,
+    pub errcode: Option<i32>,
+}
+
+impl WeChatLoginAPIResponse {
+    pub fn into_result(self) -> Result<Code2Session, WeChatError> {
+        if let Some(errcode) = self.errcode.filter(|&errcode| errcode != 0) {
+            return Err(WeChatError::Api {
+                errcode,
+                errmsg: self.errmsg,
+            });
+        }
+
+        match self.openid {
+            Some(openid) => Ok(Code2Session {
+                openid,
+                session_key: self.session_key,
+                unionid: self.unionid,
+            }),
+            None => Err(WeChatError::Transport(
+                "response is missing openid".to_owned(),
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PhoneNumber {
+    /// With the international prefix for numbers outside mainland China.
+    pub phone_number: String,
+    pub pure_phone_number: String,
+}
+
+/// Raw body of `/wxa/business/getuserphonenumber`.
+#[derive(Deserialize)]
+pub struct PhoneNumberAPIResponse {
+    pub errcode: Option<i32>,
+    pub errmsg: Option<String>,
+    pub phone_info: Option<PhoneInfo>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PhoneInfo {
+    pub phone_number: String,
+    pub pure_phone_number: String,
+}
+
+impl PhoneNumberAPIResponse {
+    pub fn into_result(self) -> Result<PhoneNumber, WeChatError> {
+        if let Some(errcode) = self.errcode.filter(|&errcode| errcode != 0) {
+            return Err(WeChatError::Api {
+                errcode,
+                errmsg: self.errmsg,
+            });
+        }
+
+        match self.phone_info {
+            Some(info) => Ok(PhoneNumber {
+                phone_number: info.phone_number,
+                pure_phone_number: info.pure_phone_number,
+            }),
+            None => Err(WeChatError::Transport(
+                "response is missing phone_info".to_owned(),
+            )),
+        }
+    }
+}
+
+/// Raw body of calls that answer nothing but an `errcode`.
+#[derive(Deserialize)]
+pub struct StatusAPIResponse {
+    pub errcode: Option<i32>,
+    pub errmsg: Option<String>,
+}
+
+impl StatusAPIResponse {
+    pub fn into_result(self) -> Result<(), WeChatError> {
+        match self.errcode.filter(|&errcode| errcode != 0) {
+            Some(errcode) => Err(WeChatError::Api {
+                errcode,
+                errmsg: self.errmsg,
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SecSuggest {
+    Pass,
+    /// Uncertain; left to a human reviewer.
+    Review,
+    Risky,
+}
+
+/// The overall verdict of a content security check.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct SecCheckResult {
+    pub suggest: SecSuggest,
+    /// Category of the strongest hit, e.g. `20001` for politics; `100` when clean.
+    pub label: i32,
+}
+
+/// Raw body of `/wxa/msg_sec_check`.
+#[derive(Deserialize)]
+pub struct MsgSecCheckAPIResponse {
+    pub errcode: Option<i32>,
+    pub errmsg: Option<String>,
+    pub result: Option<SecCheckResult>,
+}
+
+impl MsgSecCheckAPIResponse {
+    pub fn into_result(self) -> Result<SecCheckResult, WeChatError> {
+        if let Some(errcode) = self.errcode.filter(|&errcode| errcode != 0) {
+            return Err(WeChatError::Api {
+                errcode,
+                errmsg: self.errmsg,
+            });
+        }
+
+        self.result
+            .ok_or_else(|| WeChatError::Transport("response is missing result".to_owned()))
+    }
+}
+
+/// Raw body of `/wxa/media_check_async`.
+#[derive(Deserialize)]
+pub struct MediaCheckAPIResponse {
+    pub errcode: Option<i32>,
+    pub errmsg: Option<String>,
+    pub trace_id: Option<String>,
+}
+
+impl MediaCheckAPIResponse {
+    pub fn into_result(self) -> Result<String, WeChatError> {
+        if let Some(errcode) = self.errcode.filter(|&errcode| errcode != 0) {
+            return Err(WeChatError::Api {
+                errcode,
+                errmsg: self.errmsg,
+            });
+        }
+
+        self.trace_id
+            .ok_or_else(|| WeChatError::Transport("response is missing trace_id".to_owned()))
+    }
+}
+
+/// Raw body of `/cgi-bin/token`.
+#[derive(Deserialize)]
+pub struct AccessTokenAPIResponse {
+    pub access_token: Option<String>,
+    pub expires_in: Option<i64>,
+    pub errcode: Option<i32>,
+    pub errmsg: Option<String>,
+}
+
+impl AccessTokenAPIResponse {
+    pub fn into_result(self) -> Result<AccessToken, WeChatError> {
+        if let Some(errcode) = self.errcode.filter(|&errcode| errcode != 0) {
+            return Err(WeChatError::Api {
+                errcode,
+                errmsg: self.errmsg,
+            });
+        }
+
+        match (self.access_token, self.expires_in) {
+            (Some(access_token), Some(expires_in)) => Ok(AccessToken {
+                access_token,
+                expires_in,
+            }),
+            _ => Err(WeChatError::Transport(
+                "response is missing access_token or expires_in".to_owned(),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::http::Status;
+
+    use super::{Code2Session, WeChatError, WeChatLoginAPIResponse};
+    use crate::error::ApiError;
+
+    fn parse(body: &str) -> Result<Code2Session, WeChatError> {
+        serde_json::from_str::<WeChatLoginAPIResponse>(body)
+            .unwrap()
+            .into_result()
+    }
+
+    #[test]
+    fn maps_success() {
+        let session = parse(r#"{"openid":"o1","session_key":"k1","unionid":"u1"}"#).unwrap();
+        assert_eq!(session.openid, "o1");
+        assert_eq!(session.session_key.as_deref(), Some("k1"));
+        assert_eq!(session.unionid.as_deref(), Some("u1"));
+
+        let session = parse(r#"{"openid":"o1","session_key":"k1","errcode":0}"#).unwrap();
+        assert_eq!(session.unionid, None);
+    }
+
+    #[test]
+    fn maps_errcodes() {
+        for (errcode, status, code) in [
+            (-1, Status::ServiceUnavailable, "wechat_busy"),
+            (40029, Status::BadRequest, "wechat_code_invalid"),
+            (40163, Status::BadRequest, "wechat_code_used"),
+            (40226, Status::Forbidden, "wechat_user_blocked"),
+            (45011, Status::TooManyRequests, "wechat_rate_limited"),
+            (12345, Status::NotImplemented, "wechat_error"),
+        ] {
+            let body = format!(r#"{{"errcode":{},"errmsg":"boom"}}"#, errcode);
+            let error = parse(&body).unwrap_err();
+            assert_eq!(
+                error,
+                WeChatError::Api {
+                    errcode,
+                    errmsg: Some("boom".to_owned())
+                }
+            );
+
+            let error = ApiError::from(error);
+            assert_eq!(error.status, status);
+            assert_eq!(error.code, code);
+        }
+    }
+
+    #[test]
+    fn rejects_incomplete_response() {
+        let error = parse(r#"{"session_key":"k1"}"#).unwrap_err();
+        assert!(matches!(error, WeChatError::Transport(_)));
+        assert_eq!(ApiError::from(error).status, Status::BadGateway);
+    }
+}
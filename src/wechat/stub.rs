@@ -0,0 +1,211 @@
+//! Minimal stand-in for `api.weixin.qq.com`, for tests that exercise the real HTTP client.
+//!
+//! `js_code` selects the answer: a documented errcode such as `40029` returns that error,
+//! [`MALFORMED`] returns a body that is not JSON, `busy-<n>-...` answers `-1` the first `n`
+//! times it is seen, and anything else logs in as `openid-<js_code>`.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use rocket::tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+use uuid::Uuid;
+
+use crate::config::{WeChatApp, WeChatAppKind, WeChatConfig, WeChatHttpConfig};
+
+use super::{http::http_client, HttpWeChatClient};
+
+pub const SESSION_KEY: &str = "tiihtNczf5v6AKRyjwEUhQ==";
+pub const ACCESS_TOKEN: &str = "stub-access-token";
+pub const PHONE_NUMBER: &str = "13800138000";
+pub const MALFORMED: &str = "malformed";
+
+/// Every errcode jscode2session documents, with WeChat's own `errmsg`.
+pub const ERRCODES: &[(i32, &str)] = &[
+    (-1, "system error"),
+    (40029, "invalid code"),
+    (40163, "code been used"),
+    (40226, "high risk user"),
+    (45011, "api minute-quota reach limit"),
+];
+
+pub const RETRIES: u32 = 2;
+pub const BREAKER_THRESHOLD: u32 = 3;
+
+type Seen = Arc<Mutex<HashMap<String, u32>>>;
+
+/// A fresh code that answers `-1` for its first `times` uses.
+pub fn busy_code(times: u32) -> String {
+    format!("busy-{}-{}", times, Uuid::new_v4())
+}
+
+/// The primary app of every [`client`].
+pub fn app() -> WeChatApp {
+    WeChatApp {
+        appid: "appid".to_owned(),
+        secret: "secret".to_owned(),
+        kind: WeChatAppKind::MiniProgram,
+        tenant: None,
+        review_template: None,
+    }
+}
+
+/// A client with tight timeouts and no real backoff, pointed at `wechat_api`.
+pub fn client(wechat_api: &str) -> HttpWeChatClient {
+    let http = WeChatHttpConfig {
+        wechat_connect_timeout_ms: 500,
+        wechat_timeout_ms: 2_000,
+        wechat_retries: RETRIES,
+        wechat_backoff_ms: 1,
+        wechat_breaker_threshold: BREAKER_THRESHOLD,
+        wechat_breaker_cooldown: 60,
+    };
+    let config = WeChatConfig {
+        wechat_appid: "appid".to_owned(),
+        wechat_secret: "secret".to_owned(),
+        wechat_api: wechat_api.to_owned(),
+        wechat_token_margin: 300,
+        wechat_watermark_max_age: 600,
+        wechat_tenant: "default".to_owned(),
+        wechat_apps: Vec::new(),
+        wechat_review_template: None,
+        wechat_push_token: None,
+        wechat_media_base_url: None,
+    };
+
+    HttpWeChatClient::new(http_client(&http).unwrap(), config, http)
+}
+
+/// Starts the stub on an ephemeral port and returns its base URL.
+pub async fn spawn() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let seen = Seen::default();
+
+    rocket::tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            rocket::tokio::spawn(serve(stream, seen.clone()));
+        }
+    });
+
+    format!("http://{}", addr)
+}
+
+async fn serve(mut stream: TcpStream, seen: Seen) {
+    let mut request = Vec::new();
+    let mut buf = [0; 1024];
+    let header_end = loop {
+        if let Some(end) = request.windows(4).position(|w| w == b"\r\n\r\n") {
+            break end + 4;
+        }
+        match stream.read(&mut buf).await {
+            Ok(0) | Err(_) => return,
+            Ok(n) => request.extend_from_slice(&buf[..n]),
+        }
+    };
+
+    let head = String::from_utf8_lossy(&request[..header_end]).into_owned();
+    let content_length = head
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.trim().parse::<usize>().ok())
+        .unwrap_or(0);
+    // Drain the body so the client is not reset while still writing it.
+    while request.len() < header_end + content_length {
+        match stream.read(&mut buf).await {
+            Ok(0) | Err(_) => return,
+            Ok(n) => request.extend_from_slice(&buf[..n]),
+        }
+    }
+
+    let request = head;
+    let target = request.split_whitespace().nth(1).unwrap_or("/");
+    let body = respond(target, &seen);
+
+    let response = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        body.len(),
+        body
+    );
+    let _ = stream.write_all(response.as_bytes()).await;
+}
+
+fn respond(target: &str, seen: &Seen) -> String {
+    let url = reqwest::Url::parse(&format!("http://stub{}", target)).unwrap();
+    let code = url
+        .query_pairs()
+        .find(|(key, _)| key == "js_code" || key == "code")
+        .map(|(_, value)| value.into_owned())
+        .unwrap_or_default();
+
+    match url.path() {
+        "/sns/jscode2session" => {}
+        "/sns/oauth2/access_token" => {
+            return serde_json::json!({
+                "access_token": "oauth-access-token",
+                "expires_in": 7200,
+                "openid": format!("oa-openid-{}", code),
+                "unionid": format!("unionid-{}", code),
+                "scope": "snsapi_base",
+            })
+            .to_string()
+        }
+        "/wxa/business/getuserphonenumber" => {
+            let access_token = url
+                .query_pairs()
+                .find(|(key, _)| key == "access_token")
+                .map(|(_, value)| value.into_owned());
+            return if access_token.as_deref() == Some(ACCESS_TOKEN) {
+                serde_json::json!({
+                    "errcode": 0,
+                    "errmsg": "ok",
+                    "phone_info": {
+                        "phoneNumber": PHONE_NUMBER,
+                        "purePhoneNumber": PHONE_NUMBER,
+                        "countryCode": "86",
+                        "watermark": { "timestamp": 1637744274, "appid": "appid" },
+                    },
+                })
+                .to_string()
+            } else {
+                r#"{"errcode":40001,"errmsg":"invalid credential"}"#.to_owned()
+            };
+        }
+        "/cgi-bin/token" => {
+            return serde_json::json!({ "access_token": ACCESS_TOKEN, "expires_in": 7200 })
+                .to_string()
+        }
+        _ => return r#"{"errcode":40001,"errmsg":"invalid path"}"#.to_owned(),
+    }
+    if code == MALFORMED {
+        return "<html>bad gateway</html>".to_owned();
+    }
+    if let Some(times) = code
+        .strip_prefix("busy-")
+        .and_then(|rest| rest.split('-').next())
+        .and_then(|times| times.parse::<u32>().ok())
+    {
+        let mut seen = seen.lock().unwrap();
+        let count = seen.entry(code.clone()).or_default();
+        *count += 1;
+        if *count <= times {
+            return r#"{"errcode":-1,"errmsg":"system error"}"#.to_owned();
+        }
+    }
+
+    match code
+        .parse::<i32>()
+        .ok()
+        .and_then(|errcode| ERRCODES.iter().find(|(x, _)| *x == errcode))
+    {
+        Some((errcode, errmsg)) => serde_json::json!({ "errcode": errcode, "errmsg": errmsg }),
+        None => serde_json::json!({
+            "openid": format!("openid-{}", code),
+            "session_key": SESSION_KEY,
+        }),
+    }
+    .to_string()
+}
@@ -0,0 +1,322 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, RwLock};
+
+use rocket::tokio::sync::Mutex;
+use sea_orm::{
+    sea_query::{Expr, OnConflict},
+    ColumnTrait, ConnectionTrait, DatabaseBackend, DatabaseConnection, EntityTrait, QueryFilter,
+    Set, Statement, TransactionTrait,
+};
+
+use crate::config::WeChatApp;
+use crate::error::ApiError;
+use crate::orm::entities::{prelude::WeChatAccessToken, we_chat_access_token};
+
+use super::{WeChatClient, WeChatError};
+
+/// Hands out each app's server `client_credential` access_token to modules calling
+/// WeChat APIs.
+#[rocket::async_trait]
+pub trait AccessTokenProvider: Send + Sync {
+    /// A token of `app` that stays valid for at least the configured refresh margin.
+    async fn access_token(
+        &self,
+        db: &DatabaseConnection,
+        app: &WeChatApp,
+    ) -> Result<String, ApiError>;
+
+    /// Drops `token` after WeChat rejected it (errcode 40001, 40014 or 42001), so the
+    /// next [`access_token`](Self::access_token) fetches a new one.
+    async fn invalidate(
+        &self,
+        db: &DatabaseConnection,
+        app: &WeChatApp,
+        token: &str,
+    ) -> Result<(), ApiError>;
+}
+
+/// Runs `call` with the current access_token of `app`, fetching a new one once if WeChat
+/// rejects it.
+pub async fn with_access_token<T, F, Fut>(
+    provider: &dyn AccessTokenProvider,
+    db: &DatabaseConnection,
+    app: &WeChatApp,
+    mut call: F,
+) -> Result<T, ApiError>
+where
+    F: FnMut(String) -> Fut,
+    Fut: Future<Output = Result<T, WeChatError>>,
+{
+    let token = provider.access_token(db, app).await?;
+    match call(token.clone()).await {
+        Err(e) if e.is_stale_token() => {
+            provider.invalidate(db, app, &token).await?;
+            let token = provider.access_token(db, app).await?;
+            Ok(call(token).await?)
+        }
+        result => Ok(result?),
+    }
+}
+
+/// Keeps the tokens in `we_chat_access_token`, one row per appid, so every instance
+/// shares them.
+///
+/// Refreshes are single-flight twice over: a mutex inside the process, and a Postgres
+/// advisory lock across processes, each re-checking the stored token once acquired.
+pub struct DbAccessTokenManager {
+    client: Arc<dyn WeChatClient>,
+    margin: chrono::Duration,
+    cached: RwLock<HashMap<String, we_chat_access_token::Model>>,
+    refresh: Mutex<()>,
+}
+
+fn is_fresh(
+    token: &we_chat_access_token::Model,
+    now: chrono::NaiveDateTime,
+    margin: chrono::Duration,
+) -> bool {
+    token.expires_at - margin > now
+}
+
+impl DbAccessTokenManager {
+    pub fn new(client: Arc<dyn WeChatClient>, margin: chrono::Duration) -> Self {
+        DbAccessTokenManager {
+            client,
+            margin,
+            cached: RwLock::new(HashMap::new()),
+            refresh: Mutex::new(()),
+        }
+    }
+
+    fn cached(&self, appid: &str) -> Option<String> {
+        let now = chrono::Local::now().naive_local();
+        match self.cached.read().unwrap().get(appid) {
+            Some(token) if is_fresh(token, now, self.margin) => Some(token.access_token.clone()),
+            _ => None,
+        }
+    }
+
+    async fn stored<C: ConnectionTrait>(
+        &self,
+        db: &C,
+        appid: &str,
+    ) -> Result<Option<we_chat_access_token::Model>, ApiError> {
+        let now = chrono::Local::now().naive_local();
+        let token = WeChatAccessToken::find_by_id(appid.to_owned())
+            .one(db)
+            .await?;
+
+        Ok(token.filter(|token| is_fresh(token, now, self.margin)))
+    }
+
+    async fn load_or_refresh(
+        &self,
+        db: &DatabaseConnection,
+        app: &WeChatApp,
+    ) -> Result<we_chat_access_token::Model, ApiError> {
+        if let Some(token) = self.stored(db, &app.appid).await? {
+            return Ok(token);
+        }
+
+        let txn = db.begin().await?;
+
+        // Held until commit; another instance refreshing the same appid waits here.
+        txn.execute(Statement::from_sql_and_values(
+            DatabaseBackend::Postgres,
+            "SELECT pg_advisory_xact_lock(hashtext($1))",
+            [app.appid.clone().into()],
+        ))
+        .await?;
+
+        if let Some(token) = self.stored(&txn, &app.appid).await? {
+            txn.commit().await?;
+            return Ok(token);
+        }
+
+        let fetched = self.client.fetch_access_token(app).await?;
+        let now = chrono::Local::now().naive_local();
+        let token = we_chat_access_token::Model {
+            appid: app.appid.clone(),
+            access_token: fetched.access_token,
+            expires_at: now + chrono::Duration::seconds(fetched.expires_in),
+            updated_at: now,
+        };
+
+        WeChatAccessToken::insert(we_chat_access_token::ActiveModel {
+            appid: Set(token.appid.clone()),
+            access_token: Set(token.access_token.clone()),
+            expires_at: Set(token.expires_at),
+            updated_at: Set(token.updated_at),
+        })
+        .on_conflict(
+            OnConflict::column(we_chat_access_token::Column::Appid)
+                .update_columns([
+                    we_chat_access_token::Column::AccessToken,
+                    we_chat_access_token::Column::ExpiresAt,
+                    we_chat_access_token::Column::UpdatedAt,
+                ])
+                .to_owned(),
+        )
+        .exec_without_returning(&txn)
+        .await?;
+
+        txn.commit().await?;
+
+        Ok(token)
+    }
+}
+
+#[rocket::async_trait]
+impl AccessTokenProvider for DbAccessTokenManager {
+    async fn access_token(
+        &self,
+        db: &DatabaseConnection,
+        app: &WeChatApp,
+    ) -> Result<String, ApiError> {
+        if let Some(token) = self.cached(&app.appid) {
+            return Ok(token);
+        }
+
+        let _refresh = self.refresh.lock().await;
+        // Whoever held the lock before us may have refreshed already.
+        if let Some(token) = self.cached(&app.appid) {
+            return Ok(token);
+        }
+
+        let token = self.load_or_refresh(db, app).await?;
+        let access_token = token.access_token.clone();
+        self.cached
+            .write()
+            .unwrap()
+            .insert(app.appid.clone(), token);
+
+        Ok(access_token)
+    }
+
+    async fn invalidate(
+        &self,
+        db: &DatabaseConnection,
+        app: &WeChatApp,
+        token: &str,
+    ) -> Result<(), ApiError> {
+        {
+            let mut cached = self.cached.write().unwrap();
+            if cached.get(&app.appid).map(|x| x.access_token.as_str()) == Some(token) {
+                cached.remove(&app.appid);
+            }
+        }
+
+        let now = chrono::Local::now().naive_local();
+        WeChatAccessToken::update_many()
+            .col_expr(we_chat_access_token::Column::ExpiresAt, Expr::value(now))
+            .filter(we_chat_access_token::Column::Appid.eq(app.appid.clone()))
+            .filter(we_chat_access_token::Column::AccessToken.eq(token))
+            .exec(db)
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use sea_orm::{DatabaseBackend, MockDatabase, MockExecResult};
+
+    use super::{with_access_token, AccessTokenProvider, DbAccessTokenManager};
+    use crate::orm::entities::we_chat_access_token;
+    use crate::wechat::{fake::FakeWeChatClient, stub, WeChatClient};
+
+    fn stored(access_token: &str, expires_in: i64) -> we_chat_access_token::Model {
+        let now = chrono::Local::now().naive_local();
+        we_chat_access_token::Model {
+            appid: "appid".to_owned(),
+            access_token: access_token.to_owned(),
+            expires_at: now + chrono::Duration::seconds(expires_in),
+            updated_at: now,
+        }
+    }
+
+    fn manager(wechat: &Arc<FakeWeChatClient>) -> DbAccessTokenManager {
+        DbAccessTokenManager::new(wechat.clone(), chrono::Duration::seconds(300))
+    }
+
+    fn executed() -> MockExecResult {
+        MockExecResult {
+            last_insert_id: 0,
+            rows_affected: 1,
+        }
+    }
+
+    #[rocket::async_test]
+    async fn reuses_token_stored_by_another_instance() {
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([vec![stored("shared", 3600)]])
+            .into_connection();
+        let wechat = Arc::new(FakeWeChatClient::default());
+        let manager = manager(&wechat);
+
+        assert_eq!(
+            manager.access_token(&db, &stub::app()).await.unwrap(),
+            "shared"
+        );
+        // Served from memory; the mock has no second result to give.
+        assert_eq!(
+            manager.access_token(&db, &stub::app()).await.unwrap(),
+            "shared"
+        );
+        assert_eq!(wechat.access_token_fetches(), 0);
+    }
+
+    #[rocket::async_test]
+    async fn refreshes_token_close_to_expiry_once() {
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([vec![stored("old", 60)]])
+            .append_exec_results([executed()])
+            .append_query_results([vec![stored("old", 60)]])
+            .append_exec_results([executed()])
+            .into_connection();
+        let wechat = Arc::new(FakeWeChatClient::default().with_access_token("new", 7200));
+        let manager = manager(&wechat);
+        let app = stub::app();
+
+        let tokens =
+            rocket::futures::future::join_all((0..8).map(|_| manager.access_token(&db, &app)))
+                .await;
+
+        for token in tokens {
+            assert_eq!(token.unwrap(), "new");
+        }
+        assert_eq!(wechat.access_token_fetches(), 1);
+    }
+
+    #[rocket::async_test]
+    async fn replaces_token_rejected_by_wechat() {
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([vec![stored("revoked", 3600)]])
+            .append_exec_results([executed()])
+            .append_query_results([Vec::<we_chat_access_token::Model>::new()])
+            .append_exec_results([executed()])
+            .append_query_results([Vec::<we_chat_access_token::Model>::new()])
+            .append_exec_results([executed()])
+            .into_connection();
+        let wechat = Arc::new(
+            FakeWeChatClient::default()
+                .with_access_token("new", 7200)
+                .with_phone_number("phone-1", "13800138000"),
+        );
+        let manager = manager(&wechat);
+
+        let phone = with_access_token(&manager, &db, &stub::app(), |token| {
+            let wechat = wechat.clone();
+            async move { wechat.get_phone_number(&token, "phone-1").await }
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(phone.phone_number, "13800138000");
+        assert_eq!(wechat.access_token_fetches(), 1);
+    }
+}